@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pngme_core::crypto::SecretString;
+use pngme_core::img_format::{ParseLimits, DEFAULT_SPLIT_LENGTH};
+use pngme_core::payload::MAX_DECOMPRESSED_LENGTH;
 
 /// A magic tool to embed your message into a PNG image.
 #[derive(Debug, Parser)]
@@ -8,6 +11,14 @@ use clap::{Args, Parser, Subcommand};
 pub struct App {
     #[clap(subcommand)]
     pub command: Command,
+
+    /// Refuses PNG files larger than this many bytes
+    #[clap(long, global = true, default_value_t = ParseLimits::UNTRUSTED.max_total_size)]
+    pub max_png_size: u64,
+
+    /// Refuses PNG files made of more chunks than this
+    #[clap(long, global = true, default_value_t = ParseLimits::UNTRUSTED.max_chunks)]
+    pub max_png_chunks: usize,
 }
 
 #[derive(Debug, Subcommand)]
@@ -18,10 +29,66 @@ pub enum Command {
     Decode(DecodeArgs),
     /// Search for potential hidden message
     Search(SearchArgs),
+    /// Checks the image signature and the signatures of the messages hidden in chunks,
+    /// without decrypting them
+    Verify(VerifyArgs),
     /// Removes a chunk from a PNG file and saves the result
     Remove(RemoveArgs),
     /// Prints all of the chunks in a PNG file
     Print(PrintArgs),
+    /// Lists, adds or deletes the text metadata (tEXt, zTXt, iTXt) of a PNG file
+    Text(TextArgs),
+    /// Manages the keys of the passphrase-protected keystore, used with --key-id
+    ///
+    /// The keystore passphrase is read from $PNGME_KEYSTORE_PASSPHRASE or typed in.
+    /// The keystore file is $PNGME_KEYSTORE, or pngme/keystore in the config directory
+    Key(KeyArgs),
+}
+
+/// Where the encrypted message is hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EmbedMode {
+    /// In a dedicated chunk
+    Chunk,
+    /// In the least significant bits of the pixels, picked with the key or passphrase and its KDF.
+    /// Not available with --recipient
+    Lsb,
+}
+
+/// How the encryption key is derived from a passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KdfArg {
+    /// Memory-hard Argon2id
+    Argon2id,
+    /// PBKDF2-HMAC-SHA256, faster on constrained devices but easier to brute-force
+    Pbkdf2,
+}
+
+/// The authenticated cipher encrypting the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CipherArg {
+    /// AES-256-GCM
+    Aes256gcm,
+    /// XChaCha20-Poly1305, with longer random nonces
+    Xchacha20poly1305,
+}
+
+/// The compression of the message before encryption
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompressArg {
+    /// zlib-wrapped deflate
+    Deflate,
+    /// Zstandard, faster and smaller, unless built without the `zstd` feature
+    Zstd,
+}
+
+/// What an encrypted message is bound to, so that it cannot be moved elsewhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BindArg {
+    /// The type of the chunk holding the message
+    ChunkType,
+    /// The critical chunks of the image holding the message
+    Image,
 }
 
 #[derive(Debug, Args)]
@@ -30,18 +97,41 @@ pub struct EncodeArgs {
     #[arg(short, long)]
     pub in_file_path: Option<PathBuf>,
 
-    /// Chunk type: 4 bytes
-    #[arg(short, long, required = true)]
-    pub chunk_type: String,
+    /// Chunk type: 4 bytes, required by the chunk mode
+    #[arg(short, long)]
+    pub chunk_type: Option<String>,
+
+    /// Embedding mode
+    #[arg(long, value_enum, default_value_t = EmbedMode::Chunk)]
+    pub mode: EmbedMode,
 
     /// Secret message
-    #[arg(short, long, required = true)]
-    pub mess: String,
+    #[arg(
+        short,
+        long,
+        required_unless_present_any = ["file", "dir"],
+        conflicts_with_all = ["file", "dir"]
+    )]
+    pub mess: Option<SecretString>,
+
+    /// Path to a file to hide instead of a message, with its name, type, size and hash
+    #[arg(short, long, conflicts_with = "dir")]
+    pub file: Option<PathBuf>,
+
+    /// Path to a directory to hide instead of a message, with the paths, modes and mtimes
+    /// of its content. Symbolic links are not supported
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
 
     /// Path to the output PNG file
     #[arg(short, long, required = true)]
     pub out_file_path: PathBuf,
 
+    /// Largest data length in bytes of the chunks holding the message. A larger message is
+    /// split across several chunks of the same type
+    #[arg(long, default_value_t = DEFAULT_SPLIT_LENGTH)]
+    pub max_chunk_size: usize,
+
     /// Verbosity
     #[arg(short, long)]
     pub verbosity: bool,
@@ -56,11 +146,78 @@ pub struct EncodeArgs {
 
     /// The base64-encoded private key for encryption
     #[arg(short, long)]
-    pub key: Option<String>,
+    pub key: Option<SecretString>,
 
     /// Passphrase used for encryption/decryption
     #[arg(short, long)]
-    pub passphrase: Option<String>,
+    pub passphrase: Option<SecretString>,
+
+    /// Name of a key of the keystore, used instead of a key or passphrase
+    #[arg(long, conflicts_with_all = ["key", "passphrase"])]
+    pub key_id: Option<String>,
+
+    /// Encrypts to a public key instead of a key or passphrase, in the age format:
+    /// an age X25519 key (age1...) or an SSH key (ssh-ed25519 ...). Can be repeated.
+    /// Chunk mode only, as recipients share no secret to pick the pixels of LSB mode from
+    #[arg(short, long = "recipient", conflicts_with_all = ["key", "passphrase", "key_id"])]
+    pub recipients: Vec<String>,
+
+    /// Path to a file with the base64-encoded Ed25519 private key signing the message
+    #[arg(long)]
+    pub sign_key: Option<PathBuf>,
+
+    /// Also signs the critical chunks of the resulting image, the chunk of the message
+    /// and the ancillary chunks given by --sign-chunks, so that changes can be detected
+    #[arg(long, requires = "sign_key")]
+    pub sign_image: bool,
+
+    /// Ancillary chunk types covered by the image signature, comma-separated
+    #[arg(long, requires = "sign_image", value_delimiter = ',')]
+    pub sign_chunks: Vec<String>,
+
+    /// Binds the message to its chunk type and/or image, comma-separated. A message
+    /// moved into another chunk or image then fails to decrypt. Chunk mode only
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "recipients")]
+    pub bind: Vec<BindArg>,
+
+    /// Encrypts with AES-256-GCM-SIV and a nonce derived from the message, so that the same
+    /// message, key and input image always give the same output image. It reveals when
+    /// the same message is hidden twice
+    #[arg(long, conflicts_with_all = ["cipher", "recipients"])]
+    pub deterministic: bool,
+
+    /// Compresses the message, file or directory before encrypting it. Ciphertext does not
+    /// compress, so this is the only way to shrink it
+    #[arg(long, value_enum)]
+    pub compress: Option<CompressArg>,
+
+    /// Cipher of the message, picked automatically when decoding
+    #[arg(long, value_enum, default_value_t = CipherArg::Aes256gcm)]
+    pub cipher: CipherArg,
+
+    #[command(flatten)]
+    pub kdf: KdfArgs,
+}
+
+/// How the key picking the pixels of LSB mode, and the encryption key, are derived from
+/// a passphrase. Decoding a message hidden in the pixels needs the same settings
+#[derive(Args, Debug)]
+pub struct KdfArgs {
+    /// Key derivation function of the passphrase
+    #[arg(long, value_enum, default_value_t = KdfArg::Argon2id)]
+    pub kdf: KdfArg,
+
+    /// Memory cost of Argon2id in KiB [default: 19456]
+    #[arg(long)]
+    pub kdf_memory: Option<u32>,
+
+    /// Iterations of the KDF [default: 2 for Argon2id, 600000 for PBKDF2]
+    #[arg(long)]
+    pub kdf_iterations: Option<u32>,
+
+    /// Parallelism of Argon2id [default: 1]
+    #[arg(long)]
+    pub kdf_parallelism: Option<u8>,
 }
 
 #[derive(Args, Debug)]
@@ -69,25 +226,78 @@ pub struct DecodeArgs {
     #[arg(short, long, required = true)]
     pub in_file_path: PathBuf,
 
-    /// Chunk type: exactly 4 bytes
-    #[arg(short, long, required = true)]
-    pub chunk_type: String,
+    /// Chunk type: exactly 4 bytes. Without it, the first tagged chunk is decoded,
+    /// then the pixels are searched for a message hidden in LSB mode
+    #[arg(short, long)]
+    pub chunk_type: Option<String>,
+
+    /// Which chunk of that type holds the message, from 0 [default: 0]. Each piece of
+    /// a message split across chunks counts as one chunk
+    #[arg(long, requires = "chunk_type")]
+    pub index: Option<usize>,
 
     /// The base64-encoded private key for decryption
     #[arg(short, long)]
-    pub key: Option<String>,
+    pub key: Option<SecretString>,
 
     /// Verbosity
     #[arg(short, long)]
     pub verbosity: bool,
 
-    /// The Nonce used for decryption
-    #[arg(short, long, required = true)]
-    pub nonce: String,
+    /// The Nonce printed by older versions, only needed to decrypt their messages
+    #[arg(short, long)]
+    pub nonce: Option<String>,
+
+    /// Path to write the hidden file or message to, instead of printing it. A hidden file
+    /// keeps its original name in a directory
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// Directory to extract a hidden directory into. Existing files are never overwritten
+    #[arg(long)]
+    pub extract_to: Option<PathBuf>,
+
+    /// Largest size in bytes a compressed message may decompress to, against zip bombs
+    #[arg(long, default_value_t = MAX_DECOMPRESSED_LENGTH)]
+    pub max_decompressed_size: usize,
 
     /// Passphrase used for encryption/decryption
     #[arg(short, long)]
-    pub passphrase: Option<String>,
+    pub passphrase: Option<SecretString>,
+
+    /// Name of a key of the keystore, used instead of a key or passphrase
+    #[arg(long, conflicts_with_all = ["key", "passphrase"])]
+    pub key_id: Option<String>,
+
+    /// Path to a file with the private key of a recipient: age X25519 keys or an SSH key
+    #[arg(long, conflicts_with_all = ["key", "passphrase", "key_id", "nonce"])]
+    pub identity: Option<PathBuf>,
+
+    /// Path to a file with the trusted signers: a base64-encoded Ed25519 public key
+    /// and an optional name per line
+    #[arg(long)]
+    pub trusted_keys: Option<PathBuf>,
+
+    // Only used to find a message hidden in the pixels with a passphrase, whose KDF
+    // settings are not stored. Messages in a chunk record their own
+    #[command(flatten)]
+    pub kdf: KdfArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Path to the PNG file
+    #[arg(short, long, required = true)]
+    pub in_file_path: PathBuf,
+
+    /// Chunk type: exactly 4 bytes. Without it, every tagged chunk is checked
+    #[arg(short, long)]
+    pub chunk_type: Option<String>,
+
+    /// Path to a file with the trusted signers: a base64-encoded Ed25519 public key
+    /// and an optional name per line
+    #[arg(long)]
+    pub trusted_keys: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -110,6 +320,11 @@ pub struct RemoveArgs {
     /// Chunk type: 4 bytes
     #[arg(short, long, required = true)]
     pub chunk_type: String,
+
+    /// Which chunk of that type to remove, from 0. The other chunks of a message split
+    /// across several chunks are removed along with it
+    #[arg(long, default_value_t = 0)]
+    pub index: usize,
 }
 
 #[derive(Args, Debug)]
@@ -118,3 +333,108 @@ pub struct PrintArgs {
     #[arg(short, long, required = true)]
     pub in_file_path: PathBuf,
 }
+
+#[derive(Args, Debug)]
+pub struct TextArgs {
+    #[clap(subcommand)]
+    pub command: TextCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TextCommand {
+    /// Lists all text chunks of a PNG file
+    List(TextListArgs),
+    /// Adds a text chunk to a PNG file and saves the result
+    Add(TextAddArgs),
+    /// Deletes all text chunks with the given keyword and saves the result
+    Delete(TextDeleteArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TextListArgs {
+    /// Path to the input PNG file
+    #[arg(short, long, required = true)]
+    pub in_file_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct TextAddArgs {
+    /// Path to the input PNG file
+    #[arg(short, long, required = true)]
+    pub in_file_path: PathBuf,
+
+    /// Keyword: 1-79 Latin-1 characters, e.g. "Author", "Description"
+    #[arg(short, long, required = true)]
+    pub keyword: String,
+
+    /// Text content
+    #[arg(short, long, required = true)]
+    pub text: String,
+
+    /// Compress the text (zTXt, or compressed iTXt with --international)
+    #[arg(long)]
+    pub compressed: bool,
+
+    /// Store a UTF-8 text (iTXt) instead of a Latin-1 one (tEXt/zTXt)
+    #[arg(long)]
+    pub international: bool,
+
+    /// Language tag of an international text, e.g. "en", "vi-VN"
+    #[arg(long, requires = "international", default_value = "")]
+    pub language: String,
+
+    /// Keyword translated into the language of an international text
+    #[arg(long, requires = "international", default_value = "")]
+    pub translated_keyword: String,
+}
+
+#[derive(Args, Debug)]
+pub struct TextDeleteArgs {
+    /// Path to the input PNG file
+    #[arg(short, long, required = true)]
+    pub in_file_path: PathBuf,
+
+    /// Keyword of the text chunks to delete
+    #[arg(short, long, required = true)]
+    pub keyword: String,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyArgs {
+    #[clap(subcommand)]
+    pub command: KeyCommand,
+
+    /// Creates the keystore if there is none yet, with a passphrase typed twice
+    #[arg(long, global = true)]
+    pub init: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeyCommand {
+    /// Generates a random key and adds it to the keystore
+    Generate(KeyNameArgs),
+    /// Lists the names of the keys of the keystore
+    List,
+    /// Prints a key of the keystore, base64-encoded
+    Export(KeyNameArgs),
+    /// Adds a base64-encoded key to the keystore, read from a file or typed in
+    Import(KeyImportArgs),
+    /// Deletes a key from the keystore
+    Delete(KeyNameArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct KeyNameArgs {
+    /// Name of the key
+    pub name: String,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyImportArgs {
+    /// Name of the key
+    pub name: String,
+
+    /// Path to a file with the base64-encoded key
+    #[arg(short, long)]
+    pub file: Option<PathBuf>,
+}
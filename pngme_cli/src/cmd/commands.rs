@@ -1,56 +1,190 @@
 use crate::error::Error;
-use pngme_core::crypto::{aes256gcm_decrypt, aes256gcm_encrypt, sha3_hash};
+use pngme_core::crypto::{
+    aes256gcm_decrypt, age_decrypt, age_encrypt, critical_chunks_hash, generate_key, is_age_file,
+    sign_image, verify_image, Binding, Cipher, Envelope, Kdf, KeySource, SealOptions, SecretBytes,
+    SecretString, SignatureStatus, SignedPayload, TrustedKeys, SALT_LENGTH,
+};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use curl::easy::Easy;
 
-use crate::cmd::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs, SearchArgs};
+use crate::cmd::args::{
+    BindArg, CipherArg, CompressArg, DecodeArgs, EmbedMode, EncodeArgs, KdfArg, KdfArgs, KeyArgs,
+    KeyCommand, PrintArgs, RemoveArgs, SearchArgs, TextArgs, TextCommand, VerifyArgs,
+};
+use crate::keystore::Keystore;
 use crate::Result;
 use pngme_core::img_format::Chunk;
 use pngme_core::img_format::ChunkType;
 use pngme_core::img_format::Png;
+use pngme_core::img_format::{ChunkRef, Ihdr, ParseLimits, Plte, PngRef};
+use pngme_core::img_format::{
+    CompressedTextChunk, InternationalTextChunk, TextChunk, TextualChunk,
+};
 
-use std::fs::File;
-use std::io::Write;
+use std::fs::{self, File};
+use std::io::{Read, Write};
 
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use pngme_core::img_format::TAG;
+use pngme_core::img_format::{SplitPiece, TAG};
+use pngme_core::payload::{
+    compress, decompress, message_payload, message_text, Archive, Compression, FilePayload,
+    PayloadKind,
+};
+use pngme_core::stego::{lsb_embed, lsb_extract};
+
+/// Supplies the keystore passphrase without typing it, e.g. in scripts
+const KEYSTORE_PASSPHRASE_ENV: &str = "PNGME_KEYSTORE_PASSPHRASE";
+
+/// Helper function for the `Encode` and `Decode` commands.
+/// Returns the base64-decoded key, the key of the keystore with the given name,
+/// or the given or typed passphrase.
+fn key_helper(
+    key_arg: &Option<SecretString>,
+    passphrase_arg: &Option<SecretString>,
+    key_id_arg: &Option<String>,
+) -> Result<KeySource> {
+    if passphrase_arg.is_some() && key_arg.is_some() {
+        return Err(Error::OverlapKeyPassphrase);
+    }
+
+    if let Some(key_id) = key_id_arg {
+        let keystore = Keystore::open(&Keystore::default_path()?, &keystore_passphrase()?)?;
+        Ok(KeySource::Key(keystore.get(key_id)?.clone()))
+    } else if let Some(base64_key) = key_arg {
+        // Base64 decoding the given key
+        let key = STANDARD
+            .decode(base64_key.as_bytes())
+            .map_err(|_| Error::InvalidKey)?;
+        Ok(KeySource::Key(SecretBytes::new(key)))
+    } else if let Some(passphrase) = passphrase_arg {
+        Ok(KeySource::Passphrase(passphrase.clone()))
+    } else {
+        // Promt to user for typing their passphrase invisibly
+        get_passphrase().map(KeySource::Passphrase)
+    }
+}
+
+/// Helper function for the `Encode` and `Decode` commands.
+/// Returns the passphrase KDF with its default cost parameters overridden by the given ones.
+fn kdf_helper(args: &KdfArgs) -> Result<Kdf> {
+    let mut kdf = match args.kdf {
+        KdfArg::Argon2id => Kdf::ARGON2ID_DEFAULT,
+        KdfArg::Pbkdf2 => Kdf::PBKDF2_DEFAULT,
+    };
+    match &mut kdf {
+        Kdf::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => {
+            *memory_kib = args.kdf_memory.unwrap_or(*memory_kib);
+            *iterations = args.kdf_iterations.unwrap_or(*iterations);
+            *parallelism = args.kdf_parallelism.unwrap_or(*parallelism);
+        }
+        Kdf::Pbkdf2 { iterations } => {
+            if args.kdf_memory.is_some() || args.kdf_parallelism.is_some() {
+                return Err(Error::InvalidKdfParams(
+                    "PBKDF2 only takes a number of iterations".to_string(),
+                ));
+            }
+            *iterations = args.kdf_iterations.unwrap_or(*iterations);
+        }
+        Kdf::RawKey | Kdf::Sha3 => {}
+    }
+    kdf.check_params()
+        .map_err(|_| Error::InvalidKdfParams("out of the supported range".to_string()))?;
+    Ok(kdf)
+}
 
 /// Helper function for the `Encode` command.
-/// Returns base64-encoded ciphertext and Nonce.
+/// Returns the encrypted message along with everything needed to decrypt it but the secret.
 fn encrypt_helper(
-    key_arg: &Option<String>,
-    passphrase_arg: &Option<String>,
+    key_source: &KeySource,
+    options: &SealOptions,
     mess_bytes: &[u8],
-) -> Result<(Vec<u8>, String)> {
-    if passphrase_arg.is_some() && key_arg.is_some() {
-        return Err(Error::OverlapKeyPassphrase);
-    }
+) -> Result<Vec<u8>> {
+    let envelope = Envelope::seal(mess_bytes, key_source, options).map_err(Error::CoreLibErr)?;
+    Ok(envelope.as_bytes())
+}
 
-    let enc_key = if let Some(base64_enc_key) = key_arg {
-        // Use the given key to encrypt message (chunk content)
+/// Helper function for the `Encode` command.
+/// Returns the context the message is bound to, taken from the image before the message is added.
+fn binding_helper(
+    bind_args: &[BindArg],
+    chunk_type: Option<ChunkType>,
+    png: &Png,
+) -> Result<Binding> {
+    Binding::select(
+        chunk_type,
+        bind_args.contains(&BindArg::ChunkType),
+        bind_args.contains(&BindArg::Image),
+        png,
+    )
+    .map_err(Error::CoreLibErr)
+}
 
-        // Base64 decoding the encryption key
-        STANDARD
-            .decode(base64_enc_key)
-            .map_err(|_| Error::InvalidKey)?
-    } else {
-        // Use the key derived from the given passphrase for encryption
-        if let Some(passphrase) = passphrase_arg {
-            // Hash the given passphrase
-            sha3_hash(passphrase).map_err(Error::CoreLibErr)?
-        } else {
-            // Promt to user for typing their passphrase invisibly
-            get_passphrase_key()?
+/// Helper function for the `Encode` command.
+/// Returns the message, the file along with its metadata, or the archived directory.
+fn plaintext_helper(
+    mess_arg: &Option<SecretString>,
+    file_arg: &Option<PathBuf>,
+    dir_arg: &Option<PathBuf>,
+) -> Result<SecretBytes> {
+    match (mess_arg, file_arg, dir_arg) {
+        (_, _, Some(dir)) => {
+            let archive = Archive::from_dir(dir).map_err(Error::CoreLibErr)?;
+            Ok(archive.as_bytes())
         }
-    };
+        (_, Some(path), None) => {
+            let data = fs::read(path).map_err(Error::FileReadErr)?;
+            let file = FilePayload::new(&path.to_string_lossy(), SecretBytes::new(data))
+                .map_err(Error::CoreLibErr)?;
+            Ok(file.as_bytes())
+        }
+        (Some(mess), None, None) => Ok(message_payload(mess.as_bytes())),
+        (None, None, None) => Err(Error::MissingArg(
+            "a message, a file or a directory".to_string(),
+        )),
+    }
+}
+
+/// Helper function for the `Encode` and `Key` commands.
+/// Returns the base64-decoded content of a key file.
+fn key_file_helper(path: &Path) -> Result<SecretBytes> {
+    let content = SecretString::new(fs::read_to_string(path).map_err(Error::FileReadErr)?);
+    STANDARD
+        .decode(content.trim())
+        .map(SecretBytes::new)
+        .map_err(|_| Error::InvalidKey)
+}
 
-    let (ciphertext, nonce_raw) =
-        aes256gcm_encrypt(mess_bytes, &enc_key).map_err(Error::CoreLibErr)?;
-    // base64-encode Nonce
-    let nonce = STANDARD.encode(nonce_raw);
-    Ok((ciphertext, nonce))
+/// Helper function for the `Decode` and `Verify` commands.
+/// Parses the trusted keys file: a base64-encoded public key and an optional name per line.
+fn trusted_keys_helper(path_arg: &Option<PathBuf>) -> Result<TrustedKeys> {
+    let mut trusted_keys = TrustedKeys::new();
+    let Some(path) = path_arg else {
+        return Ok(trusted_keys);
+    };
+    let content = fs::read_to_string(path).map_err(Error::FileReadErr)?;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (public_key, name) = match line.split_once(char::is_whitespace) {
+            Some((public_key, name)) => (public_key, Some(name.trim())),
+            None => (line, None),
+        };
+        let public_key = STANDARD
+            .decode(public_key)
+            .map_err(|_| Error::InvalidTrustedKey(index + 1))?;
+        trusted_keys
+            .add(&public_key, name)
+            .map_err(|_| Error::InvalidTrustedKey(index + 1))?;
+    }
+    Ok(trusted_keys)
 }
 
 /// Helper function for the `Encode` command.
@@ -71,7 +205,10 @@ fn input_png_helper(
                 .url(url)
                 .map_err(|_| Error::CurlErr(url.clone()))?;
 
-            let file_name = url.split('/').last().ok_or(Error::CurlErr(url.clone()))?;
+            let file_name = url
+                .split('/')
+                .next_back()
+                .ok_or(Error::CurlErr(url.clone()))?;
             let mut download_png = File::create(file_name).map_err(Error::FileWriteErr)?;
             easy_curl
                 .write_function(move |data| {
@@ -96,21 +233,111 @@ fn input_png_helper(
 }
 
 /// Encodes a message into a PNG file and saves the result
-pub fn encode(args: EncodeArgs) -> Result<()> {
+pub fn encode(args: EncodeArgs, limits: &ParseLimits) -> Result<()> {
     let file_path = input_png_helper(&args.in_file_path, &args.url, args.verbosity)?;
-    let mut png = Png::try_from(Path::new(&file_path)).map_err(Error::CoreLibErr)?;
+    let mut png = Png::from_file(&file_path, *limits).map_err(Error::CoreLibErr)?;
     if args.verbosity {
         println!("Reading {}... done", file_path.to_string_lossy());
     }
 
-    let chunk_type = ChunkType::from_str(&args.chunk_type).map_err(Error::CoreLibErr)?;
-    let (chunk_content, nonce) = encrypt_helper(&args.key, &args.passphrase, args.mess.as_bytes())?;
-    let chunk = Chunk::new(chunk_type, &chunk_content);
+    let chunk_type = match args.mode {
+        EmbedMode::Chunk => {
+            let chunk_type = args.chunk_type.as_ref().ok_or(Error::MissingArg(
+                "a chunk type for the chunk mode".to_string(),
+            ))?;
+            Some(ChunkType::from_str(chunk_type).map_err(Error::CoreLibErr)?)
+        }
+        EmbedMode::Lsb if !args.recipients.is_empty() => return Err(Error::RecipientsInPixels),
+        EmbedMode::Lsb => None,
+    };
+
+    let mut plaintext = plaintext_helper(&args.mess, &args.file, &args.dir)?;
+    if let Some(compress_arg) = args.compress {
+        let compression = match compress_arg {
+            CompressArg::Deflate => Compression::Deflate,
+            CompressArg::Zstd => Compression::Zstd,
+        };
+        let length = plaintext.len();
+        plaintext = compress(&plaintext, compression).map_err(Error::CoreLibErr)?;
+        if args.verbosity {
+            println!(
+                "Compressing your secret message from {length} to {} bytes... done",
+                plaintext.len()
+            );
+        }
+    }
+    let kdf = kdf_helper(&args.kdf)?;
+    // Salted with the carrier, so that the passphrase gives the same key for it
+    let salt = args
+        .deterministic
+        .then(|| critical_chunks_hash(&png)[..SALT_LENGTH].to_vec());
+    let (ciphertext, key_source) = if args.recipients.is_empty() {
+        let key_source = key_helper(&args.key, &args.passphrase, &args.key_id)?;
+        let options = SealOptions {
+            cipher: match args.cipher {
+                _ if args.deterministic => Cipher::Aes256GcmSiv,
+                CipherArg::Aes256gcm => Cipher::Aes256Gcm,
+                CipherArg::Xchacha20poly1305 => Cipher::XChaCha20Poly1305,
+            },
+            kdf,
+            binding: binding_helper(&args.bind, chunk_type, &png)?,
+            salt: salt.clone(),
+        };
+        let ciphertext = encrypt_helper(&key_source, &options, &plaintext)?;
+        (ciphertext, Some(key_source))
+    } else {
+        let ciphertext = age_encrypt(&plaintext, &args.recipients).map_err(Error::CoreLibErr)?;
+        (ciphertext, None)
+    };
     if args.verbosity {
         println!("Encrypting your message... done");
     }
 
-    png.append_chunk(chunk, true).map_err(Error::CoreLibErr)?;
+    let private_key = args.sign_key.as_deref().map(key_file_helper).transpose()?;
+    let ciphertext = match &private_key {
+        Some(private_key) => {
+            let signed =
+                SignedPayload::sign(&ciphertext, private_key).map_err(Error::CoreLibErr)?;
+            if args.verbosity {
+                let public_key = STANDARD.encode(signed.public_key());
+                println!("Signing with the public key {public_key}... done");
+            }
+            signed.as_bytes()
+        }
+        None => ciphertext,
+    };
+
+    let mut signed_chunk_types = args
+        .sign_chunks
+        .iter()
+        .map(|chunk_type| ChunkType::from_str(chunk_type))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(Error::CoreLibErr)?;
+    match chunk_type {
+        Some(chunk_type) => {
+            signed_chunk_types.push(chunk_type);
+            let count = png
+                .append_split_chunks(chunk_type, &ciphertext, args.max_chunk_size)
+                .map_err(Error::CoreLibErr)?;
+            if args.verbosity && count > 1 {
+                println!("Splitting your secret message across {count} chunks... done");
+            }
+        }
+        None => {
+            // Recipients have been refused above
+            let key_source = key_source.ok_or(Error::RecipientsInPixels)?;
+            lsb_embed(&mut png, &key_source, kdf, salt.as_deref(), &ciphertext)
+                .map_err(Error::CoreLibErr)?;
+        }
+    }
+    if let (true, Some(private_key)) = (args.sign_image, &private_key) {
+        sign_image(&mut png, private_key, &signed_chunk_types).map_err(Error::CoreLibErr)?;
+        if args.verbosity {
+            println!("Signing the image... done");
+        }
+    }
+    // Refuse to write a file that PNG decoders would reject
+    png.validate().map_err(Error::CoreLibErr)?;
 
     png.to_file(Path::new(&args.out_file_path))
         .map_err(Error::CoreLibErr)?;
@@ -119,81 +346,243 @@ pub fn encode(args: EncodeArgs) -> Result<()> {
             "Embeding your secret message to {}... done",
             args.out_file_path.to_string_lossy()
         );
-        println!("Please SAVE a copy of this base64-encoded Nonce so that you can use it to decrypt your message later: {nonce}");
-    } else {
-        println!("Nonce:{nonce}");
     }
 
     Ok(())
 }
 
+/// Helper function for the `Decode` command.
+/// Decrypts an envelope, or the bare ciphertext of older versions when their Nonce is given.
 fn decrypt_helper(
     ciphertext: &[u8],
-    passphrase_arg: &Option<String>,
-    key_arg: &Option<String>,
-    nonce: &str,
-) -> Result<Vec<u8>> {
-    if passphrase_arg.is_some() && key_arg.is_some() {
-        return Err(Error::OverlapKeyPassphrase);
+    key_source: &KeySource,
+    binding: &Binding,
+    nonce_arg: &Option<String>,
+) -> Result<SecretBytes> {
+    if let Some(nonce) = nonce_arg {
+        let nonce_raw = STANDARD
+            .decode(nonce)
+            .map_err(|_| Error::InvalidNonce(nonce.to_string()))?;
+        let key = key_source.legacy_key().map_err(Error::CoreLibErr)?;
+        return aes256gcm_decrypt(ciphertext, &key, &nonce_raw).map_err(Error::CoreLibErr);
     }
 
-    let dec_key = if let Some(base64_dec_key) = key_arg {
-        STANDARD
-            .decode(base64_dec_key)
-            .map_err(|_| Error::InvalidKey)?
-    } else if let Some(passphrase) = passphrase_arg {
-        sha3_hash(passphrase).map_err(Error::CoreLibErr)?
+    let envelope = Envelope::try_from(ciphertext).map_err(Error::CoreLibErr)?;
+    envelope
+        .open_bound(key_source, binding)
+        .map_err(Error::CoreLibErr)
+}
+
+/// Helper function for the `Decode` command.
+/// Returns the ciphertext of the `index`-th chunk of the given type, or of the first tagged
+/// chunk, along with the type of the chunk. A ciphertext split across chunks is reassembled.
+fn tagged_chunk_helper(
+    png: &Png,
+    chunk_type_arg: &Option<String>,
+    index_arg: Option<usize>,
+) -> Result<Option<(ChunkType, Vec<u8>)>> {
+    let chunk = if let Some(chunk_type) = chunk_type_arg {
+        let chunks = png.chunks_by_type(chunk_type).map_err(Error::CoreLibErr)?;
+        match index_arg {
+            // An index is only meant for a chunk, no message in the pixels is looked for
+            Some(_) if chunks.is_empty() => {
+                return Err(Error::ChunkTypeNotFound(chunk_type.clone()))
+            }
+            Some(index) => Some(*chunks.get(index).ok_or(Error::CoreLibErr(
+                pngme_core::Error::InvalidChunkIndex(index),
+            ))?),
+            None => chunks.first().copied(),
+        }
     } else {
-        get_passphrase_key()?
+        png.chunks()
+            .iter()
+            .find(|chunk| chunk.data().starts_with(&TAG))
     };
-
-    let nonce = STANDARD
-        .decode(nonce)
-        .map_err(|_| Error::InvalidNonce(nonce.to_string()))?;
-    aes256gcm_decrypt(ciphertext, &dec_key, &nonce).map_err(Error::CoreLibErr)
+    chunk
+        .map(|chunk| {
+            if let Some(ciphertext) = png.split_payload(chunk).map_err(Error::CoreLibErr)? {
+                return Ok((*chunk.chunk_type(), ciphertext));
+            }
+            chunk
+                .data()
+                .strip_prefix(&TAG)
+                .map(|ciphertext| (*chunk.chunk_type(), ciphertext.to_vec()))
+                .ok_or(Error::TagMissing)
+        })
+        .transpose()
 }
 
-/// Searches for a message hidden in a PNG file and prints the message if one is found
-pub fn decode(args: DecodeArgs) -> Result<()> {
-    let png = Png::try_from(Path::new(&args.in_file_path)).map_err(Error::CoreLibErr)?;
+/// Searches for a message hidden in a PNG file and prints the message if one is found.
+/// Messages hidden in a chunk are looked for first, then the ones hidden in the pixels.
+pub fn decode(args: DecodeArgs, limits: &ParseLimits) -> Result<()> {
+    let png = Png::from_file(&args.in_file_path, *limits).map_err(Error::CoreLibErr)?;
     if args.verbosity {
         println!("Reading {}... done", args.in_file_path.to_string_lossy());
     }
 
-    if let Some(mess_chunk) = png
-        .chunk_by_type(&args.chunk_type)
-        .map_err(Error::CoreLibErr)?
-    {
-        let ciphertext = mess_chunk
-            .data()
-            .strip_prefix(&TAG)
-            .ok_or(Error::TagMissing)?;
-        let mess_bytes = decrypt_helper(ciphertext, &args.passphrase, &args.key, &args.nonce)?;
+    let identity = args
+        .identity
+        .as_ref()
+        .map(|path| fs::read(path).map(SecretBytes::new))
+        .transpose()
+        .map_err(Error::FileReadErr)?;
+    let key_source = match identity {
+        Some(_) => None,
+        None => Some(key_helper(&args.key, &args.passphrase, &args.key_id)?),
+    };
+    let (binding, ciphertext) = match tagged_chunk_helper(&png, &args.chunk_type, args.index)? {
+        Some((chunk_type, ciphertext)) => {
+            let binding = Binding::new().with_chunk_type(chunk_type).with_image(&png);
+            (binding, ciphertext)
+        }
+        None => {
+            // Messages encrypted to recipients are never hidden in the pixels
+            let key_source = key_source.as_ref().ok_or(Error::NotFoundSecMess)?;
+            let kdf = kdf_helper(&args.kdf)?;
+            let ciphertext = lsb_extract(&png, key_source, kdf)
+                .map_err(Error::CoreLibErr)?
+                .ok_or(Error::NotFoundSecMess)?;
+            (Binding::new(), ciphertext)
+        }
+    };
+    let ciphertext = if SignedPayload::is_signed(&ciphertext) {
+        let signed = SignedPayload::try_from(ciphertext.as_slice()).map_err(Error::CoreLibErr)?;
+        let status = signed.verify(&trusted_keys_helper(&args.trusted_keys)?);
         if args.verbosity {
-            println!("Decrypting your secret message... done");
+            println!("Signature of your secret message: {status}");
+        } else {
+            println!("Signature:{status}");
+        }
+        if status == SignatureStatus::Invalid {
+            return Err(Error::InvalidSignature);
+        }
+        signed.payload().to_vec()
+    } else {
+        if args.trusted_keys.is_some() {
+            println!("Signature:none");
+        }
+        ciphertext
+    };
+    let mut mess_bytes = match (&identity, &key_source) {
+        (Some(identity), _) if is_age_file(&ciphertext) => {
+            age_decrypt(&ciphertext, identity).map_err(Error::CoreLibErr)?
         }
+        (_, Some(key_source)) if !is_age_file(&ciphertext) => {
+            decrypt_helper(&ciphertext, key_source, &binding, &args.nonce)?
+        }
+        (Some(_), _) => return Err(Error::MissingArg("a key or passphrase".to_string())),
+        _ => {
+            return Err(Error::MissingArg(
+                "the identity file of a recipient (--identity)".to_string(),
+            ))
+        }
+    };
+    if args.verbosity {
+        println!("Decrypting your secret message... done");
+    }
+    if PayloadKind::of(&mess_bytes) == PayloadKind::Compressed {
+        mess_bytes =
+            decompress(&mess_bytes, args.max_decompressed_size).map_err(Error::CoreLibErr)?;
+        if args.verbosity {
+            println!("Decompressing your secret message... done");
+        }
+    }
 
-        let mess = String::from_utf8_lossy(&mess_bytes);
+    let kind = PayloadKind::of(&mess_bytes);
+    if kind == PayloadKind::Archive {
+        let archive = Archive::try_from(&*mess_bytes).map_err(Error::CoreLibErr)?;
+        let dir = args.extract_to.as_ref().ok_or(Error::MissingArg(
+            "where to extract the hidden directory (--extract-to)".to_string(),
+        ))?;
+        archive.extract_to(dir).map_err(Error::CoreLibErr)?;
+        if args.verbosity {
+            println!(
+                "Your secret directory, extracted to {}:",
+                dir.to_string_lossy()
+            );
+        }
+        for entry in archive.entries() {
+            match (args.verbosity, entry.data()) {
+                (true, Some(data)) => println!("  {} ({} bytes)", entry.path(), data.len()),
+                (true, None) => println!("  {}/", entry.path()),
+                (false, _) => println!("Entry:{}", entry.path()),
+            }
+        }
+    } else if args.extract_to.is_some() {
+        return Err(Error::NoHiddenDirectory);
+    } else if kind == PayloadKind::File {
+        let file = FilePayload::try_from(&*mess_bytes).map_err(Error::CoreLibErr)?;
+        let sha256: String = file.sha256().iter().map(|b| format!("{b:02x}")).collect();
+        if args.verbosity {
+            println!(
+                "Your secret file: {} ({}, {} bytes, SHA-256 {sha256})",
+                file.name(),
+                file.mime_type(),
+                file.size()
+            );
+        } else {
+            println!("File:{}", file.name());
+            println!("Type:{}", file.mime_type());
+            println!("Size:{}", file.size());
+            println!("SHA-256:{sha256}");
+        }
+        let out = args.out.as_ref().ok_or(Error::MissingArg(
+            "where to write the hidden file (--out)".to_string(),
+        ))?;
+        let out = if out.is_dir() {
+            out.join(file.name())
+        } else {
+            out.clone()
+        };
+        fs::write(&out, &**file.data()).map_err(Error::FileWriteErr)?;
+        if args.verbosity {
+            println!(
+                "Writing your secret file to {}... done",
+                out.to_string_lossy()
+            );
+        }
+    } else if let Some(out) = &args.out {
+        fs::write(out, message_text(&mess_bytes)).map_err(Error::FileWriteErr)?;
+        if args.verbosity {
+            println!(
+                "Writing your secret message to {}... done",
+                out.to_string_lossy()
+            );
+        }
+    } else {
+        let mess = String::from_utf8_lossy(message_text(&mess_bytes));
         if args.verbosity {
             println!("Your secret message: {mess}");
         } else {
             println!("Message:{mess}");
         }
-        Ok(())
-    } else {
-        Err(Error::NotFoundSecMess)
     }
+    Ok(())
+}
+
+/// Helper function for the `Search` and `Print` commands.
+/// Reads a PNG file into memory, stopping as soon as it is larger than the limit.
+fn png_bytes_helper(path: &Path, limits: &ParseLimits) -> Result<Vec<u8>> {
+    let mut png_bytes = Vec::new();
+    File::open(path)
+        .map_err(Error::FileReadErr)?
+        // The chunk crossing the limit is then reported by the parser
+        .take(limits.max_total_size.saturating_add(1))
+        .read_to_end(&mut png_bytes)
+        .map_err(Error::FileReadErr)?;
+    Ok(png_bytes)
 }
 
-fn search_helper(png: &Png) -> Vec<&Chunk> {
+fn search_helper<'a, 'b>(png: &'b PngRef<'a>) -> Vec<&'b ChunkRef<'a>> {
     png.chunks()
         .iter()
         .filter(|&chunk| chunk.data().starts_with(&TAG))
         .collect()
 }
 
-pub fn search(args: SearchArgs) -> Result<()> {
-    let png = Png::try_from(Path::new(&args.in_file_path)).map_err(Error::CoreLibErr)?;
+pub fn search(args: SearchArgs, limits: &ParseLimits) -> Result<()> {
+    let png_bytes = png_bytes_helper(&args.in_file_path, limits)?;
+    let png = PngRef::with_limits(&png_bytes, *limits).map_err(Error::CoreLibErr)?;
     let candidates = search_helper(&png);
     for (ind, chunk) in candidates.iter().enumerate() {
         let mess_str = String::from_utf8_lossy(chunk.data());
@@ -209,50 +598,288 @@ pub fn search(args: SearchArgs) -> Result<()> {
     Ok(())
 }
 
-/// Catches the passphrase typed by a user, then
-/// SHA3-hasing it to derive a symmetric key for encryption/decryption
-fn get_passphrase_key() -> Result<Vec<u8>> {
-    let passphrase = rpassword::prompt_password("Enter your passphrase: ")
+/// Prints the status of the image signature along with the signed chunks that have
+/// changed, then the signature status of the messages hidden in chunks.
+/// Fails if any of the signatures is invalid or if the image has changed.
+pub fn verify(args: VerifyArgs, limits: &ParseLimits) -> Result<()> {
+    let png = Png::from_file(&args.in_file_path, *limits).map_err(Error::CoreLibErr)?;
+    let trusted_keys = trusted_keys_helper(&args.trusted_keys)?;
+
+    let image = verify_image(&png, &trusted_keys).map_err(Error::CoreLibErr)?;
+    if let Some(image) = &image {
+        println!("Image:{}", image.signature());
+        for change in image.changes() {
+            println!("Changed:{change}");
+        }
+    }
+    let chunks: Vec<&Chunk> = match &args.chunk_type {
+        Some(chunk_type) => png.chunks_by_type(chunk_type).map_err(Error::CoreLibErr)?,
+        None => png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.data().starts_with(&TAG))
+            .collect(),
+    };
+    if chunks.is_empty() && image.is_none() {
+        return Err(Error::NothingToVerify);
+    }
+
+    let mut all_valid = true;
+    // A malformed or incomplete message is reported without hiding the others
+    let mut all_readable = true;
+    let mut split_ids = Vec::new();
+    for chunk in chunks {
+        let mut payload = chunk.data().strip_prefix(&TAG).ok_or(Error::TagMissing)?;
+        // The pieces of a split message are verified once, as a whole
+        let reassembled;
+        if SplitPiece::is_piece(payload) {
+            let Ok(piece) = SplitPiece::try_from(payload) else {
+                all_readable = false;
+                println!("{}:malformed", chunk.chunk_type());
+                continue;
+            };
+            if split_ids.contains(&piece.id()) {
+                continue;
+            }
+            split_ids.push(piece.id());
+            let Ok(whole) = png.split_payload(chunk) else {
+                all_readable = false;
+                println!("{}:incomplete", chunk.chunk_type());
+                continue;
+            };
+            reassembled = whole;
+            payload = reassembled.as_deref().unwrap_or(payload);
+        }
+        let status = if SignedPayload::is_signed(payload) {
+            match SignedPayload::try_from(payload) {
+                Ok(signed) => {
+                    let status = signed.verify(&trusted_keys);
+                    all_valid &= status != SignatureStatus::Invalid;
+                    status.to_string()
+                }
+                Err(_) => {
+                    all_readable = false;
+                    "malformed".to_string()
+                }
+            }
+        } else {
+            "none".to_string()
+        };
+        println!("{}:{status}", chunk.chunk_type());
+    }
+    match image {
+        _ if !all_valid => Err(Error::InvalidSignature),
+        Some(image) if image.signature() == &SignatureStatus::Invalid => {
+            Err(Error::InvalidSignature)
+        }
+        Some(image) if !image.is_intact() => Err(Error::ImageModified),
+        _ if !all_readable => Err(Error::UnreadableMessages),
+        _ => Ok(()),
+    }
+}
+
+/// Catches the passphrase typed by a user
+fn get_passphrase() -> Result<SecretString> {
+    rpassword::prompt_password("Enter your passphrase: ")
+        .map(SecretString::new)
+        .map_err(|_| Error::PassphraseReadErr)
+}
+
+/// Returns the passphrase of the keystore, from the environment or typed by a user
+fn keystore_passphrase() -> Result<SecretString> {
+    match std::env::var(KEYSTORE_PASSPHRASE_ENV) {
+        Ok(passphrase) => Ok(SecretString::new(passphrase)),
+        Err(_) => rpassword::prompt_password("Enter your keystore passphrase: ")
+            .map(SecretString::new)
+            .map_err(|_| Error::PassphraseReadErr),
+    }
+}
+
+/// Returns the passphrase of a new keystore, typed twice unless it comes from the environment
+fn new_keystore_passphrase() -> Result<SecretString> {
+    if let Ok(passphrase) = std::env::var(KEYSTORE_PASSPHRASE_ENV) {
+        return Ok(SecretString::new(passphrase));
+    }
+    let passphrase = rpassword::prompt_password("Enter a new keystore passphrase: ")
+        .map(SecretString::new)
+        .map_err(|_| Error::PassphraseReadErr)?;
+    let confirmation = rpassword::prompt_password("Enter it again: ")
+        .map(SecretString::new)
         .map_err(|_| Error::PassphraseReadErr)?;
-    sha3_hash(&passphrase).map_err(Error::CoreLibErr)
+    if passphrase != confirmation {
+        return Err(Error::PassphraseMismatch);
+    }
+    Ok(passphrase)
+}
+
+/// Generates, lists, exports, imports or deletes the keys of the keystore
+pub fn key(args: KeyArgs) -> Result<()> {
+    let path = Keystore::default_path()?;
+    let (passphrase, mut keystore) = if args.init && !path.exists() {
+        let passphrase = new_keystore_passphrase()?;
+        let keystore = Keystore::new(&path);
+        keystore.save(&passphrase)?;
+        (passphrase, keystore)
+    } else {
+        let passphrase = keystore_passphrase()?;
+        let keystore = Keystore::open(&path, &passphrase)?;
+        (passphrase, keystore)
+    };
+    match args.command {
+        KeyCommand::Generate(args) => {
+            keystore.insert(&args.name, generate_key())?;
+            keystore.save(&passphrase)
+        }
+        KeyCommand::List => {
+            for name in keystore.names() {
+                println!("{name}");
+            }
+            Ok(())
+        }
+        KeyCommand::Export(args) => {
+            let base64_key = SecretString::new(STANDARD.encode(&**keystore.get(&args.name)?));
+            println!("{}", &*base64_key);
+            Ok(())
+        }
+        KeyCommand::Import(args) => {
+            let key = match &args.file {
+                Some(path) => key_file_helper(path)?,
+                // Typed invisibly, so that the key stays out of the shell history
+                None => {
+                    let base64_key = rpassword::prompt_password("Enter your base64-encoded key: ")
+                        .map(SecretString::new)
+                        .map_err(|_| Error::PassphraseReadErr)?;
+                    STANDARD
+                        .decode(base64_key.trim())
+                        .map(SecretBytes::new)
+                        .map_err(|_| Error::InvalidKey)?
+                }
+            };
+            if key.len() != 32 {
+                return Err(Error::CoreLibErr(pngme_core::Error::InvalidKeyLength));
+            }
+            keystore.insert(&args.name, key)?;
+            keystore.save(&passphrase)
+        }
+        KeyCommand::Delete(args) => {
+            keystore.remove(&args.name)?;
+            keystore.save(&passphrase)
+        }
+    }
 }
 
 /// Removes a chunk from a PNG file and saves the result
-pub fn remove(args: RemoveArgs) -> Result<()> {
-    let mut png = Png::try_from(Path::new(&args.in_file_path)).map_err(Error::CoreLibErr)?;
-    match png.remove_chunk(&args.chunk_type) {
-        Ok(_) => {
+pub fn remove(args: RemoveArgs, limits: &ParseLimits) -> Result<()> {
+    let mut png = Png::from_file(&args.in_file_path, *limits).map_err(Error::CoreLibErr)?;
+    match png.remove_payload_at(&args.chunk_type, args.index) {
+        Ok(count) => {
             png.to_file(Path::new(&args.in_file_path))
                 .map_err(Error::CoreLibErr)?;
+            if count > 1 {
+                println!("Removed the {count} chunks of a split message");
+            }
             Ok(())
         }
         Err(err) => Err(Error::CoreLibErr(err)),
     }
 }
 
+/// Helper function for the `Print` command.
+/// Returns the summary lines of the image header and palette. A malformed one is reported
+/// in place of its summary, so that the chunks can still be listed.
+fn summary_helper(png: &PngRef) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    if let Some(ihdr_chunk) = png.chunk_by_type("IHDR").map_err(Error::CoreLibErr)? {
+        lines.push(match Ihdr::try_from(ihdr_chunk.data()) {
+            Ok(ihdr) => format!("Image: {ihdr}"),
+            Err(err) => format!("Image: invalid IHDR ({err})"),
+        });
+    }
+    if let Some(plte_chunk) = png.chunk_by_type("PLTE").map_err(Error::CoreLibErr)? {
+        lines.push(match Plte::try_from(plte_chunk.data()) {
+            Ok(plte) => format!("Palette: {} entries", plte.entries().len()),
+            Err(err) => format!("Palette: invalid PLTE ({err})"),
+        });
+    }
+    Ok(lines)
+}
+
 /// Prints all of the chunks in a PNG file
-pub fn print_chunks(args: PrintArgs) -> Result<()> {
-    let png = Png::try_from(Path::new(&args.in_file_path)).map_err(Error::CoreLibErr)?;
+pub fn print_chunks(args: PrintArgs, limits: &ParseLimits) -> Result<()> {
+    let png_bytes = png_bytes_helper(&args.in_file_path, limits)?;
+    let png = PngRef::with_limits(&png_bytes, *limits).map_err(Error::CoreLibErr)?;
+    for line in summary_helper(&png)? {
+        println!("{line}");
+    }
     for chunk in png.chunks() {
         println!("{chunk}");
     }
     Ok(())
 }
 
+/// Lists, adds or deletes the text chunks of a PNG file
+pub fn text(args: TextArgs, limits: &ParseLimits) -> Result<()> {
+    match args.command {
+        TextCommand::List(args) => {
+            let png = Png::from_file(&args.in_file_path, *limits).map_err(Error::CoreLibErr)?;
+            // A malformed chunk is reported without hiding the others
+            for (index, text) in png.try_text_chunks().into_iter().enumerate() {
+                match text {
+                    Ok(text) => println!("{text}"),
+                    Err(err) => eprintln!("Text chunk #{}: {err}", index + 1),
+                }
+            }
+            Ok(())
+        }
+        TextCommand::Add(args) => {
+            let mut png = Png::from_file(&args.in_file_path, *limits).map_err(Error::CoreLibErr)?;
+            let text = if args.international {
+                InternationalTextChunk::new(
+                    &args.keyword,
+                    &args.text,
+                    &args.language,
+                    &args.translated_keyword,
+                    args.compressed,
+                )
+                .map(TextualChunk::International)
+            } else if args.compressed {
+                CompressedTextChunk::new(&args.keyword, &args.text).map(TextualChunk::Compressed)
+            } else {
+                TextChunk::new(&args.keyword, &args.text).map(TextualChunk::Text)
+            }
+            .map_err(Error::CoreLibErr)?;
+            png.add_text_chunk(&text).map_err(Error::CoreLibErr)?;
+            png.to_file(&args.in_file_path).map_err(Error::CoreLibErr)
+        }
+        TextCommand::Delete(args) => {
+            let mut png = Png::from_file(&args.in_file_path, *limits).map_err(Error::CoreLibErr)?;
+            let removed = png
+                .remove_text_chunks(&args.keyword)
+                .map_err(Error::CoreLibErr)?;
+            if removed == 0 {
+                return Err(Error::NotFoundText(args.keyword));
+            }
+            png.to_file(&args.in_file_path).map_err(Error::CoreLibErr)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pngme_core::crypto::{aes256gcm_encrypt, ed25519_public_key};
 
     const KEY: &str = "5f6/dVmvW1c/lxQ/22Mqax/RvhzzZ4a5EBFCXYt3K4w=";
     const MESSAGE: &str = "FooBar!";
 
     fn encrypt_decrypt_helper(
         message: &str,
-        key_arg: &Option<String>,
-        passphrase_arg: &Option<String>,
+        key_arg: &Option<SecretString>,
+        passphrase_arg: &Option<SecretString>,
     ) -> Result<()> {
-        let (ciphertext, nonce) = encrypt_helper(key_arg, passphrase_arg, message.as_bytes())?;
-        let plaintext = decrypt_helper(ciphertext.as_slice(), passphrase_arg, key_arg, &nonce)?;
+        let key_source = key_helper(key_arg, passphrase_arg, &None)?;
+        let ciphertext = encrypt_helper(&key_source, &SealOptions::default(), message.as_bytes())?;
+        let plaintext = decrypt_helper(ciphertext.as_slice(), &key_source, &Binding::new(), &None)?;
         let plaintext = String::from_utf8_lossy(&plaintext);
         assert_eq!(plaintext, message);
         Ok(())
@@ -260,8 +887,8 @@ mod tests {
 
     #[test]
     fn test_aes_crypto_with_key() -> Result<()> {
-        let key_arg = Some(KEY.to_string());
-        let passphrase_arg: Option<String> = None;
+        let key_arg = Some(SecretString::from(KEY));
+        let passphrase_arg = None;
         encrypt_decrypt_helper(MESSAGE, &key_arg, &passphrase_arg)?;
         Ok(())
     }
@@ -269,16 +896,124 @@ mod tests {
     #[test]
     fn test_aes_crypto_with_passphrase() -> Result<()> {
         let key_arg = None;
-        let passphrase_arg = Some("HelloWorld!".to_string());
+        let passphrase_arg = Some(SecretString::from("HelloWorld!"));
         encrypt_decrypt_helper(MESSAGE, &key_arg, &passphrase_arg)?;
         Ok(())
     }
 
     #[test]
     fn test_aes_crypto_with_passphrase_and_key() {
-        let key_arg = Some(KEY.to_string());
-        let passphrase_arg = Some("HelloWorld!".to_string());
+        let key_arg = Some(SecretString::from(KEY));
+        let passphrase_arg = Some(SecretString::from("HelloWorld!"));
         let result = encrypt_decrypt_helper(MESSAGE, &key_arg, &passphrase_arg);
         assert!(matches!(result, Err(Error::OverlapKeyPassphrase)));
     }
+
+    #[test]
+    fn test_decrypt_legacy_message() -> Result<()> {
+        // Messages of older versions are a bare ciphertext with a separate Nonce
+        let key_source = key_helper(&None, &Some(SecretString::from("HelloWorld!")), &None)?;
+        let key = key_source.legacy_key().map_err(Error::CoreLibErr)?;
+        let (ciphertext, nonce) =
+            aes256gcm_encrypt(MESSAGE.as_bytes(), &key).map_err(Error::CoreLibErr)?;
+        let nonce = Some(STANDARD.encode(nonce));
+        assert_eq!(
+            &*decrypt_helper(&ciphertext, &key_source, &Binding::new(), &nonce)?,
+            MESSAGE.as_bytes()
+        );
+        assert!(decrypt_helper(&ciphertext, &key_source, &Binding::new(), &None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_binding_helper() -> Result<()> {
+        let png = Png::from_chunks(vec![]);
+        let chunk_type = ChunkType::from_str("ruSt").map_err(Error::CoreLibErr)?;
+        let binding = binding_helper(&[BindArg::ChunkType], Some(chunk_type), &png)?;
+        assert_eq!(binding, Binding::new().with_chunk_type(chunk_type));
+        assert_eq!(binding_helper(&[], None, &png)?, Binding::new());
+        assert!(matches!(
+            binding_helper(&[BindArg::Image], None, &png),
+            Err(Error::CoreLibErr(pngme_core::Error::UnsupportedBinding(_)))
+        ));
+
+        let critical = ChunkType::from_str("RUSt").map_err(Error::CoreLibErr)?;
+        assert!(binding_helper(&[BindArg::Image], Some(critical), &png).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tagged_chunk_helper_index() -> Result<()> {
+        let mut png = Png::from_chunks(vec![]);
+        let chunk_type = ChunkType::from_str("ruSt").map_err(Error::CoreLibErr)?;
+        for ciphertext in ["First", "Second"] {
+            png.append_chunk(Chunk::new(chunk_type, ciphertext.as_bytes()), true)
+                .map_err(Error::CoreLibErr)?;
+        }
+        let chunk_type_arg = Some("ruSt".to_string());
+        let ciphertext = |index| -> Result<Option<Vec<u8>>> {
+            Ok(tagged_chunk_helper(&png, &chunk_type_arg, index)?.map(|(_, data)| data))
+        };
+        assert_eq!(ciphertext(None)?, Some(b"First".to_vec()));
+        assert_eq!(ciphertext(Some(1))?, Some(b"Second".to_vec()));
+        assert!(ciphertext(Some(2)).is_err());
+        assert_eq!(
+            tagged_chunk_helper(&png, &Some("NoNe".to_string()), None)?,
+            None
+        );
+        assert!(matches!(
+            tagged_chunk_helper(&png, &Some("NoNe".to_string()), Some(1)),
+            Err(Error::ChunkTypeNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_helper() -> Result<()> {
+        let chunk = |chunk_type: &str, data: &[u8]| -> Result<Chunk> {
+            let chunk_type = ChunkType::from_str(chunk_type).map_err(Error::CoreLibErr)?;
+            Ok(Chunk::new(chunk_type, data))
+        };
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[1, 2, 3])?,
+            chunk("PLTE", &[0, 0, 0, 255, 255, 255])?,
+            chunk("IEND", &[])?,
+        ]);
+        let bytes = png.as_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).map_err(Error::CoreLibErr)?;
+        let lines = summary_helper(&png)?;
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("Image: invalid IHDR ("));
+        assert_eq!(lines[1], "Palette: 2 entries");
+        assert_eq!(png.chunks().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trusted_keys_file() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(Error::FileWriteErr)?;
+        let path = dir.path().join("trusted_keys");
+        let alice = ed25519_public_key(&[1; 32]).map_err(Error::CoreLibErr)?;
+        let bob = ed25519_public_key(&[2; 32]).map_err(Error::CoreLibErr)?;
+        let content = format!(
+            "# Team\n{} alice\n\n{}\n",
+            STANDARD.encode(alice),
+            STANDARD.encode(bob)
+        );
+        fs::write(&path, content).map_err(Error::FileWriteErr)?;
+        let trusted_keys = trusted_keys_helper(&Some(path.clone()))?;
+
+        let signed = SignedPayload::sign(b"ciphertext", &[1; 32]).map_err(Error::CoreLibErr)?;
+        assert_eq!(
+            signed.verify(&trusted_keys),
+            SignatureStatus::Valid("alice".to_string())
+        );
+
+        fs::write(&path, "not base64 alice\n").map_err(Error::FileWriteErr)?;
+        assert!(matches!(
+            trusted_keys_helper(&Some(path)),
+            Err(Error::InvalidTrustedKey(1))
+        ));
+        Ok(())
+    }
 }
@@ -13,15 +13,24 @@ pub enum Error {
     #[error("Please specify {0}")]
     MissingArg(String),
 
+    #[error("Could not read file: {0}")]
+    FileReadErr(#[source] io::Error),
+
     #[error("Could not write file: {0}")]
     FileWriteErr(#[source] io::Error),
 
+    #[error("Invalid KDF parameters: {0}")]
+    InvalidKdfParams(String),
+
     #[error("Invalid Nonce: {0}")]
     InvalidNonce(String),
 
     #[error("Could not find any tagged message")]
     TagMissing,
 
+    #[error("There is no chunk of type {0}")]
+    ChunkTypeNotFound(String),
+
     #[error("Could not read your passphrase")]
     PassphraseReadErr,
 
@@ -31,6 +40,48 @@ pub enum Error {
     #[error("Could not decode your base64-encoded key")]
     InvalidKey,
 
+    #[error("Could not open the keystore, the passphrase may be wrong")]
+    KeystoreOpenErr,
+
+    #[error("Could not find the keystore {0}, create it with `pngme key --init`")]
+    KeystoreNotFound(String),
+
+    #[error("The passphrases do not match")]
+    PassphraseMismatch,
+
+    #[error("Could not find the key {0} in the keystore")]
+    KeyNotFound(String),
+
+    #[error("The keystore already has a key named {0}")]
+    KeyExists(String),
+
+    #[error("Invalid key name: {0:?}, expected printable characters without spaces")]
+    InvalidKeyName(String),
+
+    #[error("Invalid trusted keys file, line {0}: expected a base64-encoded Ed25519 public key and an optional name")]
+    InvalidTrustedKey(usize),
+
+    #[error("The signature is invalid, the message may have been tampered with")]
+    InvalidSignature,
+
+    #[error("The image has been modified since it was signed")]
+    ImageModified,
+
+    #[error("Messages encrypted to recipients can only be hidden in a chunk: they share no secret to pick the pixels from")]
+    RecipientsInPixels,
+
+    #[error("Could not find any image signature or tagged message")]
+    NothingToVerify,
+
+    #[error("Some hidden messages are malformed or incomplete")]
+    UnreadableMessages,
+
+    #[error("The image hides no directory to extract")]
+    NoHiddenDirectory,
+
     #[error("Could find your secret message")]
     NotFoundSecMess,
+
+    #[error("Could not find any text chunk with the keyword: {0}")]
+    NotFoundText(String),
 }
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use pngme_core::crypto::{Envelope, KeySource, SealOptions, SecretBytes, SecretString};
+
+use crate::error::Error;
+use crate::Result;
+
+/// Overrides the location of the keystore file
+pub const KEYSTORE_ENV: &str = "PNGME_KEYSTORE";
+
+/// The named keys of a user, sealed with a passphrase in a single file.
+///
+/// Once opened, the file holds one key per line: its name, then the base64-encoded key.
+#[derive(Debug)]
+pub struct Keystore {
+    path: PathBuf,
+    keys: Vec<(String, SecretBytes)>,
+}
+
+impl Keystore {
+    /// The keystore file: `$PNGME_KEYSTORE`, or `pngme/keystore` under the config directory
+    pub fn default_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os(KEYSTORE_ENV) {
+            return Ok(PathBuf::from(path));
+        }
+        dirs::config_dir()
+            .map(|dir| dir.join("pngme").join("keystore"))
+            .ok_or(Error::MissingArg(format!(
+                "the keystore file with ${KEYSTORE_ENV}"
+            )))
+    }
+
+    /// Starts an empty keystore, written to the file on `save`.
+    pub fn new(path: &Path) -> Keystore {
+        Keystore {
+            path: path.to_path_buf(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Opens the keystore file, which must exist: any passphrase would open a new one.
+    pub fn open(path: &Path, passphrase: &SecretString) -> Result<Keystore> {
+        if !path.exists() {
+            return Err(Error::KeystoreNotFound(path.display().to_string()));
+        }
+
+        let sealed = fs::read(path).map_err(Error::FileReadErr)?;
+        let content = Envelope::try_from(sealed.as_slice())
+            .and_then(|envelope| envelope.open(&KeySource::Passphrase(passphrase.clone())))
+            .map_err(|_| Error::KeystoreOpenErr)?;
+        let content = std::str::from_utf8(&content).map_err(|_| Error::KeystoreOpenErr)?;
+
+        let keys = content
+            .lines()
+            .map(|line| {
+                let (name, key) = line.split_once(' ').ok_or(Error::KeystoreOpenErr)?;
+                let key = STANDARD
+                    .decode(key)
+                    .map(SecretBytes::new)
+                    .map_err(|_| Error::KeystoreOpenErr)?;
+                Ok((name.to_string(), key))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Keystore {
+            path: path.to_path_buf(),
+            keys,
+        })
+    }
+
+    /// Seals the keystore with the passphrase and writes it, readable by its owner only.
+    ///
+    /// The file is replaced at once, so that it is never left truncated or half-written.
+    pub fn save(&self, passphrase: &SecretString) -> Result<()> {
+        // At its final capacity, see `SecretString`
+        let length = self
+            .keys
+            .iter()
+            .map(|(name, key)| name.len() + base64::encoded_len(key.len(), true).unwrap_or(0) + 2)
+            .sum();
+        let mut content = String::with_capacity(length);
+        for (name, key) in &self.keys {
+            content.push_str(name);
+            content.push(' ');
+            STANDARD.encode_string(&**key, &mut content);
+            content.push('\n');
+        }
+        let content = SecretString::new(content);
+        let sealed = Envelope::seal(
+            content.as_bytes(),
+            &KeySource::Passphrase(passphrase.clone()),
+            &SealOptions::default(),
+        )
+        .map_err(Error::CoreLibErr)?;
+
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir).map_err(Error::FileWriteErr)?;
+
+        // Created readable by its owner only, in the same directory to be renamed over the file
+        let mut file = tempfile::NamedTempFile::new_in(dir).map_err(Error::FileWriteErr)?;
+        file.write_all(&sealed.as_bytes())
+            .and_then(|_| file.as_file().sync_all())
+            .map_err(Error::FileWriteErr)?;
+        file.persist(&self.path)
+            .map_err(|err| Error::FileWriteErr(err.error))?;
+        Ok(())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&SecretBytes> {
+        self.keys
+            .iter()
+            .find(|(key_name, _)| key_name == name)
+            .map(|(_, key)| key)
+            .ok_or(Error::KeyNotFound(name.to_string()))
+    }
+
+    /// Adds a key under a new name, made of printable characters but spaces.
+    pub fn insert(&mut self, name: &str, key: SecretBytes) -> Result<()> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(Error::InvalidKeyName(name.to_string()));
+        }
+        if self.get(name).is_ok() {
+            return Err(Error::KeyExists(name.to_string()));
+        }
+        self.keys.push((name.to_string(), key));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .keys
+            .iter()
+            .position(|(key_name, _)| key_name == name)
+            .ok_or(Error::KeyNotFound(name.to_string()))?;
+        self.keys.remove(index);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passphrase() -> SecretString {
+        SecretString::from("HelloWorld!")
+    }
+
+    #[test]
+    fn test_keystore_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(Error::FileWriteErr)?;
+        let path = dir.path().join("pngme").join("keystore");
+        assert!(matches!(
+            Keystore::open(&path, &passphrase()),
+            Err(Error::KeystoreNotFound(_))
+        ));
+
+        let mut keystore = Keystore::new(&path);
+        keystore.insert("alice", vec![1; 32].into())?;
+        keystore.insert("bob", vec![2; 32].into())?;
+        keystore.remove("alice")?;
+        keystore.save(&passphrase())?;
+
+        let keystore = Keystore::open(&path, &passphrase())?;
+        assert_eq!(keystore.names().collect::<Vec<_>>(), ["bob"]);
+        assert_eq!(&**keystore.get("bob")?, [2; 32]);
+        assert!(matches!(keystore.get("alice"), Err(Error::KeyNotFound(_))));
+        assert!(matches!(
+            Keystore::open(&path, &SecretString::from("wrong")),
+            Err(Error::KeystoreOpenErr)
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).map(|meta| meta.permissions().mode());
+            assert_eq!(mode(&path).map_err(Error::FileReadErr)? & 0o777, 0o600);
+            let parent = path.parent().expect("Joined path");
+            assert_eq!(mode(parent).map_err(Error::FileReadErr)? & 0o777, 0o700);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_keystore_invalid_names() {
+        let mut keystore = Keystore::new(Path::new(""));
+        assert!(keystore.insert("alice", vec![1; 32].into()).is_ok());
+        assert!(matches!(
+            keystore.insert("alice", vec![2; 32].into()),
+            Err(Error::KeyExists(_))
+        ));
+        assert!(matches!(
+            keystore.insert("my key", vec![2; 32].into()),
+            Err(Error::InvalidKeyName(_))
+        ));
+        assert!(keystore.insert("", vec![2; 32].into()).is_err());
+        assert!(keystore.remove("bob").is_err());
+    }
+}
@@ -1,22 +1,32 @@
 mod cmd;
 mod error;
+mod keystore;
 
 use crate::cmd::args::{App, Command};
 use crate::cmd::commands::*;
 use crate::error::Error;
 use clap::Parser;
+use pngme_core::img_format::ParseLimits;
 
 type Result<T> = std::result::Result<T, Error>;
 
 fn main() -> Result<()> {
     let cli = App::parse();
+    let limits = ParseLimits {
+        max_total_size: cli.max_png_size,
+        max_chunks: cli.max_png_chunks,
+        ..ParseLimits::UNTRUSTED
+    };
 
     let result = match cli.command {
-        Command::Decode(args) => decode(args),
-        Command::Encode(args) => encode(args),
-        Command::Search(args) => search(args),
-        Command::Remove(args) => remove(args),
-        Command::Print(args) => print_chunks(args),
+        Command::Decode(args) => decode(args, &limits),
+        Command::Encode(args) => encode(args, &limits),
+        Command::Search(args) => search(args, &limits),
+        Command::Verify(args) => verify(args, &limits),
+        Command::Remove(args) => remove(args, &limits),
+        Command::Print(args) => print_chunks(args, &limits),
+        Command::Text(args) => text(args, &limits),
+        Command::Key(args) => key(args),
     };
 
     if let Err(err) = result {
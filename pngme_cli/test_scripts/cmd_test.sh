@@ -9,6 +9,7 @@ PNGMe=../target/debug/pngme-cli
 KEY="Roh9FEiuw+UiVPunRLM+Qrxcz+0bxeKQ/MS4ri1aUT8="
 OG_FILE=images/dog.png
 ENC_FILE=tmp/dog_secret.png
+LSB_FILE=tmp/dog_lsb.png
 CHUNK_TYPE=abcd
 MESSAGE="Secret!"
 PASSPHRASE="MyPassphrase!"
@@ -30,16 +31,6 @@ assert_eq() {
     fi
 }
 
-capture_nonce() {
-    # -n: Suppresses automatic printing of the pattern space (useful for controlling what gets output).
-    # s/^Nonce:\(.*\)/\1/: This is the substitution command:
-    # - ^Nonce: matches the beginning of the line followed by Nonce:.
-    # - \(.*\) captures everything after Nonce: and stores it in a group.
-    # - \1 refers to the first captured group (which is the <value>).
-    # p: Prints the result only if the substitution was successful.
-    sed -n 's/^Nonce:\(.*\)/\1/p'
-}
-
 capture_secret_mess() {
     sed -n 's/^Message:\(.*\)/\1/p'
 }
@@ -57,23 +48,161 @@ echo "TEST SCRIPT STARTS ==========================================="
 
 echo "TEST encrypt/decrypt with the given key"
 # Encode the encrypted/secret message
-NONCE=$($PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -k $KEY | capture_nonce)
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -k $KEY
 # Decode the decrypted message
-PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -k $KEY -n "$NONCE" | capture_secret_mess)
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -k $KEY | capture_secret_mess)
 assert_eq "$PLAINTEXT" "$MESSAGE"
 echo $LINE_BREAKER
 
 echo "TEST encrypt/decrypt with the given passphrase"
-NONCE=$($PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -p $PASSPHRASE | capture_nonce)
-PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE -n "$NONCE" | capture_secret_mess)
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -p $PASSPHRASE
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE | capture_secret_mess)
 assert_eq "$PLAINTEXT" "$MESSAGE"
 echo $LINE_BREAKER
 
 echo "TEST encrypt/decrypt with the typed passphrase"
 # As the output from TTY session usually include a Carriage Return '\r', we need to trim it to get the expected output
-NONCE=$(./test_scripts/run_expect_tty.exp "$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE" "$PASSPHRASE" | capture_nonce | remove_end_cr)
-PLAINTEXT=$(./test_scripts/run_expect_tty.exp "$PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -n $NONCE" "$PASSPHRASE" | capture_secret_mess | remove_end_cr)
+./test_scripts/run_expect_tty.exp "$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE" "$PASSPHRASE" >/dev/null
+PLAINTEXT=$(./test_scripts/run_expect_tty.exp "$PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE" "$PASSPHRASE" | capture_secret_mess | remove_end_cr)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt with XChaCha20-Poly1305"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -p $PASSPHRASE --cipher xchacha20poly1305
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+echo $LINE_BREAKER
+
+echo "TEST deterministic encryption gives byte-identical images"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -p $PASSPHRASE --deterministic
+$PNGMe encode -i $OG_FILE -o tmp/dog_secret_again.png -c $CHUNK_TYPE -m $MESSAGE -p $PASSPHRASE --deterministic
+assert_eq "$(cmp $ENC_FILE tmp/dog_secret_again.png && echo same)" "same"
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt a binary file"
+head -c 1000 /dev/urandom > tmp/secret.bin
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -f tmp/secret.bin -p $PASSPHRASE
+mkdir -p tmp/out
+FILE_NAME=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE -o tmp/out | sed -n 's/^File:\(.*\)/\1/p')
+assert_eq "$FILE_NAME" "secret.bin"
+assert_eq "$(cmp tmp/secret.bin tmp/out/secret.bin && echo same)" "same"
+echo $LINE_BREAKER
+
+echo "TEST a message looking like a file payload is still a message"
+LOOKALIKE=$'PNGme-file-v1\nsecret.bin'
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m "$LOOKALIKE" -p $PASSPHRASE
+$PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE -o tmp/lookalike.txt
+assert_eq "$(cat tmp/lookalike.txt)" "$LOOKALIKE"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt a directory"
+mkdir -p tmp/secret_dir/keys
+echo $KEY > tmp/secret_dir/keys/bob
+cp tmp/secret.bin tmp/secret_dir/
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE --dir tmp/secret_dir -p $PASSPHRASE
+ENTRIES=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE --extract-to tmp/extracted | tr '\n' ' ')
+assert_eq "$ENTRIES" "Entry:keys Entry:keys/bob Entry:secret.bin "
+assert_eq "$(diff -r tmp/secret_dir tmp/extracted && echo same)" "same"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt a compressed message"
+LONG_MESSAGE=$(printf "$MESSAGE%.0s" $(seq 100))
+for COMPRESSION in deflate zstd; do
+    $PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $LONG_MESSAGE -p $PASSPHRASE --compress $COMPRESSION
+    assert_eq "$(($(wc -c < $ENC_FILE) < $(wc -c < $OG_FILE) + ${#LONG_MESSAGE}))" "1"
+    PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE | capture_secret_mess)
+    assert_eq "$PLAINTEXT" "$LONG_MESSAGE"
+done
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt a message split across chunks"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -f tmp/secret.bin -p $PASSPHRASE --max-chunk-size 200
+assert_eq "$($PNGMe search -i $ENC_FILE | sed -n 's/^Total://p')" "8"
+$PNGMe decode -i $ENC_FILE -p $PASSPHRASE -o tmp/split.bin > /dev/null
+assert_eq "$(cmp tmp/secret.bin tmp/split.bin && echo same)" "same"
+# Cut the second piece out, from the start of its chunk to the start of the next one
+OFFSETS=($(grep -obUa "${CHUNK_TYPE}PNGme" $ENC_FILE | cut -d: -f1))
+{ head -c $((OFFSETS[1] - 4)) $ENC_FILE; tail -c +$((OFFSETS[2] - 3)) $ENC_FILE; } > tmp/incomplete.png
+VERIFIED=$($PNGMe verify -i tmp/incomplete.png 2>&1)
+assert_eq "$(echo "$VERIFIED" | grep -c "^$CHUNK_TYPE:incomplete\|malformed or incomplete")" "2"
+$PNGMe remove -i $ENC_FILE -c $CHUNK_TYPE --index 3 > /dev/null
+assert_eq "$($PNGMe search -i $ENC_FILE | sed -n 's/^Total://p')" "0"
+assert_eq "$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE --index 0 -p $PASSPHRASE 2>&1 | grep -c 'no chunk of type')" "1"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt messages in chunks of the same type"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m "First" -p $PASSPHRASE
+$PNGMe encode -i $ENC_FILE -o $ENC_FILE -c $CHUNK_TYPE -m "Second" -p $PASSPHRASE
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE --index 1 -p $PASSPHRASE | capture_secret_mess)
+assert_eq "$PLAINTEXT" "Second"
+$PNGMe remove -i $ENC_FILE -c $CHUNK_TYPE --index 0
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE | capture_secret_mess)
+assert_eq "$PLAINTEXT" "Second"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt a message bound to its chunk type and image"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -p $PASSPHRASE --bind chunk-type,image
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -p $PASSPHRASE | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt with a key of the keystore"
+export PNGME_KEYSTORE=tmp/keystore PNGME_KEYSTORE_PASSPHRASE=$PASSPHRASE
+assert_eq "$($PNGMe key list 2>&1 | grep -c 'create it with')" "1"
+$PNGMe key generate alice --init
+echo $KEY > tmp/bob_key
+$PNGMe key import bob -f tmp/bob_key
+assert_eq "$($PNGMe key list | tr '\n' ' ')" "alice bob "
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE --key-id bob
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -k $KEY | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE --key-id alice
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -k $($PNGMe key export alice) | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+$PNGMe key delete alice
+assert_eq "$($PNGMe key list)" "bob"
+unset PNGME_KEYSTORE PNGME_KEYSTORE_PASSPHRASE
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt to an SSH recipient"
+ssh-keygen -q -t ed25519 -N "" -f tmp/id_ed25519
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -r "$(cat tmp/id_ed25519.pub)"
+PLAINTEXT=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE --identity tmp/id_ed25519 | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+ERROR=$($PNGMe encode -i $OG_FILE -o tmp/dog_lsb_recipient.png --mode lsb -m $MESSAGE -r "$(cat tmp/id_ed25519.pub)" 2>&1)
+assert_eq "$(echo "$ERROR" | grep -c 'only be hidden in a chunk')" "1"
+echo $LINE_BREAKER
+
+echo "TEST sign and verify a message"
+head -c 32 /dev/urandom | base64 > tmp/sign_key
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -k $KEY --sign-key tmp/sign_key
+SIGNATURE=$($PNGMe decode -i $ENC_FILE -c $CHUNK_TYPE -k $KEY | sed -n 's/^Signature:\(.*\)/\1/p')
+assert_eq "${SIGNATURE%% *}" "unknown"
+PUBLIC_KEY=$($PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -k $KEY --sign-key tmp/sign_key -v | sed -n 's/^Signing with the public key \(.*\)\.\.\. done/\1/p')
+echo "$PUBLIC_KEY alice" > tmp/trusted_keys
+SIGNATURE=$($PNGMe verify -i $ENC_FILE --trusted-keys tmp/trusted_keys)
+assert_eq "$SIGNATURE" "$CHUNK_TYPE:valid, signed by alice"
+echo $LINE_BREAKER
+
+echo "TEST sign and verify a whole image"
+$PNGMe encode -i $OG_FILE -o $ENC_FILE -c $CHUNK_TYPE -m $MESSAGE -k $KEY --sign-key tmp/sign_key --sign-image --sign-chunks tEXt
+VERIFIED=$($PNGMe verify -i $ENC_FILE --trusted-keys tmp/trusted_keys 2>&1 | grep -v "^$CHUNK_TYPE:")
+assert_eq "$VERIFIED" "Image:valid, signed by alice"
+$PNGMe text add -i $ENC_FILE -k Author -t Mallory
+CHANGED=$($PNGMe verify -i $ENC_FILE --trusted-keys tmp/trusted_keys | sed -n 's/^Changed:\(.*\)/\1/p')
+assert_eq "$CHANGED" "tEXt #1 added"
+echo $LINE_BREAKER
+
+echo "TEST encrypt/decrypt in the pixels"
+$PNGMe encode -i $OG_FILE -o $LSB_FILE -m $MESSAGE -p $PASSPHRASE --mode lsb
+PLAINTEXT=$($PNGMe decode -i $LSB_FILE -p $PASSPHRASE | capture_secret_mess)
+assert_eq "$PLAINTEXT" "$MESSAGE"
+$PNGMe encode -i $OG_FILE -o $LSB_FILE -m $MESSAGE -p $PASSPHRASE --mode lsb --kdf pbkdf2 --kdf-iterations 1000
+PLAINTEXT=$($PNGMe decode -i $LSB_FILE -p $PASSPHRASE --kdf pbkdf2 --kdf-iterations 1000 | capture_secret_mess)
 assert_eq "$PLAINTEXT" "$MESSAGE"
+assert_eq "$($PNGMe decode -i $OG_FILE -p $PASSPHRASE 2>&1 | grep -c 'Could find your secret message')" "1"
 echo $LINE_BREAKER
 
 echo "TEST search hidden message candidates"
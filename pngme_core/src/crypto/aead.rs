@@ -0,0 +1,221 @@
+use super::SecretBytes;
+use crate::{Error, Result};
+
+use aes_gcm::aead::generic_array::typenum::Unsigned;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, Payload};
+use aes_gcm::Aes256Gcm;
+use aes_gcm_siv::Aes256GcmSiv;
+use chacha20poly1305::XChaCha20Poly1305;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Separates the synthetic nonces from any other use of the key
+const SYNTHETIC_NONCE_DOMAIN: &[u8] = b"PNGme-synthetic-nonce-v1\n";
+
+/// The authenticated cipher protecting an encrypted message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cipher {
+    /// AES-256-GCM, with 96-bit nonces
+    #[default]
+    Aes256Gcm,
+    /// XChaCha20-Poly1305, with 192-bit nonces that are safe to pick at random
+    XChaCha20Poly1305,
+    /// AES-256-GCM-SIV, with 96-bit nonces derived from the message. The same message
+    /// and key always give the same ciphertext
+    Aes256GcmSiv,
+}
+
+impl Cipher {
+    /// The identifier of this cipher in the envelope format
+    pub fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::XChaCha20Poly1305 => 2,
+            Cipher::Aes256GcmSiv => 3,
+        }
+    }
+
+    /// Whether the nonce is derived from the message instead of picked at random
+    pub fn is_deterministic(&self) -> bool {
+        matches!(self, Cipher::Aes256GcmSiv)
+    }
+
+    /// The nonce length of this cipher, in bytes
+    pub fn nonce_length(&self) -> usize {
+        match self {
+            Cipher::Aes256Gcm => nonce_length::<Aes256Gcm>(),
+            Cipher::XChaCha20Poly1305 => nonce_length::<XChaCha20Poly1305>(),
+            Cipher::Aes256GcmSiv => nonce_length::<Aes256GcmSiv>(),
+        }
+    }
+
+    /// Derives a nonce from the key and the plaintext, with HMAC-SHA256.
+    /// AES-GCM-SIV stays secure when the nonce repeats, which only happens for repeated
+    /// messages, so only their equality is revealed.
+    pub(crate) fn synthetic_nonce(&self, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|_| Error::InvalidKeyLength)?;
+        mac.update(SYNTHETIC_NONCE_DOMAIN);
+        mac.update(plaintext);
+        Ok(mac.finalize().into_bytes()[..self.nonce_length()].to_vec())
+    }
+
+    /// Encrypts the plaintext, authenticating the associated data along with it.
+    pub(crate) fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self {
+            Cipher::Aes256Gcm => seal::<Aes256Gcm>(key, nonce, aad, plaintext),
+            Cipher::XChaCha20Poly1305 => seal::<XChaCha20Poly1305>(key, nonce, aad, plaintext),
+            Cipher::Aes256GcmSiv => seal::<Aes256GcmSiv>(key, nonce, aad, plaintext),
+        }
+    }
+
+    /// Decrypts the ciphertext, checking the associated data it was sealed with.
+    pub(crate) fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<SecretBytes> {
+        match self {
+            Cipher::Aes256Gcm => open::<Aes256Gcm>(key, nonce, aad, ciphertext),
+            Cipher::XChaCha20Poly1305 => open::<XChaCha20Poly1305>(key, nonce, aad, ciphertext),
+            Cipher::Aes256GcmSiv => open::<Aes256GcmSiv>(key, nonce, aad, ciphertext),
+        }
+    }
+}
+
+impl TryFrom<u8> for Cipher {
+    type Error = Error;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Cipher::Aes256Gcm),
+            2 => Ok(Cipher::XChaCha20Poly1305),
+            3 => Ok(Cipher::Aes256GcmSiv),
+            _ => Err(Error::InvalidEnvelope(format!("unknown cipher {value}"))),
+        }
+    }
+}
+
+fn nonce_length<C: AeadCore>() -> usize {
+    C::NonceSize::USIZE
+}
+
+fn new_cipher<C: KeyInit>(key: &[u8]) -> Result<C> {
+    C::new_from_slice(key).map_err(|_| Error::InvalidKeyLength)
+}
+
+fn seal<C: Aead + KeyInit>(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = new_cipher::<C>(key)?;
+    if nonce.len() != nonce_length::<C>() {
+        return Err(Error::EncryptionErr);
+    }
+    cipher
+        .encrypt(
+            nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::EncryptionErr)
+}
+
+fn open<C: Aead + KeyInit>(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<SecretBytes> {
+    let cipher = new_cipher::<C>(key)?;
+    if nonce.len() != nonce_length::<C>() {
+        return Err(Error::DecryptionErr);
+    }
+    cipher
+        .decrypt(
+            nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map(SecretBytes::new)
+        .map_err(|_| Error::DecryptionErr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CIPHERS: [Cipher; 3] = [
+        Cipher::Aes256Gcm,
+        Cipher::XChaCha20Poly1305,
+        Cipher::Aes256GcmSiv,
+    ];
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_cipher_roundtrip() -> Result<()> {
+        for cipher in CIPHERS {
+            let nonce = vec![1; cipher.nonce_length()];
+            let ciphertext = cipher.seal(&KEY, &nonce, b"header", b"FooBar!")?;
+            // The message and a 16-byte tag
+            assert_eq!(ciphertext.len(), 7 + 16);
+            assert_eq!(
+                &*cipher.open(&KEY, &nonce, b"header", &ciphertext)?,
+                b"FooBar!"
+            );
+            assert!(cipher.open(&KEY, &nonce, b"other", &ciphertext).is_err());
+            assert!(cipher
+                .open(&[8; 32], &nonce, b"header", &ciphertext)
+                .is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cipher_nonce_length() {
+        assert_eq!(Cipher::Aes256Gcm.nonce_length(), 12);
+        assert_eq!(Cipher::XChaCha20Poly1305.nonce_length(), 24);
+        assert_eq!(Cipher::Aes256GcmSiv.nonce_length(), 12);
+        for cipher in CIPHERS {
+            assert!(cipher.seal(&KEY, &[0; 16], b"", b"FooBar!").is_err());
+            assert!(matches!(
+                cipher.seal(&KEY[..16], &vec![0; cipher.nonce_length()], b"", b"FooBar!"),
+                Err(Error::InvalidKeyLength)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_synthetic_nonce() -> Result<()> {
+        let cipher = Cipher::Aes256GcmSiv;
+        let nonce = cipher.synthetic_nonce(&KEY, b"FooBar!")?;
+        assert_eq!(nonce.len(), cipher.nonce_length());
+        assert_eq!(cipher.synthetic_nonce(&KEY, b"FooBar!")?, nonce);
+        assert_ne!(cipher.synthetic_nonce(&KEY, b"FooBar?")?, nonce);
+        assert_ne!(cipher.synthetic_nonce(&[8; 32], b"FooBar!")?, nonce);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cipher_id() -> Result<()> {
+        for cipher in CIPHERS {
+            assert_eq!(Cipher::try_from(cipher.id())?, cipher);
+        }
+        assert!(Cipher::try_from(0).is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
+use aes_gcm::aead::rand_core::RngCore;
 use aes_gcm::aead::Aead;
-use aes_gcm::{aead::OsRng, AeadCore, Aes256Gcm, Key, KeyInit};
+use aes_gcm::{aead::OsRng, AeadCore, Aes256Gcm, Key, KeyInit, Nonce};
 
+use super::SecretBytes;
 use crate::Error;
 use crate::Result;
 
@@ -9,10 +11,8 @@ fn aes256gcm_cipher(key_raw: &[u8]) -> Result<Aes256Gcm> {
         return Err(Error::InvalidKeyLength);
     }
 
-    let key_raw: [u8; 32] = key_raw.try_into().map_err(|_| Error::InvalidKey)?;
-
     // 256-bit key
-    let key = Key::<Aes256Gcm>::from_slice(&key_raw);
+    let key = Key::<Aes256Gcm>::from_slice(key_raw);
     let cipher = Aes256Gcm::new(key);
 
     Ok(cipher)
@@ -33,11 +33,24 @@ pub fn aes256gcm_encrypt(data: &[u8], key_raw: &[u8]) -> Result<(Vec<u8>, Vec<u8
     Ok((ciphertext, nonce.as_slice().to_vec()))
 }
 
-pub fn aes256gcm_decrypt(ciphertext: &[u8], key_raw: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+pub fn aes256gcm_decrypt(ciphertext: &[u8], key_raw: &[u8], nonce: &[u8]) -> Result<SecretBytes> {
     let cipher = aes256gcm_cipher(key_raw)?;
+    if nonce.len() != AES256GCM_NONCE_LENGTH {
+        return Err(Error::DecryptionErr);
+    }
     let plaintext = cipher
-        .decrypt(nonce.into(), ciphertext)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
         .map_err(|_| Error::DecryptionErr)?;
 
-    Ok(plaintext)
+    Ok(SecretBytes::new(plaintext))
 }
+
+/// Generates a random 256-bit key, suitable for every cipher of the envelope format.
+pub fn generate_key() -> SecretBytes {
+    let mut key = SecretBytes::zeroed(32);
+    OsRng.fill_bytes(key.as_mut_slice());
+    key
+}
+
+/// The nonce length of AES-256-GCM
+const AES256GCM_NONCE_LENGTH: usize = 12;
@@ -0,0 +1,182 @@
+use sha2::{Digest, Sha256};
+
+use crate::img_format::{ChunkType, Png};
+use crate::{Error, Result};
+
+/// The flag of a message bound to the type of its chunk
+const CHUNK_TYPE_FLAG: u8 = 1;
+/// The flag of a message bound to the critical chunks of its image
+const IMAGE_FLAG: u8 = 2;
+
+/// The context an encrypted message is bound to.
+///
+/// It is authenticated as associated data but not stored with the message, so a message
+/// moved into another chunk type or another image fails to decrypt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Binding {
+    chunk_type: Option<ChunkType>,
+    image_hash: Option<[u8; 32]>,
+}
+
+impl Binding {
+    pub fn new() -> Binding {
+        Binding::default()
+    }
+
+    /// Binds the message to the type of the chunk holding it.
+    pub fn with_chunk_type(mut self, chunk_type: ChunkType) -> Binding {
+        self.chunk_type = Some(chunk_type);
+        self
+    }
+
+    /// Binds the message to the critical chunks of the image holding it.
+    pub fn with_image(mut self, png: &Png) -> Binding {
+        self.image_hash = Some(critical_chunks_hash(png));
+        self
+    }
+
+    /// Binds a message about to be hidden in a chunk of the given type, or in the pixels of
+    /// `png` if `None`, to that type and/or to the image.
+    /// Fails if any binding is asked for a message hidden in the pixels, or if the image
+    /// binding is asked for a critical chunk, which would be part of the image it is bound to.
+    pub fn select(
+        chunk_type: Option<ChunkType>,
+        bind_chunk_type: bool,
+        bind_image: bool,
+        png: &Png,
+    ) -> Result<Binding> {
+        let mut binding = Binding::new();
+        if !bind_chunk_type && !bind_image {
+            return Ok(binding);
+        }
+        let chunk_type = chunk_type.ok_or(Error::UnsupportedBinding(
+            "only messages hidden in a chunk can be bound".to_string(),
+        ))?;
+        if bind_chunk_type {
+            binding = binding.with_chunk_type(chunk_type);
+        }
+        if bind_image {
+            if chunk_type.is_critical() {
+                return Err(Error::UnsupportedBinding(format!(
+                    "the critical chunk type {chunk_type} cannot be bound to its image"
+                )));
+            }
+            binding = binding.with_image(png);
+        }
+        Ok(binding)
+    }
+
+    /// The flags recording what the message is bound to
+    pub(crate) fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.chunk_type.is_some() {
+            flags |= CHUNK_TYPE_FLAG;
+        }
+        if self.image_hash.is_some() {
+            flags |= IMAGE_FLAG;
+        }
+        flags
+    }
+
+    /// Checks that the flags are known.
+    pub(crate) fn check_flags(flags: u8) -> Result<()> {
+        if flags & !(CHUNK_TYPE_FLAG | IMAGE_FLAG) != 0 {
+            return Err(Error::InvalidEnvelope(format!(
+                "unknown binding flags {flags:#04x}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the associated data of the context selected by the flags.
+    /// Fails if that part of the context is not known.
+    pub(crate) fn associated_data(&self, flags: u8) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        if flags & CHUNK_TYPE_FLAG != 0 {
+            let chunk_type = self
+                .chunk_type
+                .ok_or(Error::MissingBinding("its chunk type".to_string()))?;
+            data.extend_from_slice(&chunk_type.as_bytes());
+        }
+        if flags & IMAGE_FLAG != 0 {
+            let image_hash = self
+                .image_hash
+                .ok_or(Error::MissingBinding("its image".to_string()))?;
+            data.extend_from_slice(&image_hash);
+        }
+        Ok(data)
+    }
+}
+
+/// Hashes the type, length and data of every critical chunk but IEND, in order.
+/// Ancillary chunks can be added or removed without changing the hash.
+pub fn critical_chunks_hash(png: &Png) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        if !chunk_type.is_critical() || chunk_type.as_bytes() == *b"IEND" {
+            continue;
+        }
+        hasher.update(chunk_type.as_bytes());
+        hasher.update(chunk.length().to_be_bytes());
+        hasher.update(chunk.data());
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_format::testing::testing_png;
+    use crate::img_format::Chunk;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_critical_chunks_hash() -> Result<()> {
+        let mut png = testing_png(&[4, 5])?;
+        let hash = critical_chunks_hash(&png);
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt")?, &[6]), false)?;
+        assert_eq!(critical_chunks_hash(&png), hash);
+        assert_ne!(critical_chunks_hash(&testing_png(&[4, 6])?), hash);
+        Ok(())
+    }
+
+    #[test]
+    fn test_binding_select() -> Result<()> {
+        let png = testing_png(&[4, 5])?;
+        let chunk_type = ChunkType::from_str("ruSt")?;
+        assert_eq!(
+            Binding::select(Some(chunk_type), true, true, &png)?,
+            Binding::new().with_chunk_type(chunk_type).with_image(&png)
+        );
+        assert_eq!(Binding::select(None, false, false, &png)?, Binding::new());
+        assert!(matches!(
+            Binding::select(None, false, true, &png),
+            Err(Error::UnsupportedBinding(_))
+        ));
+
+        let critical = ChunkType::from_str("RUSt")?;
+        assert!(Binding::select(Some(critical), true, false, &png).is_ok());
+        assert!(Binding::select(Some(critical), false, true, &png).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_binding_associated_data() -> Result<()> {
+        let png = testing_png(&[4, 5])?;
+        let binding = Binding::new()
+            .with_chunk_type(ChunkType::from_str("ruSt")?)
+            .with_image(&png);
+        assert_eq!(binding.flags(), CHUNK_TYPE_FLAG | IMAGE_FLAG);
+        assert_eq!(binding.associated_data(0)?, b"");
+        assert_eq!(binding.associated_data(CHUNK_TYPE_FLAG)?, b"ruSt");
+        assert_eq!(binding.associated_data(IMAGE_FLAG)?.len(), 32);
+
+        assert!(matches!(
+            Binding::new().associated_data(IMAGE_FLAG),
+            Err(Error::MissingBinding(_))
+        ));
+        assert!(Binding::check_flags(4).is_err());
+        Ok(())
+    }
+}
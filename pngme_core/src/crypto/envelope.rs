@@ -0,0 +1,531 @@
+use crate::{Error, Result};
+
+use super::kdf::SALT_LENGTH;
+use super::{Binding, Cipher, Kdf, KeySource, SecretBytes};
+
+/// The version of the envelope format of messages bound to no context
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// The version of the envelope format of messages bound to their context,
+/// which records what they are bound to
+pub const BOUND_ENVELOPE_VERSION: u8 = 2;
+
+/// The choices made when sealing an `Envelope`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SealOptions {
+    pub cipher: Cipher,
+    /// The KDF of a passphrase, raw keys are used as they are
+    pub kdf: Kdf,
+    /// The context the message is bound to
+    pub binding: Binding,
+    /// The salt of the passphrase KDF, random if not given. A fixed salt and a deterministic
+    /// cipher make the same message and passphrase give the same envelope
+    pub salt: Option<Vec<u8>>,
+}
+
+/// A self-describing encrypted message.
+///
+/// Everything needed to decrypt the message, except the secret of the user, is stored with it:
+///
+/// | version | cipher | KDF | params length | params | salt length | salt | nonce length | nonce | ciphertext |
+///
+/// Versions, identifiers and lengths take one byte each. The bytes before the ciphertext
+/// form the header, which is authenticated along with the message. Bound messages have
+/// a one-byte set of binding flags right after the KDF, and their context is
+/// authenticated after the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    cipher: Cipher,
+    kdf: Kdf,
+    binding_flags: u8,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Encrypts the plaintext with a fresh random nonce, or a nonce derived from the message
+    /// for deterministic ciphers, and a fresh random salt when the key is derived from
+    /// a passphrase and no salt is given.
+    pub fn seal(
+        plaintext: &[u8],
+        key_source: &KeySource,
+        options: &SealOptions,
+    ) -> Result<Envelope> {
+        let kdf = match key_source {
+            KeySource::Key(_) => Kdf::RawKey,
+            KeySource::Passphrase(_) if options.kdf.is_salted() => options.kdf,
+            KeySource::Passphrase(_) => {
+                return Err(Error::InvalidEnvelope(
+                    "passphrases need a salted KDF".to_string(),
+                ))
+            }
+        };
+        kdf.check_params()?;
+        let salt = match &options.salt {
+            _ if !kdf.is_salted() => Vec::new(),
+            Some(salt) if salt.len() == SALT_LENGTH => salt.clone(),
+            Some(_) => {
+                return Err(Error::InvalidEnvelope(format!(
+                    "expected a {SALT_LENGTH}-byte salt"
+                )))
+            }
+            None => random_bytes(SALT_LENGTH)?,
+        };
+        Self::seal_with(
+            options.cipher,
+            kdf,
+            salt,
+            &options.binding,
+            plaintext,
+            key_source,
+        )
+    }
+
+    fn seal_with(
+        cipher: Cipher,
+        kdf: Kdf,
+        salt: Vec<u8>,
+        binding: &Binding,
+        plaintext: &[u8],
+        key_source: &KeySource,
+    ) -> Result<Envelope> {
+        let mut envelope = Self {
+            cipher,
+            kdf,
+            binding_flags: binding.flags(),
+            salt,
+            nonce: Vec::new(),
+            ciphertext: Vec::new(),
+        };
+        let key = kdf.derive_key(key_source, &envelope.salt)?;
+        envelope.nonce = if cipher.is_deterministic() {
+            cipher.synthetic_nonce(&key, plaintext)?
+        } else {
+            random_bytes(cipher.nonce_length())?
+        };
+        let aad = envelope.associated_data(binding)?;
+        envelope.ciphertext = cipher.seal(&key, &envelope.nonce, &aad, plaintext)?;
+        Ok(envelope)
+    }
+
+    /// Decrypts a message bound to no context, failing if it or its header has been
+    /// tampered with.
+    pub fn open(&self, key_source: &KeySource) -> Result<SecretBytes> {
+        self.open_bound(key_source, &Binding::new())
+    }
+
+    /// Decrypts the message in its context. Only the parts of the context the message
+    /// has been bound to are used, and they must be known.
+    pub fn open_bound(&self, key_source: &KeySource, binding: &Binding) -> Result<SecretBytes> {
+        let aad = self.associated_data(binding)?;
+        let key = self.kdf.derive_key(key_source, &self.salt)?;
+        self.cipher.open(&key, &self.nonce, &aad, &self.ciphertext)
+    }
+
+    /// Returns the header, followed by the context the message is bound to.
+    fn associated_data(&self, binding: &Binding) -> Result<Vec<u8>> {
+        let mut aad = self.header();
+        aad.extend(binding.associated_data(self.binding_flags)?);
+        Ok(aad)
+    }
+
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    pub fn kdf(&self) -> Kdf {
+        self.kdf
+    }
+
+    /// Whether the message is bound to its context
+    pub fn is_bound(&self) -> bool {
+        self.binding_flags != 0
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+
+    /// Returns the header: all the fields before the ciphertext.
+    pub fn header(&self) -> Vec<u8> {
+        let mut header = if self.binding_flags == 0 {
+            vec![ENVELOPE_VERSION, self.cipher.id(), self.kdf.id()]
+        } else {
+            vec![
+                BOUND_ENVELOPE_VERSION,
+                self.cipher.id(),
+                self.kdf.id(),
+                self.binding_flags,
+            ]
+        };
+        for field in [self.kdf.params().as_slice(), &self.salt, &self.nonce] {
+            // Fields are built by this module, all of them are short
+            header.push(field.len() as u8);
+            header.extend_from_slice(field);
+        }
+        header
+    }
+
+    /// Returns this envelope as a byte sequence.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        let mut rest = value;
+        let fixed = take(&mut rest, 3)?;
+        let (version, cipher, kdf) = (fixed[0], fixed[1], fixed[2]);
+        let binding_flags = match version {
+            ENVELOPE_VERSION => 0,
+            BOUND_ENVELOPE_VERSION => {
+                let flags = take(&mut rest, 1)?[0];
+                Binding::check_flags(flags)?;
+                if flags == 0 {
+                    // Unbound messages use the first version
+                    return Err(Error::InvalidEnvelope("empty binding flags".to_string()));
+                }
+                flags
+            }
+            _ => {
+                return Err(Error::InvalidEnvelope(format!(
+                    "unsupported version {version}"
+                )))
+            }
+        };
+        let cipher = Cipher::try_from(cipher)?;
+        let kdf = Kdf::from_parts(kdf, take_field(&mut rest)?)?;
+        let salt = take_field(&mut rest)?.to_vec();
+        let nonce = take_field(&mut rest)?.to_vec();
+        if nonce.len() != cipher.nonce_length() {
+            return Err(Error::InvalidEnvelope(format!(
+                "expected a nonce of {} bytes, found {}",
+                cipher.nonce_length(),
+                nonce.len()
+            )));
+        }
+
+        Ok(Self {
+            cipher,
+            kdf,
+            binding_flags,
+            salt,
+            nonce,
+            ciphertext: rest.to_vec(),
+        })
+    }
+}
+
+/// Splits the first `length` bytes off `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], length: usize) -> Result<&'a [u8]> {
+    if bytes.len() < length {
+        return Err(Error::InvalidEnvelope("truncated header".to_string()));
+    }
+    let (field, rest) = bytes.split_at(length);
+    *bytes = rest;
+    Ok(field)
+}
+
+/// Splits a field prefixed by its one-byte length off `bytes`.
+fn take_field<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let length = take(bytes, 1)?[0] as usize;
+    take(bytes, length)
+}
+
+fn random_bytes(length: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; length];
+    getrandom::getrandom(&mut bytes).map_err(|_| Error::EncryptionErr)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_format::testing::testing_png;
+    use crate::img_format::ChunkType;
+    use std::str::FromStr;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    /// Cheap parameters, the defaults are too slow for tests
+    fn test_options() -> SealOptions {
+        SealOptions {
+            kdf: Kdf::Argon2id {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() -> Result<()> {
+        for key_source in [
+            KeySource::Key(KEY.to_vec().into()),
+            KeySource::Passphrase("HelloWorld!".into()),
+        ] {
+            let envelope = Envelope::seal(b"FooBar!", &key_source, &test_options())?;
+            let parsed = Envelope::try_from(envelope.as_bytes().as_slice())?;
+            assert_eq!(parsed, envelope);
+            assert_eq!(&*parsed.open(&key_source)?, b"FooBar!");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_deterministic() -> Result<()> {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let options = SealOptions {
+            cipher: Cipher::Aes256GcmSiv,
+            salt: Some(vec![3; SALT_LENGTH]),
+            ..test_options()
+        };
+        let envelope = Envelope::seal(b"FooBar!", &passphrase, &options)?;
+        let bytes = envelope.as_bytes();
+        assert_eq!(bytes[1], 3);
+        assert_eq!(
+            Envelope::seal(b"FooBar!", &passphrase, &options)?.as_bytes(),
+            bytes
+        );
+        assert_ne!(
+            Envelope::seal(b"FooBar?", &passphrase, &options)?.nonce(),
+            envelope.nonce()
+        );
+        let parsed = Envelope::try_from(bytes.as_slice())?;
+        assert_eq!(&*parsed.open(&passphrase)?, b"FooBar!");
+
+        // A random salt gives a different key, so a different nonce
+        let salted = SealOptions {
+            salt: None,
+            ..options.clone()
+        };
+        assert_ne!(
+            Envelope::seal(b"FooBar!", &passphrase, &salted)?.as_bytes(),
+            bytes
+        );
+        let short_salt = SealOptions {
+            salt: Some(vec![3; 8]),
+            ..options
+        };
+        assert!(Envelope::seal(b"FooBar!", &passphrase, &short_salt).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_xchacha20poly1305() -> Result<()> {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let options = SealOptions {
+            cipher: Cipher::XChaCha20Poly1305,
+            ..test_options()
+        };
+        let envelope = Envelope::seal(b"FooBar!", &passphrase, &options)?;
+        let bytes = envelope.as_bytes();
+        assert_eq!(bytes[1], 2);
+        assert_eq!(envelope.nonce().len(), 24);
+        // The cipher is picked from the envelope when decrypting
+        let parsed = Envelope::try_from(bytes.as_slice())?;
+        assert_eq!(parsed.cipher(), Cipher::XChaCha20Poly1305);
+        assert_eq!(&*parsed.open(&passphrase)?, b"FooBar!");
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_pbkdf2() -> Result<()> {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let options = SealOptions {
+            kdf: Kdf::Pbkdf2 { iterations: 10 },
+            ..Default::default()
+        };
+        let envelope = Envelope::seal(b"FooBar!", &passphrase, &options)?;
+        let parsed = Envelope::try_from(envelope.as_bytes().as_slice())?;
+        assert_eq!(parsed.kdf(), Kdf::Pbkdf2 { iterations: 10 });
+        assert_eq!(&*parsed.open(&passphrase)?, b"FooBar!");
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_layout() -> Result<()> {
+        let envelope = Envelope::seal(
+            b"FooBar!",
+            &KeySource::Key(KEY.to_vec().into()),
+            &test_options(),
+        )?;
+        let bytes = envelope.as_bytes();
+        assert_eq!(bytes[..6], [ENVELOPE_VERSION, 1, 0, 0, 0, 12]);
+        assert_eq!(bytes[6..18], *envelope.nonce());
+        // The ciphertext holds the message and a 16-byte tag
+        assert_eq!(bytes.len(), 18 + 7 + 16);
+
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let envelope = Envelope::seal(b"FooBar!", &passphrase, &test_options())?;
+        let bytes = envelope.as_bytes();
+        // Argon2id with 9 bytes of parameters, then a 16-byte salt
+        assert_eq!(bytes[..4], [ENVELOPE_VERSION, 1, 2, 9]);
+        assert_eq!(bytes[13], 16);
+        assert_eq!(bytes[14..30], *envelope.salt());
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_random_salt() -> Result<()> {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let first = Envelope::seal(b"FooBar!", &passphrase, &test_options())?;
+        let second = Envelope::seal(b"FooBar!", &passphrase, &test_options())?;
+        assert_ne!(first.salt(), second.salt());
+        assert_ne!(first.ciphertext(), second.ciphertext());
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_wrong_secret() -> Result<()> {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let envelope = Envelope::seal(b"FooBar!", &passphrase, &test_options())?;
+        let wrong = KeySource::Passphrase("HelloWorld?".into());
+        assert!(matches!(envelope.open(&wrong), Err(Error::DecryptionErr)));
+        let key = KeySource::Key(KEY.to_vec().into());
+        assert!(matches!(envelope.open(&key), Err(Error::DecryptionErr)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_unsalted_passphrase() {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let options = SealOptions {
+            kdf: Kdf::Sha3,
+            ..Default::default()
+        };
+        assert!(Envelope::seal(b"FooBar!", &passphrase, &options).is_err());
+    }
+
+    #[test]
+    fn test_envelope_sha3() -> Result<()> {
+        // Envelopes of the first version derived the key with SHA3-256
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let envelope = Envelope::seal_with(
+            Cipher::Aes256Gcm,
+            Kdf::Sha3,
+            Vec::new(),
+            &Binding::new(),
+            b"FooBar!",
+            &passphrase,
+        )?;
+        let parsed = Envelope::try_from(envelope.as_bytes().as_slice())?;
+        assert_eq!(&*parsed.open(&passphrase)?, b"FooBar!");
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_tampered_header() -> Result<()> {
+        let passphrase = KeySource::Passphrase("HelloWorld!".into());
+        let envelope = Envelope::seal_with(
+            Cipher::Aes256Gcm,
+            Kdf::Sha3,
+            Vec::new(),
+            &Binding::new(),
+            b"FooBar!",
+            &passphrase,
+        )?;
+        let mut bytes = envelope.as_bytes();
+        // Switch the KDF to a raw key, which gives the same key
+        bytes[2] = 0;
+        let key_source = KeySource::Key(passphrase.legacy_key()?);
+        assert!(Envelope::try_from(bytes.as_slice())?
+            .open(&key_source)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_envelope() {
+        assert!(Envelope::try_from(&[][..]).is_err());
+        // Unknown version, cipher and KDF
+        assert!(Envelope::try_from(&[3, 1, 0, 0, 0, 0][..]).is_err());
+        assert!(Envelope::try_from(&[1, 9, 0, 0, 0, 0][..]).is_err());
+        assert!(Envelope::try_from(&[1, 1, 9, 0, 0, 0][..]).is_err());
+        // Truncated nonce
+        assert!(Envelope::try_from(&[1, 1, 0, 0, 0, 12, 0, 0][..]).is_err());
+        // Wrong nonce length
+        assert!(Envelope::try_from(&[1, 1, 0, 0, 0, 2, 0, 0][..]).is_err());
+        // Empty and unknown binding flags
+        assert!(Envelope::try_from(&[2, 1, 0, 0, 0, 0, 0][..]).is_err());
+        assert!(Envelope::try_from(&[2, 1, 0, 4, 0, 0, 0][..]).is_err());
+    }
+
+    #[test]
+    fn test_envelope_bound_to_chunk_type() -> Result<()> {
+        let key = KeySource::Key(KEY.to_vec().into());
+        let binding = Binding::new().with_chunk_type(ChunkType::from_str("ruSt")?);
+        let options = SealOptions {
+            binding: binding.clone(),
+            ..test_options()
+        };
+        let bytes = Envelope::seal(b"FooBar!", &key, &options)?.as_bytes();
+        assert_eq!(bytes[..4], [BOUND_ENVELOPE_VERSION, 1, 0, 1]);
+
+        let envelope = Envelope::try_from(bytes.as_slice())?;
+        assert!(envelope.is_bound());
+        assert_eq!(&*envelope.open_bound(&key, &binding)?, b"FooBar!");
+        // Renamed chunk
+        let renamed = Binding::new().with_chunk_type(ChunkType::from_str("ruSy")?);
+        assert!(matches!(
+            envelope.open_bound(&key, &renamed),
+            Err(Error::DecryptionErr)
+        ));
+        assert!(matches!(envelope.open(&key), Err(Error::MissingBinding(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_bound_to_image() -> Result<()> {
+        let key = KeySource::Key(KEY.to_vec().into());
+        let png = testing_png(&[4, 5])?;
+        let options = SealOptions {
+            binding: Binding::new().with_image(&png),
+            ..test_options()
+        };
+        let envelope = Envelope::seal(b"FooBar!", &key, &options)?;
+        // Only the image is used, even when the chunk type is known
+        let context = Binding::new()
+            .with_chunk_type(ChunkType::from_str("ruSt")?)
+            .with_image(&png);
+        assert_eq!(&*envelope.open_bound(&key, &context)?, b"FooBar!");
+        // Transplanted into another image
+        let other = Binding::new().with_image(&testing_png(&[4, 6])?);
+        assert!(envelope.open_bound(&key, &other).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_stripped_binding() -> Result<()> {
+        let key = KeySource::Key(KEY.to_vec().into());
+        let binding = Binding::new().with_chunk_type(ChunkType::from_str("ruSt")?);
+        let options = SealOptions {
+            binding: binding.clone(),
+            ..test_options()
+        };
+        let bytes = Envelope::seal(b"FooBar!", &key, &options)?.as_bytes();
+        // Rewrite the message as an unbound one
+        let mut stripped = vec![ENVELOPE_VERSION];
+        stripped.extend_from_slice(&bytes[1..3]);
+        stripped.extend_from_slice(&bytes[4..]);
+        let envelope = Envelope::try_from(stripped.as_slice())?;
+        assert!(envelope.open(&key).is_err());
+        assert!(envelope.open_bound(&key, &binding).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use ed25519_dalek::Signer;
+use sha2::{Digest, Sha256};
+
+use super::signature::{check_signature, signing_key, ED25519_KEY_LENGTH, SIGNATURE_LENGTH};
+use super::{SignatureStatus, TrustedKeys};
+use crate::img_format::{Chunk, ChunkType, Png};
+use crate::{Error, Result};
+
+/// The chunk type holding the signature of the image: ancillary, private,
+/// and unsafe to copy since it depends on the critical chunks
+pub const INTEGRITY_CHUNK_TYPE: &str = "siGN";
+
+/// The version of the integrity chunk format
+const INTEGRITY_VERSION: u8 = 1;
+
+/// Separates the image signatures from any other use of the same key
+const SIGNING_DOMAIN: &[u8] = b"PNGme-image-sig-v1\n";
+
+/// The length of a covered chunk entry: chunk type, index and SHA-256 hash
+const ENTRY_LENGTH: usize = 4 + 4 + 32;
+
+/// Signs the critical chunks of the image along with the ancillary chunks of the given
+/// types, and stores the signature in an integrity chunk, replacing any previous one.
+///
+/// The IDAT chunks are hashed as a single stream, so splitting it differently does not
+/// count as a change. Every other chunk is hashed on its own.
+pub fn sign_image(png: &mut Png, private_key: &[u8], ancillary: &[ChunkType]) -> Result<()> {
+    let signing_key = signing_key(private_key)?;
+    let covered: Vec<ChunkType> = ancillary
+        .iter()
+        .filter(|chunk_type| !chunk_type.is_critical() && !is_integrity_chunk(chunk_type))
+        .copied()
+        .collect();
+    if covered.len() > u8::MAX as usize {
+        return Err(Error::InvalidLength(
+            "At most 255 ancillary chunk types can be signed".to_string(),
+        ));
+    }
+
+    let mut manifest = ImageManifest {
+        public_key: signing_key.verifying_key().to_bytes(),
+        entries: entries(png, &covered),
+        covered,
+        signature: [0; SIGNATURE_LENGTH],
+    };
+    manifest.signature = signing_key.sign(&manifest.signed_bytes()).to_bytes();
+
+    while png.remove_chunk(INTEGRITY_CHUNK_TYPE).is_ok() {}
+    let chunk_type = ChunkType::from_str(INTEGRITY_CHUNK_TYPE)?;
+    png.append_chunk(Chunk::new(chunk_type, &manifest.as_bytes()), false)
+}
+
+/// Checks the signature of the integrity chunk, then lists the signed chunks that have
+/// changed since. Returns `None` when the image has not been signed.
+pub fn verify_image(png: &Png, trusted_keys: &TrustedKeys) -> Result<Option<ImageVerification>> {
+    let Some(chunk) = png.chunk_by_type(INTEGRITY_CHUNK_TYPE)? else {
+        return Ok(None);
+    };
+    let manifest = ImageManifest::try_from(chunk.data())?;
+    let signature = check_signature(
+        &manifest.public_key,
+        &manifest.signed_bytes(),
+        &manifest.signature,
+        trusted_keys,
+    );
+
+    let current = entries(png, &manifest.covered);
+    let mut changes = Vec::new();
+    for signed in &manifest.entries {
+        match current.iter().find(|entry| entry.same_chunk(signed)) {
+            None => changes.push(signed.change(ChangeKind::Removed)),
+            Some(entry) if entry.hash != signed.hash => {
+                changes.push(signed.change(ChangeKind::Modified))
+            }
+            Some(_) => {}
+        }
+    }
+    for entry in &current {
+        if !manifest
+            .entries
+            .iter()
+            .any(|signed| signed.same_chunk(entry))
+        {
+            changes.push(entry.change(ChangeKind::Added));
+        }
+    }
+
+    Ok(Some(ImageVerification { signature, changes }))
+}
+
+/// The outcome of checking the integrity chunk of an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageVerification {
+    signature: SignatureStatus,
+    changes: Vec<ChunkChange>,
+}
+
+impl ImageVerification {
+    /// The status of the signature of the integrity chunk itself
+    pub fn signature(&self) -> &SignatureStatus {
+        &self.signature
+    }
+
+    /// The signed chunks that have changed since the image was signed
+    pub fn changes(&self) -> &[ChunkChange] {
+        &self.changes
+    }
+
+    /// Whether the signature is valid and no signed chunk has changed
+    pub fn is_intact(&self) -> bool {
+        self.signature != SignatureStatus::Invalid && self.changes.is_empty()
+    }
+}
+
+/// A signed chunk that has changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkChange {
+    pub chunk_type: ChunkType,
+    /// The position of the chunk among the chunks of the same type
+    pub index: usize,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Modified,
+    Removed,
+    Added,
+}
+
+impl Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ChangeKind::Modified => "modified",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Added => "added",
+        };
+        write!(f, "{} #{} {kind}", self.chunk_type, self.index + 1)
+    }
+}
+
+/// The hash of a covered chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    chunk_type: ChunkType,
+    index: u32,
+    hash: [u8; 32],
+}
+
+impl Entry {
+    fn same_chunk(&self, other: &Entry) -> bool {
+        self.chunk_type == other.chunk_type && self.index == other.index
+    }
+
+    fn change(&self, kind: ChangeKind) -> ChunkChange {
+        ChunkChange {
+            chunk_type: self.chunk_type,
+            index: self.index as usize,
+            kind,
+        }
+    }
+}
+
+/// The content of the integrity chunk, laid out as:
+///
+/// | version | public key | covered count | covered types | entry count | entries | signature |
+///
+/// Counts are a u8 and a big-endian u32, entries are a chunk type, a big-endian u32 index
+/// and a SHA-256 hash. The signature covers every other byte.
+struct ImageManifest {
+    public_key: [u8; ED25519_KEY_LENGTH],
+    covered: Vec<ChunkType>,
+    entries: Vec<Entry>,
+    signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl ImageManifest {
+    /// Returns the bytes covered by the signature, prefixed by the signing domain.
+    fn signed_bytes(&self) -> Vec<u8> {
+        [SIGNING_DOMAIN, &self.body()].concat()
+    }
+
+    /// Returns every field but the signature.
+    fn body(&self) -> Vec<u8> {
+        let mut bytes = vec![INTEGRITY_VERSION];
+        bytes.extend_from_slice(&self.public_key);
+        bytes.push(self.covered.len() as u8);
+        for chunk_type in &self.covered {
+            bytes.extend_from_slice(&chunk_type.as_bytes());
+        }
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.chunk_type.as_bytes());
+            bytes.extend_from_slice(&entry.index.to_be_bytes());
+            bytes.extend_from_slice(&entry.hash);
+        }
+        bytes
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.body();
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for ImageManifest {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let truncated = || Error::InvalidIntegrityChunk("truncated".to_string());
+        let (&version, rest) = bytes.split_first().ok_or_else(truncated)?;
+        if version != INTEGRITY_VERSION {
+            return Err(Error::InvalidIntegrityChunk(format!(
+                "unsupported version {version}"
+            )));
+        }
+        let (public_key, rest) = take(rest, ED25519_KEY_LENGTH).ok_or_else(truncated)?;
+        let (&covered_count, rest) = rest.split_first().ok_or_else(truncated)?;
+        let (covered, rest) = take(rest, covered_count as usize * 4).ok_or_else(truncated)?;
+        let (entry_count, rest) = take(rest, 4).ok_or_else(truncated)?;
+        let entry_count = u32::from_be_bytes(entry_count.try_into().expect("Checked length"));
+        let entries_length = (entry_count as usize)
+            .checked_mul(ENTRY_LENGTH)
+            .ok_or_else(truncated)?;
+        let (entries, signature) = take(rest, entries_length).ok_or_else(truncated)?;
+        if signature.len() != SIGNATURE_LENGTH {
+            return Err(Error::InvalidIntegrityChunk(
+                "invalid signature length".to_string(),
+            ));
+        }
+
+        let covered = covered
+            .chunks(4)
+            .map(chunk_type)
+            .collect::<Result<Vec<_>>>()?;
+        let entries = entries
+            .chunks(ENTRY_LENGTH)
+            .map(|entry| {
+                Ok(Entry {
+                    chunk_type: chunk_type(&entry[..4])?,
+                    index: u32::from_be_bytes(entry[4..8].try_into().expect("Checked length")),
+                    hash: entry[8..].try_into().expect("Checked length"),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            public_key: public_key.try_into().expect("Checked length"),
+            covered,
+            entries,
+            signature: signature.try_into().expect("Checked length"),
+        })
+    }
+}
+
+/// Hashes the critical chunks and the ancillary chunks of the covered types.
+fn entries(png: &Png, covered: &[ChunkType]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    let mut idat: Option<Sha256> = None;
+    for chunk in png.chunks() {
+        let chunk_type = *chunk.chunk_type();
+        if is_integrity_chunk(&chunk_type)
+            || !(chunk_type.is_critical() || covered.contains(&chunk_type))
+        {
+            continue;
+        }
+        if chunk_type.as_bytes() == *b"IDAT" {
+            idat.get_or_insert_with(Sha256::new).update(chunk.data());
+            continue;
+        }
+        let index = counts.entry(chunk_type.as_bytes()).or_insert(0);
+        entries.push(Entry {
+            chunk_type,
+            index: *index,
+            hash: Sha256::digest(chunk.data()).into(),
+        });
+        *index += 1;
+    }
+    if let Some(idat) = idat {
+        entries.push(Entry {
+            chunk_type: ChunkType::from_str("IDAT").expect("Valid chunk type"),
+            index: 0,
+            hash: idat.finalize().into(),
+        });
+    }
+    entries
+}
+
+fn is_integrity_chunk(chunk_type: &ChunkType) -> bool {
+    chunk_type.as_bytes() == *INTEGRITY_CHUNK_TYPE.as_bytes()
+}
+
+fn chunk_type(bytes: &[u8]) -> Result<ChunkType> {
+    let bytes: [u8; 4] = bytes.try_into().expect("Checked length");
+    ChunkType::try_from(bytes)
+        .map_err(|_| Error::InvalidIntegrityChunk("invalid chunk type".to_string()))
+}
+
+/// Splits the first `length` bytes off, if there are enough of them.
+fn take(bytes: &[u8], length: usize) -> Option<(&[u8], &[u8])> {
+    (bytes.len() >= length).then(|| bytes.split_at(length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::ed25519_public_key;
+    use crate::img_format::testing::testing_image;
+    use crate::img_format::{ChunkPlacement, ColorType, Interlace, PixelBuffer};
+
+    const ALICE: [u8; 32] = [1; 32];
+
+    fn testing_png() -> Result<Png> {
+        let mut png = testing_image(4, 4, 8, ColorType::Grayscale, Interlace::None)?;
+        png.append_chunk(Chunk::new(ChunkType::from_str("tEXt")?, b"a\0b"), false)?;
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt")?, b"data"), false)?;
+        Ok(png)
+    }
+
+    fn trusting_alice() -> Result<TrustedKeys> {
+        let mut trusted_keys = TrustedKeys::new();
+        trusted_keys.add(&ed25519_public_key(&ALICE)?, Some("alice"))?;
+        Ok(trusted_keys)
+    }
+
+    fn signed_png() -> Result<Png> {
+        let mut png = testing_png()?;
+        sign_image(&mut png, &ALICE, &[ChunkType::from_str("tEXt")?])?;
+        Png::try_from(png.as_bytes().as_slice())
+    }
+
+    #[test]
+    fn test_sign_image_intact() -> Result<()> {
+        let png = signed_png()?;
+        png.validate()?;
+        let verification = verify_image(&png, &trusting_alice()?)?.unwrap();
+        assert_eq!(
+            verification.signature(),
+            &SignatureStatus::Valid("alice".to_string())
+        );
+        assert!(verification.is_intact());
+
+        assert_eq!(verify_image(&testing_png()?, &trusting_alice()?)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_image_twice() -> Result<()> {
+        let mut png = signed_png()?;
+        sign_image(&mut png, &ALICE, &[])?;
+        let count = png
+            .chunks()
+            .iter()
+            .filter(|chunk| is_integrity_chunk(chunk.chunk_type()))
+            .count();
+        assert_eq!(count, 1);
+        assert!(verify_image(&png, &trusting_alice()?)?.unwrap().is_intact());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_image_changes() -> Result<()> {
+        let mut png = signed_png()?;
+        // Uncovered ancillary chunks may change freely
+        png.remove_chunk("ruSt")?;
+        png.remove_chunk("tEXt")?;
+        png.append_chunk(Chunk::new(ChunkType::from_str("tEXt")?, b"c\0d"), false)?;
+        png.append_chunk(Chunk::new(ChunkType::from_str("tEXt")?, b"e\0f"), false)?;
+        let pixels = PixelBuffer::new(4, 4, 8, ColorType::Grayscale, vec![0; 16])?;
+        png.encode_pixels(&pixels, Interlace::None)?;
+
+        let verification = verify_image(&png, &trusting_alice()?)?.unwrap();
+        assert_eq!(
+            verification.signature(),
+            &SignatureStatus::Valid("alice".to_string())
+        );
+        let changes: Vec<String> = verification
+            .changes()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            vec!["tEXt #1 modified", "IDAT #1 modified", "tEXt #2 added"]
+        );
+        assert!(!verification.is_intact());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_image_added_critical_chunk() -> Result<()> {
+        let mut png = signed_png()?;
+        let plte = Chunk::new(ChunkType::from_str("PLTE")?, &[0; 3]);
+        png.insert_chunk(
+            plte,
+            false,
+            ChunkPlacement::After(ChunkType::from_str("IHDR")?),
+        )?;
+        let verification = verify_image(&png, &trusting_alice()?)?.unwrap();
+        assert_eq!(
+            verification.changes(),
+            &[ChunkChange {
+                chunk_type: ChunkType::from_str("PLTE")?,
+                index: 0,
+                kind: ChangeKind::Added,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_image_tampered_signature() -> Result<()> {
+        let mut png = signed_png()?;
+        let mut manifest = png.remove_chunk(INTEGRITY_CHUNK_TYPE)?.data().to_vec();
+        // Flip a bit of the last signed hash
+        let last_hash_byte = manifest.len() - SIGNATURE_LENGTH - 1;
+        manifest[last_hash_byte] ^= 1;
+        png.append_chunk(
+            Chunk::new(ChunkType::from_str(INTEGRITY_CHUNK_TYPE)?, &manifest),
+            false,
+        )?;
+        let verification = verify_image(&png, &trusting_alice()?)?.unwrap();
+        assert_eq!(verification.signature(), &SignatureStatus::Invalid);
+        assert!(!verification.is_intact());
+
+        png.remove_chunk(INTEGRITY_CHUNK_TYPE)?;
+        png.append_chunk(
+            Chunk::new(ChunkType::from_str(INTEGRITY_CHUNK_TYPE)?, &manifest[..50]),
+            false,
+        )?;
+        assert!(matches!(
+            verify_image(&png, &trusting_alice()?),
+            Err(Error::InvalidIntegrityChunk(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{Error, Result};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use sha2::Sha256;
+
+use super::{sha3_hash, SecretBytes, SecretString};
+
+/// The length of the derived keys
+const KEY_LENGTH: usize = 32;
+
+/// The length of the salt of the passphrase KDFs
+pub const SALT_LENGTH: usize = 16;
+
+/// Upper bounds of the cost parameters read from a message, so that a crafted message
+/// cannot make decoding run out of memory or time
+const MAX_ARGON2_MEMORY_KIB: u32 = 1 << 20;
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_ARGON2_PARALLELISM: u8 = 16;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// How the key of a message is obtained from what the user knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// The user gives the key itself
+    RawKey,
+    /// The key is the unsalted SHA3-256 hash of the passphrase.
+    /// Only kept to decrypt the messages written before Argon2id was introduced.
+    Sha3,
+    /// Argon2id, memory-hard: the default for passphrases
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u8,
+    },
+    /// PBKDF2-HMAC-SHA256, for environments too constrained for Argon2id
+    Pbkdf2 { iterations: u32 },
+}
+
+impl Kdf {
+    /// Argon2id with the parameters recommended by OWASP: 19 MiB, 2 iterations
+    pub const ARGON2ID_DEFAULT: Kdf = Kdf::Argon2id {
+        memory_kib: 19 * 1024,
+        iterations: 2,
+        parallelism: 1,
+    };
+
+    /// PBKDF2-HMAC-SHA256 with the number of iterations recommended by OWASP
+    pub const PBKDF2_DEFAULT: Kdf = Kdf::Pbkdf2 {
+        iterations: 600_000,
+    };
+
+    /// The identifier of this KDF in the envelope format
+    pub fn id(&self) -> u8 {
+        match self {
+            Kdf::RawKey => 0,
+            Kdf::Sha3 => 1,
+            Kdf::Argon2id { .. } => 2,
+            Kdf::Pbkdf2 { .. } => 3,
+        }
+    }
+
+    /// Whether this KDF derives the key from a passphrase with a random salt
+    pub fn is_salted(&self) -> bool {
+        matches!(self, Kdf::Argon2id { .. } | Kdf::Pbkdf2 { .. })
+    }
+
+    /// The cost parameters of this KDF, big-endian encoded
+    pub(crate) fn params(&self) -> Vec<u8> {
+        match self {
+            Kdf::RawKey | Kdf::Sha3 => Vec::new(),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => [
+                memory_kib.to_be_bytes().as_slice(),
+                &iterations.to_be_bytes(),
+                &[*parallelism],
+            ]
+            .concat(),
+            Kdf::Pbkdf2 { iterations } => iterations.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// Parses the KDF identifier and cost parameters of a message.
+    pub(crate) fn from_parts(id: u8, params: &[u8]) -> Result<Kdf> {
+        let invalid_params = || Error::InvalidEnvelope(format!("invalid parameters for KDF {id}"));
+        let kdf = match (id, params.len()) {
+            (0, 0) => Kdf::RawKey,
+            (1, 0) => Kdf::Sha3,
+            (2, 9) => Kdf::Argon2id {
+                memory_kib: u32::from_be_bytes([params[0], params[1], params[2], params[3]]),
+                iterations: u32::from_be_bytes([params[4], params[5], params[6], params[7]]),
+                parallelism: params[8],
+            },
+            (3, 4) => Kdf::Pbkdf2 {
+                iterations: u32::from_be_bytes([params[0], params[1], params[2], params[3]]),
+            },
+            (0..=3, _) => return Err(invalid_params()),
+            _ => return Err(Error::InvalidEnvelope(format!("unknown KDF {id}"))),
+        };
+        kdf.check_params().map_err(|_| invalid_params())?;
+        Ok(kdf)
+    }
+
+    /// Checks that the cost parameters are within the supported bounds.
+    pub fn check_params(&self) -> Result<()> {
+        let valid = match *self {
+            Kdf::RawKey | Kdf::Sha3 => true,
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                (1..=MAX_ARGON2_PARALLELISM).contains(&parallelism)
+                    // Argon2 needs at least 8 KiB per lane
+                    && (8 * parallelism as u32..=MAX_ARGON2_MEMORY_KIB).contains(&memory_kib)
+                    && (1..=MAX_ARGON2_ITERATIONS).contains(&iterations)
+            }
+            Kdf::Pbkdf2 { iterations } => (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations),
+        };
+        if !valid {
+            return Err(Error::KeyDerivationErr);
+        }
+        Ok(())
+    }
+
+    /// Derives the key from the raw key or passphrase of the user.
+    /// Fails if the KDF expects the other kind of secret.
+    pub(crate) fn derive_key(&self, key_source: &KeySource, salt: &[u8]) -> Result<SecretBytes> {
+        match (self, key_source) {
+            (Kdf::RawKey, KeySource::Key(key)) => Ok(key.clone()),
+            (Kdf::Sha3, KeySource::Passphrase(passphrase)) => {
+                sha3_hash(passphrase).map(SecretBytes::new)
+            }
+            (
+                Kdf::Argon2id {
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                },
+                KeySource::Passphrase(passphrase),
+            ) => {
+                let params = Params::new(
+                    *memory_kib,
+                    *iterations,
+                    *parallelism as u32,
+                    Some(KEY_LENGTH),
+                )
+                .map_err(|_| Error::KeyDerivationErr)?;
+                let mut key = SecretBytes::zeroed(KEY_LENGTH);
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+                    .map_err(|_| Error::KeyDerivationErr)?;
+                Ok(key)
+            }
+            (Kdf::Pbkdf2 { iterations }, KeySource::Passphrase(passphrase)) => {
+                let mut key = SecretBytes::zeroed(KEY_LENGTH);
+                pbkdf2::pbkdf2_hmac::<Sha256>(
+                    passphrase.as_bytes(),
+                    salt,
+                    *iterations,
+                    key.as_mut_slice(),
+                );
+                Ok(key)
+            }
+            // The message was not encrypted with this kind of secret
+            _ => Err(Error::DecryptionErr),
+        }
+    }
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::ARGON2ID_DEFAULT
+    }
+}
+
+/// The secret given by the user to encrypt or decrypt a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// A raw 256-bit key
+    Key(SecretBytes),
+    Passphrase(SecretString),
+}
+
+impl KeySource {
+    /// The key used before the envelope format: the raw key, or the SHA3-256 hash of the
+    /// passphrase. Only kept to decrypt the messages of older versions.
+    pub fn legacy_key(&self) -> Result<SecretBytes> {
+        match self {
+            KeySource::Key(key) => Ok(key.clone()),
+            KeySource::Passphrase(passphrase) => sha3_hash(passphrase).map(SecretBytes::new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, the defaults are too slow for tests
+    const ARGON2ID_TEST: Kdf = Kdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn passphrase() -> KeySource {
+        KeySource::Passphrase("HelloWorld!".into())
+    }
+
+    #[test]
+    fn test_kdf_params_roundtrip() -> Result<()> {
+        for kdf in [
+            Kdf::RawKey,
+            Kdf::Sha3,
+            Kdf::ARGON2ID_DEFAULT,
+            Kdf::PBKDF2_DEFAULT,
+        ] {
+            assert_eq!(Kdf::from_parts(kdf.id(), &kdf.params())?, kdf);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_kdf_invalid_params() {
+        assert!(Kdf::from_parts(0, &[1]).is_err());
+        assert!(Kdf::from_parts(2, &[0; 4]).is_err());
+        assert!(Kdf::from_parts(9, &[]).is_err());
+        // Zero iterations
+        assert!(Kdf::from_parts(3, &[0; 4]).is_err());
+        // 4 GiB of memory
+        let huge = Kdf::Argon2id {
+            memory_kib: u32::MAX,
+            iterations: 1,
+            parallelism: 1,
+        };
+        assert!(Kdf::from_parts(2, &huge.params()).is_err());
+    }
+
+    #[test]
+    fn test_argon2id_salted() -> Result<()> {
+        let key = ARGON2ID_TEST.derive_key(&passphrase(), &[1; SALT_LENGTH])?;
+        assert_eq!(key.len(), KEY_LENGTH);
+        assert_eq!(
+            ARGON2ID_TEST.derive_key(&passphrase(), &[1; SALT_LENGTH])?,
+            key
+        );
+        assert_ne!(
+            ARGON2ID_TEST.derive_key(&passphrase(), &[2; SALT_LENGTH])?,
+            key
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pbkdf2_salted() -> Result<()> {
+        let kdf = Kdf::Pbkdf2 { iterations: 10 };
+        let key = kdf.derive_key(&passphrase(), &[1; SALT_LENGTH])?;
+        assert_eq!(key.len(), KEY_LENGTH);
+        assert_ne!(kdf.derive_key(&passphrase(), &[2; SALT_LENGTH])?, key);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kdf_wrong_secret_kind() {
+        let key = KeySource::Key(vec![0; KEY_LENGTH].into());
+        assert!(ARGON2ID_TEST.derive_key(&key, &[1; SALT_LENGTH]).is_err());
+        assert!(Kdf::RawKey.derive_key(&passphrase(), &[]).is_err());
+    }
+}
@@ -1,6 +1,41 @@
+mod aead;
 mod aes;
+mod binding;
+mod envelope;
 mod hasher;
+mod integrity;
+mod kdf;
+mod recipient;
+mod secret;
+mod signature;
 
+pub use aead::Cipher;
 pub use aes::aes256gcm_decrypt;
 pub use aes::aes256gcm_encrypt;
+pub use aes::generate_key;
+pub use binding::critical_chunks_hash;
+pub use binding::Binding;
+pub use envelope::Envelope;
+pub use envelope::SealOptions;
+pub use envelope::BOUND_ENVELOPE_VERSION;
+pub use envelope::ENVELOPE_VERSION;
 pub use hasher::sha3_hash;
+pub use integrity::sign_image;
+pub use integrity::verify_image;
+pub use integrity::ChangeKind;
+pub use integrity::ChunkChange;
+pub use integrity::ImageVerification;
+pub use integrity::INTEGRITY_CHUNK_TYPE;
+pub use kdf::Kdf;
+pub use kdf::KeySource;
+pub use kdf::SALT_LENGTH;
+pub use recipient::age_decrypt;
+pub use recipient::age_encrypt;
+pub use recipient::is_age_file;
+pub use secret::SecretBytes;
+pub use secret::SecretString;
+pub use signature::ed25519_public_key;
+pub use signature::SignatureStatus;
+pub use signature::SignedPayload;
+pub use signature::TrustedKeys;
+pub use signature::ED25519_KEY_LENGTH;
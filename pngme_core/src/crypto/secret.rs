@@ -0,0 +1,148 @@
+use std::fmt;
+use std::ops::Deref;
+
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// Secret bytes, e.g. a key or a decrypted message.
+///
+/// They are wiped from memory when dropped, and never printed by `Debug`. Build them at
+/// their final capacity: a growing `Vec` moves to a new buffer, and the old one is freed
+/// without being wiped. They are compared in constant time, but for their length.
+#[derive(Clone, Default)]
+pub struct SecretBytes(Zeroizing<Vec<u8>>);
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> SecretBytes {
+        SecretBytes(Zeroizing::new(bytes))
+    }
+
+    /// Zero-filled secret bytes, to be overwritten in place
+    pub(crate) fn zeroed(length: usize) -> SecretBytes {
+        SecretBytes::new(vec![0; length])
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.0.as_mut_slice()
+    }
+
+    /// Shortens the bytes in place, the cut bytes are wiped along with the rest on drop
+    pub(crate) fn truncate(&mut self, length: usize) {
+        self.0.truncate(length);
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        SecretBytes::new(bytes)
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(bytes: &[u8]) -> Self {
+        SecretBytes::new(bytes.to_vec())
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq for SecretBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+}
+
+impl Eq for SecretBytes {}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
+/// A secret text, e.g. a passphrase.
+///
+/// It is wiped from memory when dropped, and never printed by `Debug`. Like `SecretBytes`,
+/// build it at its final capacity and compare it in constant time.
+#[derive(Clone, Default)]
+pub struct SecretString(Zeroizing<String>);
+
+impl SecretString {
+    pub fn new(text: String) -> SecretString {
+        SecretString(Zeroizing::new(text))
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(text: String) -> Self {
+        SecretString::new(text)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(text: &str) -> Self {
+        SecretString::new(text.to_string())
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_bytes().ct_eq(other.0.as_bytes()).into()
+    }
+}
+
+impl Eq for SecretString {}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_redacted() {
+        let key = SecretBytes::new(vec![42; 32]);
+        assert_eq!(format!("{key:?}"), "SecretBytes([REDACTED; 32])");
+        assert_eq!(&*key, &[42; 32]);
+
+        let passphrase = SecretString::from("HelloWorld!");
+        assert!(!format!("{passphrase:?}").contains("HelloWorld!"));
+        assert_eq!(&*passphrase, "HelloWorld!");
+    }
+
+    #[test]
+    fn test_secret_eq() {
+        assert_eq!(
+            SecretBytes::new(vec![42; 32]),
+            SecretBytes::new(vec![42; 32])
+        );
+        assert_ne!(
+            SecretBytes::new(vec![42; 32]),
+            SecretBytes::new(vec![42; 31])
+        );
+        assert_ne!(
+            SecretBytes::new(vec![42; 32]),
+            SecretBytes::new(vec![43; 32])
+        );
+        assert_eq!(SecretString::from("Hello"), SecretString::from("Hello"));
+        assert_ne!(SecretString::from("Hello"), SecretString::from("World"));
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::{self, Display};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// Every signed payload starts with this line
+const SIGNED_MAGIC: &[u8] = b"PNGme-sig-v1\n";
+
+/// The length of Ed25519 public and private keys
+pub const ED25519_KEY_LENGTH: usize = 32;
+
+/// The length of Ed25519 signatures
+pub(crate) const SIGNATURE_LENGTH: usize = 64;
+
+/// A payload, usually a ciphertext, along with a detached Ed25519 signature.
+///
+/// It is laid out as:
+///
+/// | magic | signer public key | signature | payload |
+///
+/// The signature covers every other byte, so the signer cannot be swapped without
+/// invalidating it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedPayload {
+    public_key: [u8; ED25519_KEY_LENGTH],
+    signature: [u8; SIGNATURE_LENGTH],
+    payload: Vec<u8>,
+}
+
+impl SignedPayload {
+    /// Whether the data is a signed payload
+    pub fn is_signed(data: &[u8]) -> bool {
+        data.starts_with(SIGNED_MAGIC)
+    }
+
+    /// Signs the payload with an Ed25519 private key.
+    pub fn sign(payload: &[u8], private_key: &[u8]) -> Result<SignedPayload> {
+        let signing_key = signing_key(private_key)?;
+        let mut signed = SignedPayload {
+            public_key: signing_key.verifying_key().to_bytes(),
+            signature: [0; SIGNATURE_LENGTH],
+            payload: payload.to_vec(),
+        };
+        signed.signature = signing_key.sign(&signed.signed_bytes()).to_bytes();
+        Ok(signed)
+    }
+
+    /// Checks the signature, then whether the signer is one of the trusted keys.
+    pub fn verify(&self, trusted_keys: &TrustedKeys) -> SignatureStatus {
+        check_signature(
+            &self.public_key,
+            &self.signed_bytes(),
+            &self.signature,
+            trusted_keys,
+        )
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// The short identifier of the signer public key
+    pub fn key_id(&self) -> String {
+        key_id(&self.public_key)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = SIGNED_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.public_key);
+        bytes.extend_from_slice(&self.signature);
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Returns the bytes covered by the signature: all of them but the signature itself.
+    fn signed_bytes(&self) -> Vec<u8> {
+        [SIGNED_MAGIC, &self.public_key, &self.payload].concat()
+    }
+}
+
+impl TryFrom<&[u8]> for SignedPayload {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let rest = bytes
+            .strip_prefix(SIGNED_MAGIC)
+            .ok_or_else(|| Error::InvalidEnvelope("not a signed message".to_string()))?;
+        if rest.len() < ED25519_KEY_LENGTH + SIGNATURE_LENGTH {
+            return Err(Error::InvalidEnvelope("truncated signature".to_string()));
+        }
+        let (public_key, rest) = rest.split_at(ED25519_KEY_LENGTH);
+        let (signature, payload) = rest.split_at(SIGNATURE_LENGTH);
+        Ok(Self {
+            public_key: public_key.try_into().expect("Checked length"),
+            signature: signature.try_into().expect("Checked length"),
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// The outcome of checking the signature of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Signed by the trusted key with this name
+    Valid(String),
+    /// Correctly signed, by a key with this ID that is not trusted
+    UnknownSigner(String),
+    /// The message or its signature has been tampered with
+    Invalid,
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Valid(name) => write!(f, "valid, signed by {name}"),
+            SignatureStatus::UnknownSigner(key_id) => write!(f, "unknown signer {key_id}"),
+            SignatureStatus::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+/// The named Ed25519 public keys whose signatures are trusted
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys {
+    keys: Vec<(String, [u8; ED25519_KEY_LENGTH])>,
+}
+
+impl TrustedKeys {
+    pub fn new() -> TrustedKeys {
+        TrustedKeys::default()
+    }
+
+    /// Trusts a public key. Without a name, the key is named after its ID.
+    pub fn add(&mut self, public_key: &[u8], name: Option<&str>) -> Result<()> {
+        let public_key: [u8; ED25519_KEY_LENGTH] = public_key
+            .try_into()
+            .map_err(|_| Error::InvalidPublicKey("expected 32 bytes".to_string()))?;
+        VerifyingKey::from_bytes(&public_key)
+            .map_err(|_| Error::InvalidPublicKey("not an Ed25519 key".to_string()))?;
+        let name = name.map_or_else(|| key_id(&public_key), str::to_string);
+        self.keys.push((name, public_key));
+        Ok(())
+    }
+
+    fn name_of(&self, public_key: &[u8]) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|(_, key)| key == public_key)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Checks an Ed25519 signature of the message, then whether the signer is trusted.
+pub(crate) fn check_signature(
+    public_key: &[u8; ED25519_KEY_LENGTH],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_LENGTH],
+    trusted_keys: &TrustedKeys,
+) -> SignatureStatus {
+    let valid = VerifyingKey::from_bytes(public_key)
+        .and_then(|key| key.verify_strict(message, &Signature::from_bytes(signature)))
+        .is_ok();
+    if !valid {
+        return SignatureStatus::Invalid;
+    }
+    match trusted_keys.name_of(public_key) {
+        Some(name) => SignatureStatus::Valid(name.to_string()),
+        None => SignatureStatus::UnknownSigner(key_id(public_key)),
+    }
+}
+
+/// Returns the Ed25519 public key of a private key.
+pub fn ed25519_public_key(private_key: &[u8]) -> Result<Vec<u8>> {
+    Ok(signing_key(private_key)?
+        .verifying_key()
+        .to_bytes()
+        .to_vec())
+}
+
+pub(crate) fn signing_key(private_key: &[u8]) -> Result<SigningKey> {
+    let private_key: [u8; ED25519_KEY_LENGTH] = private_key
+        .try_into()
+        .map_err(|_| Error::InvalidSigningKey)?;
+    Ok(SigningKey::from_bytes(&private_key))
+}
+
+/// The first 8 bytes of the SHA-256 hash of the public key, hex-encoded
+fn key_id(public_key: &[u8]) -> String {
+    Sha256::digest(public_key)[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: [u8; 32] = [1; 32];
+    const MALLORY: [u8; 32] = [2; 32];
+
+    fn trusting_alice() -> Result<TrustedKeys> {
+        let mut trusted_keys = TrustedKeys::new();
+        trusted_keys.add(&ed25519_public_key(&ALICE)?, Some("alice"))?;
+        Ok(trusted_keys)
+    }
+
+    #[test]
+    fn test_signed_payload_roundtrip() -> Result<()> {
+        let signed = SignedPayload::sign(b"ciphertext", &ALICE)?;
+        let bytes = signed.as_bytes();
+        assert!(SignedPayload::is_signed(&bytes));
+        assert_eq!(bytes.len(), SIGNED_MAGIC.len() + 32 + 64 + 10);
+
+        let parsed = SignedPayload::try_from(bytes.as_slice())?;
+        assert_eq!(parsed, signed);
+        assert_eq!(parsed.payload(), b"ciphertext");
+        assert_eq!(parsed.public_key(), ed25519_public_key(&ALICE)?);
+        assert_eq!(
+            parsed.verify(&trusting_alice()?),
+            SignatureStatus::Valid("alice".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_unknown_signer() -> Result<()> {
+        let signed = SignedPayload::sign(b"ciphertext", &MALLORY)?;
+        assert_eq!(
+            signed.verify(&trusting_alice()?),
+            SignatureStatus::UnknownSigner(signed.key_id())
+        );
+        assert_eq!(signed.key_id().len(), 16);
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_tampered() -> Result<()> {
+        let bytes = SignedPayload::sign(b"ciphertext", &ALICE)?.as_bytes();
+
+        let mut tampered_payload = bytes.clone();
+        *tampered_payload.last_mut().unwrap() ^= 1;
+        let signed = SignedPayload::try_from(tampered_payload.as_slice())?;
+        assert_eq!(signed.verify(&trusting_alice()?), SignatureStatus::Invalid);
+
+        // Claiming that a trusted key signed it
+        let mut swapped_signer = bytes.clone();
+        swapped_signer[SIGNED_MAGIC.len()..SIGNED_MAGIC.len() + 32]
+            .copy_from_slice(&ed25519_public_key(&MALLORY)?);
+        let signed = SignedPayload::try_from(swapped_signer.as_slice())?;
+        assert_eq!(signed.verify(&trusting_alice()?), SignatureStatus::Invalid);
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed_payload_invalid() {
+        assert!(SignedPayload::try_from(&b"ciphertext"[..]).is_err());
+        assert!(SignedPayload::try_from(SIGNED_MAGIC).is_err());
+        assert!(matches!(
+            SignedPayload::sign(b"ciphertext", &[0; 16]),
+            Err(Error::InvalidSigningKey)
+        ));
+        assert!(TrustedKeys::new().add(&[0; 16], None).is_err());
+    }
+}
@@ -10,15 +10,65 @@ pub enum Error {
     #[error("Invalid byte value, only [a-zA-Z]")]
     InvalidByteValue,
 
+    #[deprecated(note = "PNG signature errors are reported as `Error::Malformed`")]
     #[error("Invalid header byte: Only PNG header byte is allowed")]
     InvalidHeaderByte,
 
     #[error("Could not convert a vector of bytes to string")]
     StringConversion,
 
+    #[deprecated(note = "CRC mismatches are reported as `Error::Malformed`")]
     #[error("Invalid CRC")]
     InvalidCRC,
 
+    #[error("Malformed PNG at byte {offset} (chunk #{chunk_index}): expected {expected}")]
+    Malformed {
+        offset: u64,
+        chunk_index: usize,
+        expected: String,
+    },
+
+    #[error("PNG parsing limit exceeded at byte {offset} (chunk #{chunk_index}): {limit}")]
+    LimitExceeded {
+        offset: u64,
+        chunk_index: usize,
+        limit: String,
+    },
+
+    #[error("Invalid IHDR chunk: {0}")]
+    InvalidIhdr(String),
+
+    #[error("Invalid PLTE chunk: {0}")]
+    InvalidPlte(String),
+
+    #[error("Invalid PNG structure: {0}")]
+    InvalidStructure(String),
+
+    #[error("Invalid text chunk: {0}")]
+    InvalidText(String),
+
+    #[error("Invalid image data: {0}")]
+    InvalidImageData(String),
+
+    #[error("The image can hide at most {available} bytes, {needed} bytes are needed")]
+    InsufficientCapacity { needed: usize, available: usize },
+
+    #[error("Could not hide data in the pixels of this image: {0}")]
+    UnsupportedImage(String),
+
+    #[error("Could not compress data")]
+    CompressionErr(#[source] io::Error),
+
+    #[error("Could not decompress data")]
+    DecompressionErr(#[source] io::Error),
+
+    #[error("Decompressed data is larger than {0} bytes")]
+    DecompressedTooLarge(usize),
+
+    #[error("This build does not support {0} compression")]
+    UnsupportedCompression(&'static str),
+
+    #[deprecated(note = "Chunk types can be repeated, chunks are addressed by index")]
     #[error("The Chunk Type is already existed")]
     DuplicatedChunkType,
 
@@ -31,9 +81,15 @@ pub enum Error {
     #[error("Could not find the chunk type")]
     NotFoundChunkType,
 
+    #[error("Invalid chunk index: {0}")]
+    InvalidChunkIndex(usize),
+
     #[error("Could not read file: {0}")]
     FileOpenErr(#[source] io::Error),
 
+    #[error("Could not write file: {0}")]
+    FileWriteErr(#[source] io::Error),
+
     #[error("The URL should link to a PNG file")]
     InvalidPNGURL(String),
 
@@ -43,6 +99,39 @@ pub enum Error {
     #[error("Invalid key for AES256")]
     InvalidKey,
 
+    #[error("Invalid encrypted message: {0}")]
+    InvalidEnvelope(String),
+
+    #[error("Invalid recipient: {0}")]
+    InvalidRecipient(String),
+
+    #[error("Invalid identity file: {0}")]
+    InvalidIdentity(String),
+
+    #[error("Invalid signing key: an Ed25519 private key should be 32-byte long")]
+    InvalidSigningKey,
+
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+
+    #[error("Invalid image signature chunk: {0}")]
+    InvalidIntegrityChunk(String),
+
+    #[error("Invalid split message: {0}")]
+    InvalidSplit(String),
+
+    #[error("Invalid hidden data: {0}")]
+    InvalidPayload(String),
+
+    #[error("The message is bound to {0}, which is not known")]
+    MissingBinding(String),
+
+    #[error("Could not bind the message: {0}")]
+    UnsupportedBinding(String),
+
+    #[error("Could not derive a key from your passphrase")]
+    KeyDerivationErr,
+
     #[error("Could not encrypt your data chunk")]
     EncryptionErr,
 
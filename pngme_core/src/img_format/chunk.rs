@@ -1,7 +1,7 @@
 use crate::{Error, Result};
 use std::fmt::Display;
-use std::io::{BufReader, Read};
 
+use crate::img_format::ChunkRef;
 use crate::img_format::ChunkType;
 use crate::img_format::DisplayableVec;
 use crc::{Crc, CRC_32_ISO_HDLC};
@@ -26,6 +26,16 @@ impl Chunk {
         }
     }
 
+    /// Creates a chunk from its already-split parts.
+    /// The given CRC must have been checked against the chunk type and data.
+    pub(crate) fn from_parts(chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Chunk {
+        Self {
+            chunk_type,
+            chunk_data: DisplayableVec(data),
+            crc,
+        }
+    }
+
     /// The length of the data portion of this chunk.
     pub fn length(&self) -> u32 {
         self.data().len() as u32
@@ -79,63 +89,22 @@ impl Chunk {
     }
 }
 
+/// The CRC algorithm used by the PNG spec
+pub(crate) const PNG_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
 /// Compute CRC from Chunk Type and Chunk Data
-fn compute_crc(chunk_type: &[u8], chunk_data: &[u8]) -> u32 {
-    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-    let bytes_vec: Vec<u8> = chunk_type
-        .iter()
-        .chain(chunk_data.iter())
-        .copied()
-        .collect();
-    crc.checksum(&bytes_vec)
+pub(crate) fn compute_crc(chunk_type: &[u8], chunk_data: &[u8]) -> u32 {
+    let mut digest = PNG_CRC.digest();
+    digest.update(chunk_type);
+    digest.update(chunk_data);
+    digest.finalize()
 }
 
 impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
-        let data: Vec<u8> = value.to_vec();
-        if data.len() < 4 {
-            return Err(Error::InvalidLength(
-                "Data is less than 4 bytes".to_string(),
-            ));
-        }
-
-        // first 4 bytes for the length
-        // the second 4 bytes for the chunk type
-        // the following for chunk data
-        // the last 4 bytes for CRC
-        let mut reader = BufReader::new(value);
-
-        let mut length_bytes: [u8; 4] = [0; 4];
-        reader
-            .read_exact(&mut length_bytes)
-            .map_err(Error::BufferReaderErr)?;
-        let length = u32::from_be_bytes(length_bytes);
-
-        let chunk_type_slice = value.get(4..8).expect("invalid length byte");
-        let chunk_type = ChunkType::try_from(chunk_type_slice)?;
-
-        let data_crc_border: usize = (8 + length) as usize;
-        let chunk_data_slice = value.get(8..data_crc_border).expect("invalid length byte");
-        let chunk_data = DisplayableVec::new(chunk_data_slice);
-
-        let crc = value
-            .get(data_crc_border..(data_crc_border + 4))
-            .expect("invalid length byte");
-        let mut crc_byte: [u8; 4] = [0; 4];
-        crc_byte.copy_from_slice(crc);
-        let crc: u32 = u32::from_be_bytes(crc_byte);
-
-        if crc != compute_crc(chunk_type_slice, chunk_data_slice) {
-            return Err(Error::InvalidCRC);
-        }
-
-        Ok(Self {
-            chunk_type,
-            chunk_data,
-            crc,
-        })
+        ChunkRef::try_from(value).map(Chunk::from)
     }
 }
 
@@ -0,0 +1,176 @@
+use crate::{Error, Result};
+use std::fmt::Display;
+
+use crate::img_format::displayable_vec::fmt_bytes;
+use crate::img_format::parse::{check_crc, malformed, parse_chunk_type};
+use crate::img_format::{Chunk, ChunkType, ParseLimits};
+
+/// A read-only view over a chunk stored in a borrowed byte slice.
+/// Unlike `Chunk`, the chunk data is never copied.
+#[derive(Clone, Copy)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    chunk_data: &'a [u8],
+    crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// The length of the data portion of this chunk.
+    pub fn length(&self) -> u32 {
+        self.chunk_data.len() as u32
+    }
+
+    /// The `ChunkType` of this chunk
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    /// The raw data contained in this chunk in bytes
+    pub fn data(&self) -> &'a [u8] {
+        self.chunk_data
+    }
+
+    /// The CRC of this chunk
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// The number of bytes this chunk takes in the PNG datastream:
+    /// length, chunk type, data and CRC.
+    pub fn total_length(&self) -> usize {
+        self.chunk_data.len() + 12
+    }
+
+    /// Copies this chunk into an owned `Chunk`.
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::from_parts(self.chunk_type, self.chunk_data.to_vec(), self.crc)
+    }
+
+    /// Borrows the chunk at the start of `bytes`, which begin at `offset` in the
+    /// datastream and hold the chunk numbered `chunk_index`.
+    pub(crate) fn parse(
+        bytes: &'a [u8],
+        offset: u64,
+        chunk_index: usize,
+        limits: &ParseLimits,
+    ) -> Result<Self> {
+        // first 4 bytes for the length
+        // the second 4 bytes for the chunk type
+        // the following for chunk data
+        // the last 4 bytes for CRC
+        let length_bytes: [u8; 4] = bytes
+            .get(..4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| malformed(offset, chunk_index, "4-byte chunk length"))?;
+        let length = u32::from_be_bytes(length_bytes);
+        limits.check_chunk(offset, chunk_index, length)?;
+
+        let chunk_type_bytes: [u8; 4] = bytes
+            .get(4..8)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| malformed(offset + 4, chunk_index, "4-byte chunk type"))?;
+        let chunk_type = parse_chunk_type(offset + 4, chunk_index, chunk_type_bytes)?;
+
+        // `check_chunk` guarantees that the length is below 2^31
+        let data_crc_border = 8 + length as usize;
+        let chunk_data = bytes.get(8..data_crc_border).ok_or_else(|| {
+            malformed(
+                offset + 8,
+                chunk_index,
+                format!("{length} bytes of chunk data"),
+            )
+        })?;
+
+        let crc_offset = offset + data_crc_border as u64;
+        let crc_bytes: [u8; 4] = bytes
+            .get(data_crc_border..)
+            .and_then(|b| b.get(..4))
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| malformed(crc_offset, chunk_index, "4-byte CRC"))?;
+        let crc = u32::from_be_bytes(crc_bytes);
+        check_crc(crc_offset, chunk_index, &chunk_type, chunk_data, crc)?;
+
+        Ok(Self {
+            chunk_type,
+            chunk_data,
+            crc,
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = Error;
+
+    /// Borrows the chunk at the start of the given bytes.
+    /// Any bytes following the chunk are ignored.
+    fn try_from(value: &'a [u8]) -> std::result::Result<Self, Self::Error> {
+        ChunkRef::parse(value, 0, 0, &ParseLimits::default())
+    }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(value: ChunkRef<'_>) -> Self {
+        value.to_chunk()
+    }
+}
+
+impl Display for ChunkRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = self
+            .length()
+            .to_be_bytes()
+            .into_iter()
+            .chain(self.chunk_type.as_bytes())
+            .chain(self.chunk_data.iter().copied())
+            .chain(self.crc.to_be_bytes());
+        fmt_bytes(f, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_chunk() -> Chunk {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        Chunk::new(chunk_type, b"This is where your secret message will be!")
+    }
+
+    #[test]
+    fn test_chunk_ref_from_bytes() -> Result<()> {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        let chunk_ref = ChunkRef::try_from(bytes.as_slice())?;
+
+        assert_eq!(chunk_ref.length(), 42);
+        assert_eq!(chunk_ref.chunk_type(), chunk.chunk_type());
+        assert_eq!(chunk_ref.data(), chunk.data());
+        assert_eq!(chunk_ref.crc(), chunk.crc());
+        assert_eq!(chunk_ref.total_length(), bytes.len());
+        // The chunk data is borrowed from the input bytes
+        assert_eq!(chunk_ref.data().as_ptr(), bytes[8..].as_ptr());
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_chunk_ref() {
+        let bytes = testing_chunk().as_bytes();
+        for (end, offset) in [(0, 0), (3, 0), (7, 4), (20, 8), (bytes.len() - 1, 50)] {
+            let result = ChunkRef::try_from(&bytes[..end]);
+            assert!(
+                matches!(result, Err(Error::Malformed { offset: o, .. }) if o == offset),
+                "truncated at {end}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunk_ref_display() -> Result<()> {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        let chunk_ref = ChunkRef::try_from(bytes.as_slice())?;
+        assert_eq!(chunk_ref.to_string(), chunk.to_string());
+        Ok(())
+    }
+}
@@ -71,10 +71,10 @@ impl TryFrom<&[u8]> for ChunkType {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        are_valid_chars(&value.iter())?;
-        let mut bytes: [u8; 4] = [0; 4];
-        bytes.copy_from_slice(value);
-        Ok(Self { bytes })
+        let bytes: [u8; 4] = value.try_into().map_err(|_| {
+            Error::InvalidLength("The chunk type must be exactly 4-byte long".to_string())
+        })?;
+        ChunkType::try_from(bytes)
     }
 }
 
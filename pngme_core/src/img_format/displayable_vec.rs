@@ -11,12 +11,26 @@ impl DisplayableVec {
 
 impl Display for DisplayableVec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for bytes in self.0.chunks(4) {
-            let s: Vec<String> = bytes.iter().map(|&b| b.to_string()).collect();
-            let s = s.join(" ");
+        fmt_bytes(f, self.0.iter().copied())
+    }
+}
 
-            writeln!(f, "{}", s)?;
+/// Writes the given bytes as decimal numbers, 4 bytes per line.
+pub(crate) fn fmt_bytes(
+    f: &mut std::fmt::Formatter<'_>,
+    bytes: impl Iterator<Item = u8>,
+) -> std::fmt::Result {
+    let mut count = 0;
+    for byte in bytes {
+        match count % 4 {
+            0 => write!(f, "{}", byte)?,
+            3 => writeln!(f, " {}", byte)?,
+            _ => write!(f, " {}", byte)?,
         }
-        Ok(())
+        count += 1;
+    }
+    if count % 4 != 0 {
+        writeln!(f)?;
     }
+    Ok(())
 }
@@ -0,0 +1,178 @@
+use crate::Error;
+
+/// The per-scanline filter types of the PNG spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+const FILTER_TYPES: [FilterType; 5] = [
+    FilterType::None,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Average,
+    FilterType::Paeth,
+];
+
+impl TryFrom<u8> for FilterType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err(Error::InvalidImageData(format!(
+                "unknown filter type {value}"
+            ))),
+        }
+    }
+}
+
+/// Reverses the filter of a scanline in place.
+///
+/// `previous` is the already unfiltered previous scanline (all zeros for the first one),
+/// `bpp` the number of bytes per complete pixel, rounded up to 1.
+pub(crate) fn unfilter(filter_type: FilterType, row: &mut [u8], previous: &[u8], bpp: usize) {
+    match filter_type {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        FilterType::Up => {
+            for (byte, up) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(*up);
+            }
+        }
+        FilterType::Average => {
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] as u16 } else { 0 };
+                let average = ((left + previous[i] as u16) / 2) as u8;
+                row[i] = row[i].wrapping_add(average);
+            }
+        }
+        FilterType::Paeth => {
+            for i in 0..row.len() {
+                let (left, upper_left) = if i >= bpp {
+                    (row[i - bpp], previous[i - bpp])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth_predictor(left, previous[i], upper_left));
+            }
+        }
+    }
+}
+
+/// Applies the given filter to a scanline, writing the filtered bytes into `output`.
+pub(crate) fn filter(
+    filter_type: FilterType,
+    row: &[u8],
+    previous: &[u8],
+    bpp: usize,
+    output: &mut [u8],
+) {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predictor = match filter_type {
+            FilterType::None => 0,
+            FilterType::Sub => left,
+            FilterType::Up => previous[i],
+            FilterType::Average => ((left as u16 + previous[i] as u16) / 2) as u8,
+            FilterType::Paeth => paeth_predictor(left, previous[i], upper_left),
+        };
+        output[i] = row[i].wrapping_sub(predictor);
+    }
+}
+
+/// Picks the filter of a scanline with the usual heuristic: the filter minimizing the sum
+/// of the filtered bytes, taken as signed differences.
+pub(crate) fn select_filter(row: &[u8], previous: &[u8], bpp: usize) -> FilterType {
+    let mut output = vec![0; row.len()];
+    FILTER_TYPES
+        .into_iter()
+        .min_by_key(|&filter_type| {
+            filter(filter_type, row, previous, bpp, &mut output);
+            output
+                .iter()
+                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                .sum::<u64>()
+        })
+        .unwrap_or(FilterType::None)
+}
+
+/// Picks the neighbour (left, above or upper left) closest to `left + above - upper_left`.
+fn paeth_predictor(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_above = (estimate - above as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+    if distance_left <= distance_above && distance_left <= distance_upper_left {
+        left
+    } else if distance_above <= distance_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_roundtrip() {
+        let previous = [10, 200, 30, 255, 0, 17];
+        let row = [12, 180, 90, 1, 250, 17];
+        for filter_type in FILTER_TYPES {
+            for bpp in [1, 3] {
+                let mut filtered = [0; 6];
+                filter(filter_type, &row, &previous, bpp, &mut filtered);
+                unfilter(filter_type, &mut filtered, &previous, bpp);
+                assert_eq!(filtered, row, "{filter_type:?} with bpp {bpp}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_filter() {
+        // A horizontal gradient is best predicted from the left
+        let row: Vec<u8> = (0..16).map(|i| i * 10).collect();
+        assert_eq!(select_filter(&row, &[0; 16], 1), FilterType::Sub);
+        // A row repeating the previous one is best predicted from above
+        assert_eq!(
+            select_filter(&[9, 200, 3, 77], &[9, 200, 3, 77], 1),
+            FilterType::Up
+        );
+        assert_eq!(select_filter(&[0; 4], &[0; 4], 1), FilterType::None);
+    }
+
+    #[test]
+    fn test_sub_filter() {
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Sub, &mut row, &[0; 4], 1);
+        assert_eq!(row, [1, 3, 6, 10]);
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth_predictor(10, 20, 10), 20);
+        assert_eq!(paeth_predictor(20, 10, 10), 20);
+        assert_eq!(paeth_predictor(10, 10, 20), 10);
+    }
+
+    #[test]
+    fn test_filter_type_from_byte() {
+        assert_eq!(FilterType::try_from(4).unwrap(), FilterType::Paeth);
+        assert!(FilterType::try_from(5).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{Error, Result};
+
+use super::filter::{filter, select_filter, unfilter, FilterType};
+use super::pixel_buffer::row_length;
+use super::zlib::{deflate, inflate};
+use crate::img_format::{ColorType, Ihdr, Interlace, PixelBuffer};
+
+/// The Adam7 passes: (first column, first row, column step, row step)
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// A reduced image of an Adam7 pass, or the whole image when it is not interlaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Pass {
+    pub(crate) x0: u32,
+    pub(crate) y0: u32,
+    pub(crate) dx: u32,
+    pub(crate) dy: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// Returns the non-empty passes of the image, in the order they are stored.
+pub(crate) fn passes(ihdr: &Ihdr) -> Vec<Pass> {
+    match ihdr.interlace() {
+        Interlace::None => vec![Pass {
+            x0: 0,
+            y0: 0,
+            dx: 1,
+            dy: 1,
+            width: ihdr.width(),
+            height: ihdr.height(),
+        }],
+        Interlace::Adam7 => ADAM7_PASSES
+            .iter()
+            .map(|&(x0, y0, dx, dy)| Pass {
+                x0,
+                y0,
+                dx,
+                dy,
+                width: reduced_length(ihdr.width(), x0, dx),
+                height: reduced_length(ihdr.height(), y0, dy),
+            })
+            // Empty passes have no scanline at all, not even a filter byte
+            .filter(|pass| pass.width > 0 && pass.height > 0)
+            .collect(),
+    }
+}
+
+/// The number of pixels of a pass along one dimension
+fn reduced_length(length: u32, start: u32, step: u32) -> u32 {
+    if start >= length {
+        0
+    } else {
+        (length - start).div_ceil(step)
+    }
+}
+
+/// The length of the filtered, uncompressed image data.
+pub(crate) fn filtered_length(ihdr: &Ihdr) -> Result<usize> {
+    passes(ihdr)
+        .iter()
+        .try_fold(0usize, |total, pass| {
+            row_length(pass.width, ihdr.bit_depth(), ihdr.color_type())
+                .checked_add(1)?
+                .checked_mul(pass.height as usize)?
+                .checked_add(total)
+        })
+        .ok_or(Error::InvalidImageData("image is too large".to_string()))
+}
+
+/// Decompresses, unfilters and deinterlaces the concatenated data of the IDAT chunks.
+pub(crate) fn decode(ihdr: &Ihdr, compressed: &[u8]) -> Result<PixelBuffer> {
+    let expected = filtered_length(ihdr)?;
+    let filtered = inflate(compressed, expected)?;
+    if filtered.len() < expected {
+        return Err(Error::InvalidImageData(format!(
+            "expected {expected} bytes of filtered data, found {}",
+            filtered.len()
+        )));
+    }
+
+    let bits_per_pixel = ihdr.bits_per_pixel();
+    // Filters work on whole bytes, sub-byte pixels are compared with the previous byte
+    let bpp = (bits_per_pixel / 8).max(1);
+    let stride = row_length(ihdr.width(), ihdr.bit_depth(), ihdr.color_type());
+    let mut data = vec![0; stride * ihdr.height() as usize];
+    let mut scanlines = filtered.as_slice();
+
+    for pass in passes(ihdr) {
+        let length = row_length(pass.width, ihdr.bit_depth(), ihdr.color_type());
+        let mut previous = vec![0; length];
+        let mut row = vec![0; length];
+        for y in 0..pass.height {
+            // The length has been checked above
+            let (scanline, rest) = scanlines.split_at(1 + length);
+            scanlines = rest;
+            row.copy_from_slice(&scanline[1..]);
+            unfilter(FilterType::try_from(scanline[0])?, &mut row, &previous, bpp);
+
+            let start = (pass.y0 + y * pass.dy) as usize * stride;
+            let target = &mut data[start..start + stride];
+            if pass.dx == 1 {
+                target.copy_from_slice(&row);
+            } else {
+                for x in 0..pass.width {
+                    let target_x = pass.x0 + x * pass.dx;
+                    copy_pixel(&row, x as usize, target, target_x as usize, bits_per_pixel);
+                }
+            }
+            std::mem::swap(&mut row, &mut previous);
+        }
+    }
+
+    PixelBuffer::new(
+        ihdr.width(),
+        ihdr.height(),
+        ihdr.bit_depth(),
+        ihdr.color_type(),
+        data,
+    )
+}
+
+/// Interlaces, filters and compresses the pixels into the data of the IDAT chunks.
+/// The pixels must have the dimensions, bit depth and color type of the header.
+pub(crate) fn encode(ihdr: &Ihdr, pixels: &PixelBuffer) -> Result<Vec<u8>> {
+    let bits_per_pixel = ihdr.bits_per_pixel();
+    let bpp = (bits_per_pixel / 8).max(1);
+    // Filtering rarely pays off for palette indices and packed samples
+    let adaptive = ihdr.color_type() != ColorType::Indexed && ihdr.bit_depth() >= 8;
+    let stride = pixels.stride();
+    let mut filtered = Vec::with_capacity(filtered_length(ihdr)?);
+
+    for pass in passes(ihdr) {
+        let length = row_length(pass.width, ihdr.bit_depth(), ihdr.color_type());
+        let mut previous = vec![0; length];
+        let mut row = vec![0; length];
+        for y in 0..pass.height {
+            let start = (pass.y0 + y * pass.dy) as usize * stride;
+            let source = &pixels.data()[start..start + stride];
+            if pass.dx == 1 {
+                row.copy_from_slice(source);
+            } else {
+                // Keep the padding bits of the reduced row at zero
+                row.fill(0);
+                for x in 0..pass.width {
+                    let source_x = pass.x0 + x * pass.dx;
+                    copy_pixel(
+                        source,
+                        source_x as usize,
+                        &mut row,
+                        x as usize,
+                        bits_per_pixel,
+                    );
+                }
+            }
+
+            let filter_type = if adaptive {
+                select_filter(&row, &previous, bpp)
+            } else {
+                FilterType::None
+            };
+            filtered.push(filter_type as u8);
+            let start = filtered.len();
+            filtered.resize(start + length, 0);
+            filter(filter_type, &row, &previous, bpp, &mut filtered[start..]);
+            std::mem::swap(&mut row, &mut previous);
+        }
+    }
+
+    deflate(&filtered)
+}
+
+/// Copies the pixel at column `source_x` of a row into column `target_x` of another row.
+pub(crate) fn copy_pixel(
+    source: &[u8],
+    source_x: usize,
+    target: &mut [u8],
+    target_x: usize,
+    bits_per_pixel: usize,
+) {
+    if bits_per_pixel >= 8 {
+        let size = bits_per_pixel / 8;
+        target[target_x * size..(target_x + 1) * size]
+            .copy_from_slice(&source[source_x * size..(source_x + 1) * size]);
+    } else {
+        // Pixels below 8 bits are packed from the most significant bit
+        let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+        let source_bit = source_x * bits_per_pixel;
+        let source_shift = 8 - bits_per_pixel - source_bit % 8;
+        let value = (source[source_bit / 8] >> source_shift) & mask;
+        let target_bit = target_x * bits_per_pixel;
+        let target_shift = 8 - bits_per_pixel - target_bit % 8;
+        let byte = &mut target[target_bit / 8];
+        *byte = (*byte & !(mask << target_shift)) | (value << target_shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adam7_passes() -> Result<()> {
+        let ihdr = Ihdr::new(3, 3, 8, ColorType::Grayscale, Interlace::Adam7)?;
+        let sizes: Vec<(u32, u32)> = passes(&ihdr)
+            .iter()
+            .map(|pass| (pass.width, pass.height))
+            .collect();
+        // Passes 2 and 3 start outside of a 3x3 image
+        assert_eq!(sizes, [(1, 1), (1, 1), (2, 1), (1, 2), (3, 1)]);
+        assert_eq!(filtered_length(&ihdr)?, 2 + 2 + 3 + 2 * 2 + 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_filtered_rows() -> Result<()> {
+        let ihdr = Ihdr::new(2, 3, 8, ColorType::Rgb, Interlace::None)?;
+        #[rustfmt::skip]
+        let filtered = [
+            1, 10, 20, 30, 5, 5, 5,
+            2, 1, 1, 1, 1, 1, 1,
+            4, 0, 0, 0, 0, 0, 0,
+        ];
+        let pixels = decode(&ihdr, &deflate(&filtered)?)?;
+        #[rustfmt::skip]
+        let expected = [
+            10, 20, 30, 15, 25, 35,
+            11, 21, 31, 16, 26, 36,
+            11, 21, 31, 16, 26, 36,
+        ];
+        assert_eq!(pixels.data(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_adam7() -> Result<()> {
+        // Each pixel holds its own position: 3 * y + x
+        let ihdr = Ihdr::new(3, 3, 8, ColorType::Grayscale, Interlace::Adam7)?;
+        let filtered = [0, 0, 0, 2, 0, 6, 8, 0, 1, 0, 7, 0, 3, 4, 5];
+        let pixels = decode(&ihdr, &deflate(&filtered)?)?;
+        assert_eq!(pixels.data(), [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_adam7_below_8_bit() -> Result<()> {
+        // A 9x2 1-bit image with an arbitrary pattern
+        let ihdr = Ihdr::new(9, 2, 1, ColorType::Grayscale, Interlace::Adam7)?;
+        let rows: [[u8; 9]; 2] = [[1, 0, 1, 1, 0, 0, 1, 0, 1], [0, 1, 1, 0, 1, 0, 0, 1, 1]];
+        let mut filtered = Vec::new();
+        for pass in passes(&ihdr) {
+            for y in 0..pass.height {
+                let mut scanline = vec![0; 1 + row_length(pass.width, 1, ColorType::Grayscale)];
+                for x in 0..pass.width {
+                    let bit =
+                        rows[(pass.y0 + y * pass.dy) as usize][(pass.x0 + x * pass.dx) as usize];
+                    scanline[1 + x as usize / 8] |= bit << (7 - x % 8);
+                }
+                filtered.extend(scanline);
+            }
+        }
+
+        let pixels = decode(&ihdr, &deflate(&filtered)?)?;
+        assert_eq!(
+            pixels.data(),
+            [0b1011_0010, 0b1000_0000, 0b0110_1001, 0b1000_0000]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_invalid_data() -> Result<()> {
+        let ihdr = Ihdr::new(2, 1, 8, ColorType::Grayscale, Interlace::None)?;
+        // Truncated
+        assert!(decode(&ihdr, &deflate(&[0, 1])?).is_err());
+        // Too long
+        assert!(decode(&ihdr, &deflate(&[0, 1, 2, 3])?).is_err());
+        // Unknown filter type
+        assert!(decode(&ihdr, &deflate(&[5, 1, 2])?).is_err());
+        Ok(())
+    }
+
+    /// Deterministic pseudo-random bytes
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state: u32 = 0x2545_f491;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_roundtrip() -> Result<()> {
+        let color_types = [
+            ColorType::Grayscale,
+            ColorType::Rgb,
+            ColorType::Indexed,
+            ColorType::GrayscaleAlpha,
+            ColorType::Rgba,
+        ];
+        for color_type in color_types {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                for interlace in [Interlace::None, Interlace::Adam7] {
+                    let ihdr = Ihdr::new(13, 7, bit_depth, color_type, interlace)?;
+                    let stride = row_length(13, bit_depth, color_type);
+                    let mut data = noise(stride * 7);
+                    // Smooth rows give the adaptive filters something to predict
+                    data[..stride].fill(42);
+                    let pixels = PixelBuffer::new(13, 7, bit_depth, color_type, data)?;
+
+                    let decoded = decode(&ihdr, &encode(&ihdr, &pixels)?)?;
+                    if bit_depth >= 8 || interlace == Interlace::None {
+                        assert_eq!(decoded, pixels, "{color_type} {bit_depth} {interlace:?}");
+                    } else {
+                        // Adam7 does not carry the padding bits at the end of the rows
+                        for index in 0..pixels.sample_count() {
+                            assert_eq!(decoded.sample(index), pixels.sample(index));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_selects_filters() -> Result<()> {
+        let ihdr = Ihdr::new(16, 2, 8, ColorType::Grayscale, Interlace::None)?;
+        let gradient: Vec<u8> = (0..32).map(|i| (i % 16) * 10).collect();
+        let pixels = PixelBuffer::new(16, 2, 8, ColorType::Grayscale, gradient)?;
+        let filtered = inflate(&encode(&ihdr, &pixels)?, 34)?;
+        // Sub for the first row, Up for the identical second one
+        assert_eq!((filtered[0], filtered[17]), (1, 2));
+
+        let ihdr = Ihdr::new(16, 2, 8, ColorType::Indexed, Interlace::None)?;
+        let pixels = PixelBuffer::new(16, 2, 8, ColorType::Indexed, pixels.data().to_vec())?;
+        let filtered = inflate(&encode(&ihdr, &pixels)?, 34)?;
+        assert_eq!((filtered[0], filtered[17]), (0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_pixel() {
+        let source = [0b1011_0000];
+        let mut target = [0b0000_1111];
+        copy_pixel(&source, 1, &mut target, 2, 2);
+        assert_eq!(target, [0b0000_1111]);
+        copy_pixel(&source, 0, &mut target, 3, 2);
+        assert_eq!(target, [0b0000_1110]);
+
+        let mut target = [0; 6];
+        copy_pixel(&[1, 2, 3, 4, 5, 6], 1, &mut target, 0, 24);
+        assert_eq!(target, [4, 5, 6, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{Error, Result};
+use std::fmt::Display;
+
+use crate::img_format::{Chunk, ChunkType};
+
+/// The chunk type of the image header
+pub(crate) const IHDR: [u8; 4] = *b"IHDR";
+
+/// The color type of a PNG image, as described in the PNG spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// The number of samples per pixel
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// The bit depths allowed for this color type
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(Error::InvalidIhdr(format!("unknown color type {value}"))),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ColorType::Grayscale => "Grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "Indexed",
+            ColorType::GrayscaleAlpha => "Grayscale + Alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The interlace method of a PNG image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interlace {
+    None = 0,
+    Adam7 = 1,
+}
+
+impl TryFrom<u8> for Interlace {
+    type Error = Error;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Interlace::None),
+            1 => Ok(Interlace::Adam7),
+            _ => Err(Error::InvalidIhdr(format!(
+                "unknown interlace method {value}"
+            ))),
+        }
+    }
+}
+
+impl Display for Interlace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Interlace::None => "None",
+            Interlace::Adam7 => "Adam7",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The image header (IHDR chunk) holding the basic facts of a PNG image.
+/// The compression and filter methods are always 0, the only ones defined by the PNG spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlace: Interlace,
+}
+
+impl Ihdr {
+    /// Creates an image header, checking the values against the PNG spec.
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlace: Interlace,
+    ) -> Result<Ihdr> {
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(Error::InvalidIhdr(format!(
+                "invalid image dimensions {width}x{height}"
+            )));
+        }
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(Error::InvalidIhdr(format!(
+                "bit depth {bit_depth} is not allowed for color type {color_type}"
+            )));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace,
+        })
+    }
+
+    /// The image width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The image height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of bits per sample or per palette index
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    /// The `ColorType` of the image
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// The `Interlace` method of the image
+    pub fn interlace(&self) -> Interlace {
+        self.interlace
+    }
+
+    /// The number of bits per pixel
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Returns this header as an IHDR `Chunk`.
+    pub fn to_chunk(&self) -> Chunk {
+        let data: Vec<u8> = self
+            .width
+            .to_be_bytes()
+            .iter()
+            .chain(self.height.to_be_bytes().iter())
+            .chain(
+                [
+                    self.bit_depth,
+                    self.color_type as u8,
+                    0,
+                    0,
+                    self.interlace as u8,
+                ]
+                .iter(),
+            )
+            .copied()
+            .collect();
+        // IHDR is a valid chunk type
+        Chunk::new(ChunkType::try_from(IHDR).unwrap(), &data)
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = Error;
+
+    /// Parses the data of an IHDR chunk.
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        let data: [u8; 13] = value
+            .try_into()
+            .map_err(|_| Error::InvalidIhdr(format!("expected 13 bytes, found {}", value.len())))?;
+
+        let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        if data[10] != 0 {
+            return Err(Error::InvalidIhdr(format!(
+                "unknown compression method {}",
+                data[10]
+            )));
+        }
+        if data[11] != 0 {
+            return Err(Error::InvalidIhdr(format!(
+                "unknown filter method {}",
+                data[11]
+            )));
+        }
+
+        Ihdr::new(
+            width,
+            height,
+            data[8],
+            ColorType::try_from(data[9])?,
+            Interlace::try_from(data[12])?,
+        )
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(value: &Chunk) -> std::result::Result<Self, Self::Error> {
+        if value.chunk_type().as_bytes() != IHDR {
+            return Err(Error::InvalidIhdr(format!(
+                "expected an IHDR chunk, found {}",
+                value.chunk_type()
+            )));
+        }
+        Ihdr::try_from(value.data())
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} pixels, bit depth {}, color type {}, interlace {}",
+            self.width, self.height, self.bit_depth, self.color_type, self.interlace
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // IHDR data of a 50x50 RGBA image
+    const IHDR_DATA: [u8; 13] = [0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 0, 0];
+
+    #[test]
+    fn test_ihdr_from_bytes() -> Result<()> {
+        let ihdr = Ihdr::try_from(&IHDR_DATA[..])?;
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 50);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+        assert_eq!(ihdr.interlace(), Interlace::None);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ihdr_to_chunk() -> Result<()> {
+        let ihdr = Ihdr::try_from(&IHDR_DATA[..])?;
+        let chunk = ihdr.to_chunk();
+        assert_eq!(&chunk.chunk_type().to_string(), "IHDR");
+        assert_eq!(chunk.data(), IHDR_DATA);
+        assert_eq!(Ihdr::try_from(&chunk)?, ihdr);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        assert!(Ihdr::try_from(&IHDR_DATA[..12]).is_err());
+
+        // RGBA with 4-bit samples
+        let mut data = IHDR_DATA;
+        data[8] = 4;
+        assert!(Ihdr::try_from(&data[..]).is_err());
+
+        // Unknown color type
+        let mut data = IHDR_DATA;
+        data[9] = 5;
+        assert!(Ihdr::try_from(&data[..]).is_err());
+
+        // Zero width
+        let mut data = IHDR_DATA;
+        data[3] = 0;
+        assert!(Ihdr::try_from(&data[..]).is_err());
+
+        // Unknown interlace method
+        let mut data = IHDR_DATA;
+        data[12] = 2;
+        assert!(Ihdr::try_from(&data[..]).is_err());
+    }
+}
@@ -1,10 +1,47 @@
 mod chunk;
+mod chunk_ref;
 mod chunk_type;
 mod displayable_vec;
+mod filter;
+mod idat;
+mod ihdr;
+mod parse;
+mod pixel_buffer;
+mod plte;
 mod png;
+mod png_ref;
+mod reader;
+mod split;
+#[cfg(test)]
+pub(crate) mod testing;
+mod text;
+mod validate;
+mod writer;
+pub(crate) mod zlib;
 
 pub use chunk::Chunk;
+pub use chunk_ref::ChunkRef;
 pub use chunk_type::ChunkType;
 use displayable_vec::DisplayableVec;
+pub use ihdr::ColorType;
+pub use ihdr::Ihdr;
+pub use ihdr::Interlace;
+pub use parse::ParseLimits;
+pub use parse::MAX_CHUNK_LENGTH;
+pub use pixel_buffer::PixelBuffer;
+pub use plte::Plte;
+pub use png::ChunkPlacement;
 pub use png::Png;
 pub use png::TAG;
+pub use png_ref::PngRef;
+pub use reader::PngReader;
+pub use split::reassemble;
+pub use split::split;
+pub use split::SplitPiece;
+pub use split::DEFAULT_SPLIT_LENGTH;
+pub use text::CompressedTextChunk;
+pub use text::InternationalTextChunk;
+pub use text::TextChunk;
+pub use text::TextualChunk;
+pub use text::MAX_TEXT_LENGTH;
+pub use writer::PngWriter;
@@ -0,0 +1,174 @@
+use crate::img_format::chunk::compute_crc;
+use crate::img_format::png::STANDARD_HEADER;
+use crate::img_format::ChunkType;
+use crate::{Error, Result};
+
+/// The maximum length of a chunk data allowed by the PNG spec: 2^31 - 1 bytes
+pub const MAX_CHUNK_LENGTH: u32 = (1 << 31) - 1;
+
+/// Bounds applied while parsing a PNG datastream, so that untrusted files
+/// cannot make the parser allocate or loop without limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum length of a single chunk data, never above `MAX_CHUNK_LENGTH`
+    pub max_chunk_length: u32,
+    /// Maximum number of chunks
+    pub max_chunks: usize,
+    /// Maximum size of the whole datastream in bytes, signature included
+    pub max_total_size: u64,
+}
+
+impl Default for ParseLimits {
+    /// Only the chunk length limit of the PNG spec is enforced by default
+    fn default() -> Self {
+        Self {
+            max_chunk_length: MAX_CHUNK_LENGTH,
+            max_chunks: usize::MAX,
+            max_total_size: u64::MAX,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Limits for images of unknown origin, e.g. uploaded to a web page:
+    /// at most 256 MiB and 65536 chunks
+    pub const UNTRUSTED: ParseLimits = ParseLimits {
+        max_chunk_length: MAX_CHUNK_LENGTH,
+        max_chunks: 1 << 16,
+        max_total_size: 256 << 20,
+    };
+
+    /// Checks the chunk starting at `offset` and declaring `length` bytes of data
+    /// against these limits.
+    pub(crate) fn check_chunk(&self, offset: u64, chunk_index: usize, length: u32) -> Result<()> {
+        let limit_exceeded = |limit: String| Error::LimitExceeded {
+            offset,
+            chunk_index,
+            limit,
+        };
+
+        if chunk_index >= self.max_chunks {
+            return Err(limit_exceeded(format!(
+                "more than {} chunks",
+                self.max_chunks
+            )));
+        }
+        let max_chunk_length = self.max_chunk_length.min(MAX_CHUNK_LENGTH);
+        if length > max_chunk_length {
+            return Err(limit_exceeded(format!(
+                "chunk length {length} is above {max_chunk_length} bytes"
+            )));
+        }
+        // 12 extra bytes here for Chunk Length + Chunk Type + CRC
+        let chunk_end = offset.saturating_add(length as u64 + 12);
+        if chunk_end > self.max_total_size {
+            return Err(limit_exceeded(format!(
+                "datastream is larger than {} bytes",
+                self.max_total_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the error returned for malformed input.
+pub(crate) fn malformed(offset: u64, chunk_index: usize, expected: impl Into<String>) -> Error {
+    Error::Malformed {
+        offset,
+        chunk_index,
+        expected: expected.into(),
+    }
+}
+
+/// Checks that the given bytes are the PNG signature.
+pub(crate) fn check_signature(header: &[u8]) -> Result<()> {
+    if header.len() < STANDARD_HEADER.len() {
+        return Err(malformed(0, 0, "8-byte PNG signature"));
+    }
+    match header
+        .iter()
+        .zip(STANDARD_HEADER)
+        .position(|(a, b)| *a != b)
+    {
+        Some(position) => Err(malformed(position as u64, 0, "PNG signature")),
+        None => Ok(()),
+    }
+}
+
+/// Parses the chunk type located at `offset`.
+pub(crate) fn parse_chunk_type(
+    offset: u64,
+    chunk_index: usize,
+    bytes: [u8; 4],
+) -> Result<ChunkType> {
+    ChunkType::try_from(bytes)
+        .map_err(|_| malformed(offset, chunk_index, "chunk type made of ASCII letters"))
+}
+
+/// Checks the CRC located at `offset` against the chunk type and data.
+pub(crate) fn check_crc(
+    offset: u64,
+    chunk_index: usize,
+    chunk_type: &ChunkType,
+    data: &[u8],
+    crc: u32,
+) -> Result<()> {
+    let computed = compute_crc(&chunk_type.as_bytes(), data);
+    if crc != computed {
+        return Err(malformed(
+            offset,
+            chunk_index,
+            format!("CRC {computed:#010x}, found {crc:#010x}"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_limits() {
+        let limits = ParseLimits {
+            max_chunk_length: 100,
+            max_chunks: 2,
+            max_total_size: 200,
+        };
+        assert!(limits.check_chunk(8, 0, 100).is_ok());
+        assert!(matches!(
+            limits.check_chunk(8, 0, 101),
+            Err(Error::LimitExceeded { offset: 8, .. })
+        ));
+        assert!(matches!(
+            limits.check_chunk(120, 2, 10),
+            Err(Error::LimitExceeded { chunk_index: 2, .. })
+        ));
+        assert!(limits.check_chunk(150, 1, 40).is_err());
+    }
+
+    #[test]
+    fn test_spec_chunk_length_cap() {
+        let limits = ParseLimits {
+            max_chunk_length: u32::MAX,
+            ..Default::default()
+        };
+        assert!(limits.check_chunk(8, 0, MAX_CHUNK_LENGTH).is_ok());
+        assert!(limits.check_chunk(8, 0, MAX_CHUNK_LENGTH + 1).is_err());
+    }
+
+    #[test]
+    fn test_check_signature() {
+        assert!(check_signature(&STANDARD_HEADER).is_ok());
+        assert!(matches!(
+            check_signature(&STANDARD_HEADER[..5]),
+            Err(Error::Malformed { offset: 0, .. })
+        ));
+        let mut header = STANDARD_HEADER;
+        header[3] = 0;
+        assert!(matches!(
+            check_signature(&header),
+            Err(Error::Malformed { offset: 3, .. })
+        ));
+    }
+}
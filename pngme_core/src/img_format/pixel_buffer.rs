@@ -0,0 +1,181 @@
+use crate::{Error, Result};
+
+use crate::img_format::{ColorType, Ihdr, Interlace};
+
+/// The decoded pixels of a PNG image.
+///
+/// Rows are stored top to bottom without filter bytes nor interlacing, each row taking
+/// `stride()` bytes. Samples are packed exactly as in PNG: big-endian for 16-bit samples,
+/// several samples per byte (most significant bits first) for bit depths below 8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    data: Vec<u8>,
+}
+
+impl PixelBuffer {
+    /// Creates a pixel buffer, the data length must match the image dimensions.
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        data: Vec<u8>,
+    ) -> Result<PixelBuffer> {
+        // Validate the image parameters against the PNG spec
+        Ihdr::new(width, height, bit_depth, color_type, Interlace::None)?;
+        let expected = row_length(width, bit_depth, color_type)
+            .checked_mul(height as usize)
+            .ok_or(Error::InvalidImageData("image is too large".to_string()))?;
+        if data.len() != expected {
+            return Err(Error::InvalidImageData(format!(
+                "expected {expected} bytes of pixels, found {}",
+                data.len()
+            )));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// The raw packed pixel bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The raw packed pixel bytes, for in-place edits
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// The number of bytes of a row
+    pub fn stride(&self) -> usize {
+        row_length(self.width, self.bit_depth, self.color_type)
+    }
+
+    /// The number of samples of the image: width x height x channels
+    pub fn sample_count(&self) -> usize {
+        self.samples_per_row() * self.height as usize
+    }
+
+    /// Returns the sample at the given index, counted row by row.
+    pub fn sample(&self, index: usize) -> u16 {
+        let (byte, shift) = self.sample_position(index);
+        match self.bit_depth {
+            16 => u16::from_be_bytes([self.data[byte], self.data[byte + 1]]),
+            8 => self.data[byte] as u16,
+            depth => ((self.data[byte] >> shift) & sample_mask(depth)) as u16,
+        }
+    }
+
+    /// Replaces the sample at the given index, counted row by row.
+    /// The value is truncated to the bit depth of the image.
+    pub fn set_sample(&mut self, index: usize, value: u16) {
+        let (byte, shift) = self.sample_position(index);
+        match self.bit_depth {
+            16 => self.data[byte..byte + 2].copy_from_slice(&value.to_be_bytes()),
+            8 => self.data[byte] = value as u8,
+            depth => {
+                let mask = sample_mask(depth) << shift;
+                self.data[byte] = (self.data[byte] & !mask) | (((value as u8) << shift) & mask);
+            }
+        }
+    }
+
+    fn samples_per_row(&self) -> usize {
+        self.width as usize * self.color_type.channels()
+    }
+
+    /// Returns the byte holding the given sample, and the shift of the sample within
+    /// that byte for bit depths below 8.
+    fn sample_position(&self, index: usize) -> (usize, u32) {
+        let samples_per_row = self.samples_per_row();
+        let (row, column) = (index / samples_per_row, index % samples_per_row);
+        let depth = self.bit_depth as usize;
+        let bit = column * depth;
+        // Samples below 8 bits are packed from the most significant bit
+        let shift = if depth < 8 { 8 - depth - bit % 8 } else { 0 };
+        (row * self.stride() + bit / 8, shift as u32)
+    }
+}
+
+/// The number of bytes of a row of `width` pixels.
+/// Saturates at `usize::MAX`, so that later checked arithmetic fails on huge images.
+pub(crate) fn row_length(width: u32, bit_depth: u8, color_type: ColorType) -> usize {
+    let bits = width as u64 * color_type.channels() as u64 * bit_depth as u64;
+    usize::try_from(bits.div_ceil(8)).unwrap_or(usize::MAX)
+}
+
+fn sample_mask(bit_depth: u8) -> u8 {
+    ((1u16 << bit_depth) - 1) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_buffer_length() {
+        assert!(PixelBuffer::new(3, 2, 8, ColorType::Rgb, vec![0; 18]).is_ok());
+        assert!(PixelBuffer::new(3, 2, 8, ColorType::Rgb, vec![0; 17]).is_err());
+        // 3 pixels of 1 bit take a whole byte
+        assert!(PixelBuffer::new(3, 2, 1, ColorType::Grayscale, vec![0; 2]).is_ok());
+        // RGB does not allow 4-bit samples
+        assert!(PixelBuffer::new(3, 2, 4, ColorType::Rgb, vec![0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_samples_8_and_16_bit() -> Result<()> {
+        let mut pixels = PixelBuffer::new(2, 1, 16, ColorType::GrayscaleAlpha, vec![0; 8])?;
+        assert_eq!(pixels.sample_count(), 4);
+        pixels.set_sample(2, 0x1234);
+        assert_eq!(pixels.sample(2), 0x1234);
+        assert_eq!(pixels.data(), [0, 0, 0, 0, 0x12, 0x34, 0, 0]);
+
+        let mut pixels = PixelBuffer::new(1, 2, 8, ColorType::Rgb, vec![0; 6])?;
+        pixels.set_sample(4, 200);
+        assert_eq!(pixels.data(), [0, 0, 0, 0, 200, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_samples_below_8_bit() -> Result<()> {
+        // 2 rows of 3 pixels, each row is padded to a whole byte
+        let mut pixels = PixelBuffer::new(3, 2, 2, ColorType::Grayscale, vec![0; 2])?;
+        pixels.set_sample(1, 0b11);
+        pixels.set_sample(3, 0b01);
+        assert_eq!(pixels.data(), [0b0011_0000, 0b0100_0000]);
+        assert_eq!(pixels.sample(1), 0b11);
+        assert_eq!(pixels.sample(3), 0b01);
+        assert_eq!(pixels.sample(2), 0);
+
+        let mut pixels = PixelBuffer::new(8, 1, 1, ColorType::Grayscale, vec![0; 1])?;
+        pixels.set_sample(7, 1);
+        assert_eq!(pixels.data(), [0b0000_0001]);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{Error, Result};
+
+use crate::img_format::{Chunk, ChunkType};
+
+/// The chunk type of the palette
+pub(crate) const PLTE: [u8; 4] = *b"PLTE";
+
+/// The palette (PLTE chunk) of a PNG image: a list of 1 to 256 RGB entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plte {
+    entries: Vec<[u8; 3]>,
+}
+
+impl Plte {
+    /// Creates a palette from the given RGB entries.
+    pub fn new(entries: Vec<[u8; 3]>) -> Result<Plte> {
+        if entries.is_empty() || entries.len() > 256 {
+            return Err(Error::InvalidPlte(format!(
+                "expected 1 to 256 entries, found {}",
+                entries.len()
+            )));
+        }
+        Ok(Self { entries })
+    }
+
+    /// The RGB entries of this palette
+    pub fn entries(&self) -> &[[u8; 3]] {
+        &self.entries
+    }
+
+    /// Returns this palette as a PLTE `Chunk`.
+    pub fn to_chunk(&self) -> Chunk {
+        let data: Vec<u8> = self.entries.iter().flatten().copied().collect();
+        // PLTE is a valid chunk type
+        Chunk::new(ChunkType::try_from(PLTE).unwrap(), &data)
+    }
+}
+
+impl TryFrom<&[u8]> for Plte {
+    type Error = Error;
+
+    /// Parses the data of a PLTE chunk.
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        if !value.len().is_multiple_of(3) {
+            return Err(Error::InvalidPlte(format!(
+                "length {} is not divisible by 3",
+                value.len()
+            )));
+        }
+        let entries = value
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect();
+        Plte::new(entries)
+    }
+}
+
+impl TryFrom<&Chunk> for Plte {
+    type Error = Error;
+
+    fn try_from(value: &Chunk) -> std::result::Result<Self, Self::Error> {
+        if value.chunk_type().as_bytes() != PLTE {
+            return Err(Error::InvalidPlte(format!(
+                "expected a PLTE chunk, found {}",
+                value.chunk_type()
+            )));
+        }
+        Plte::try_from(value.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plte_roundtrip() -> Result<()> {
+        let plte = Plte::new(vec![[0, 0, 0], [255, 128, 0]])?;
+        let chunk = plte.to_chunk();
+        assert_eq!(chunk.data(), [0, 0, 0, 255, 128, 0]);
+        assert_eq!(Plte::try_from(&chunk)?, plte);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_plte() {
+        assert!(Plte::try_from(&[0, 0, 0, 1][..]).is_err());
+        assert!(Plte::try_from(&[][..]).is_err());
+        assert!(Plte::try_from(&[0; 3 * 257][..]).is_err());
+    }
+}
@@ -6,15 +6,45 @@ use crate::img_format::DisplayableVec;
 use crate::{Error, Result};
 
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 
-use super::ChunkType;
+use super::idat;
+use super::plte::PLTE;
+use super::split::{self, SplitPiece};
+use super::validate::validate_chunks;
+use super::{
+    ChunkType, ColorType, Ihdr, Interlace, ParseLimits, PixelBuffer, Plte, PngReader, PngWriter,
+    TextualChunk,
+};
 
 /// Fill in this array with the correct values per the PNG spec
-const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+pub(crate) const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 /// This "PNGme" tag is gonna be prepended to each new chunk, marking that
 /// the newly-added chunk is processed by PNGme -> Searchable PNGme-processed chunks
 pub const TAG: [u8; 5] = [80, 78, 71, 109, 101];
+/// The chunk type holding the compressed image data
+pub(crate) const IDAT: [u8; 4] = *b"IDAT";
+/// The chunk type closing every PNG datastream
+pub(crate) const IEND: [u8; 4] = *b"IEND";
+
+/// The maximum data length of the IDAT chunks written by `Png::encode_pixels`
+const IDAT_CHUNK_LENGTH: usize = 1 << 20;
+/// Ancillary chunks laid out after the color type and bit depth of the image
+const PIXEL_FORMAT_CHUNK_TYPES: [[u8; 4]; 3] = [*b"tRNS", *b"bKGD", *b"sBIT"];
+/// The palette histogram, only meaningful along with PLTE
+const HIST: [u8; 4] = *b"hIST";
+
+/// Where a new `Chunk` should be inserted into the `Png` list of chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkPlacement {
+    /// Right before the IEND chunk, or at the end if there is no IEND chunk.
+    #[default]
+    BeforeIend,
+    /// Right after the first chunk of the given type.
+    After(ChunkType),
+    /// At the given position of the chunk list.
+    Index(usize),
+}
 
 pub struct Png {
     header: [u8; 8],
@@ -30,49 +60,187 @@ impl Png {
         }
     }
 
+    /// Collects all remaining chunks of the given `PngReader` into a `Png`
+    pub fn from_reader<R: Read>(reader: PngReader<R>) -> Result<Png> {
+        let chunks = reader.collect::<Result<Vec<Chunk>>>()?;
+        Ok(Self::from_chunks(chunks))
+    }
+
+    /// Parses a PNG datastream, enforcing the given `ParseLimits`
+    pub fn with_limits(bytes: &[u8], limits: ParseLimits) -> Result<Png> {
+        Png::from_reader(PngReader::with_limits(bytes, limits)?)
+    }
+
+    /// Reads a PNG file chunk by chunk, enforcing the given `ParseLimits`
+    pub fn from_file<P: AsRef<Path>>(path: P, limits: ParseLimits) -> Result<Png> {
+        let png_file = File::open(path).map_err(Error::FileOpenErr)?;
+        Png::from_reader(PngReader::with_limits(BufReader::new(png_file), limits)?)
+    }
+
     /// Export this PNG struct to a PNG file with the given file path
     pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut png_file = OpenOptions::new()
+        let png_file = OpenOptions::new()
             .write(true)
             .truncate(true)
             .create(true)
             .open(path)
             .map_err(Error::FileOpenErr)?;
-        png_file
-            .write_all(&self.as_bytes())
-            .map_err(Error::BufferWriterErr)?;
+        self.write_to(BufWriter::new(png_file))?;
         Ok(())
     }
 
-    /// Appends a chunk to the end of this `Png` file's `Chunk` list.
-    pub fn append_chunk(&mut self, mut chunk: Chunk, is_tagged: bool) -> Result<()> {
-        if self
-            .chunks
-            .iter()
-            .any(|chunk_iter| chunk.chunk_type() == chunk_iter.chunk_type())
-        {
-            return Err(Error::DuplicatedChunkType);
+    /// Streams this PNG struct into the given writer, chunk by chunk.
+    /// Returns the writer once everything has been flushed.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<W> {
+        let mut png_writer = PngWriter::new(writer)?;
+        for chunk in self.chunks.iter() {
+            png_writer.write_chunk(chunk)?;
         }
+        png_writer.finish()
+    }
+
+    /// Appends a chunk to this `Png` file's `Chunk` list, right before the IEND chunk
+    /// so that the result is still a valid PNG.
+    pub fn append_chunk(&mut self, chunk: Chunk, is_tagged: bool) -> Result<()> {
+        self.insert_chunk(chunk, is_tagged, ChunkPlacement::default())
+    }
+
+    /// Inserts a chunk to this `Png` file's `Chunk` list at the given placement.
+    /// Chunks of the same type may appear more than once, `validate` rejects the critical
+    /// chunks the PNG spec does not allow to repeat.
+    pub fn insert_chunk(
+        &mut self,
+        mut chunk: Chunk,
+        is_tagged: bool,
+        placement: ChunkPlacement,
+    ) -> Result<()> {
         if is_tagged {
             chunk.prepend(&TAG)?;
         }
-        self.chunks.push(chunk);
+        let index = self.placement_index(placement)?;
+        self.chunks.insert(index, chunk);
         Ok(())
     }
 
+    /// Appends the payload as tagged chunks of the given type right before the IEND chunk,
+    /// split into as many chunks as needed to keep each of them under `max_chunk_length` bytes.
+    /// A payload fitting in one chunk is appended as a single tagged chunk, as readable by
+    /// older versions. Returns the number of chunks appended.
+    pub fn append_split_chunks(
+        &mut self,
+        chunk_type: ChunkType,
+        payload: &[u8],
+        max_chunk_length: usize,
+    ) -> Result<usize> {
+        if TAG.len() + payload.len() <= max_chunk_length {
+            self.append_chunk(Chunk::new(chunk_type, payload), true)?;
+            return Ok(1);
+        }
+
+        let pieces = split::split(payload, max_chunk_length)?;
+        let index = self.placement_index(ChunkPlacement::BeforeIend)?;
+        let count = pieces.len();
+        self.chunks.splice(
+            index..index,
+            pieces
+                .iter()
+                .map(|piece| Chunk::new(chunk_type, &[&TAG, piece.as_slice()].concat())),
+        );
+        Ok(count)
+    }
+
+    /// Reassembles the payload that the given tagged chunk holds a piece of, from the chunks
+    /// of its type, in any order. Returns `None` if the chunk holds a whole payload.
+    /// Malformed pieces in other chunks are skipped, and reported as missing if needed.
+    pub fn split_payload(&self, chunk: &Chunk) -> Result<Option<Vec<u8>>> {
+        let Some(piece) = chunk.data().strip_prefix(&TAG) else {
+            return Ok(None);
+        };
+        if !SplitPiece::is_piece(piece) {
+            return Ok(None);
+        }
+        let id = SplitPiece::try_from(piece)?.id();
+
+        let pieces = self
+            .chunks
+            .iter()
+            .filter(|other| other.chunk_type() == chunk.chunk_type())
+            .filter_map(|other| other.data().strip_prefix(&TAG))
+            .filter(|other| SplitPiece::is_piece(other))
+            .filter_map(|other| SplitPiece::try_from(other).ok())
+            .filter(|other| other.id() == id)
+            .collect::<Vec<_>>();
+        split::reassemble(&pieces).map(Some)
+    }
+
+    /// Resolves the given placement into an index of the `Chunk` list.
+    fn placement_index(&self, placement: ChunkPlacement) -> Result<usize> {
+        match placement {
+            ChunkPlacement::BeforeIend => Ok(self
+                .chunks
+                .iter()
+                .rposition(|c| c.chunk_type().as_bytes() == IEND)
+                .unwrap_or(self.chunks.len())),
+            ChunkPlacement::After(chunk_type) => self
+                .chunks
+                .iter()
+                .position(|c| *c.chunk_type() == chunk_type)
+                .map(|index| index + 1)
+                .ok_or(Error::NotFoundChunkType),
+            ChunkPlacement::Index(index) if index <= self.chunks.len() => Ok(index),
+            ChunkPlacement::Index(index) => Err(Error::InvalidChunkIndex(index)),
+        }
+    }
+
     /// Searches for a `Chunk` with the specified `chunk_type` and removes the first
     /// matching `Chunk` from this `Png` list of chunks.
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        if let Some(index) = self
+        self.remove_chunk_at(chunk_type, 0)
+    }
+
+    /// Removes the `index`-th `Chunk` of the specified `chunk_type`, counting from 0.
+    pub fn remove_chunk_at(&mut self, chunk_type: &str, index: usize) -> Result<Chunk> {
+        let position = self.position_of(chunk_type, index)?;
+        Ok(self.chunks.remove(position))
+    }
+
+    /// Removes the `index`-th `Chunk` of the specified `chunk_type`, counting from 0, along
+    /// with the other pieces of the split payload it may hold, unreadable without any of them.
+    /// Returns the number of removed chunks.
+    pub fn remove_payload_at(&mut self, chunk_type: &str, index: usize) -> Result<usize> {
+        let position = self.position_of(chunk_type, index)?;
+        let Some(id) = split_piece_id(&self.chunks[position]) else {
+            self.chunks.remove(position);
+            return Ok(1);
+        };
+        let chunk_type = *self.chunks[position].chunk_type();
+        let count = self.chunks.len();
+        self.chunks
+            .retain(|chunk| *chunk.chunk_type() != chunk_type || split_piece_id(chunk) != Some(id));
+        Ok(count - self.chunks.len())
+    }
+
+    /// Replaces the `index`-th `Chunk` of the specified `chunk_type`, counting from 0,
+    /// keeping its place in the list. Returns the replaced `Chunk`.
+    pub fn replace_chunk(&mut self, chunk_type: &str, index: usize, chunk: Chunk) -> Result<Chunk> {
+        let position = self.position_of(chunk_type, index)?;
+        Ok(std::mem::replace(&mut self.chunks[position], chunk))
+    }
+
+    /// Resolves the `index`-th chunk of the given type into an index of the `Chunk` list.
+    fn position_of(&self, chunk_type_str: &str, index: usize) -> Result<usize> {
+        let target_chunk_type = ChunkType::from_str(chunk_type_str)?;
+        let mut positions = self
             .chunks
             .iter()
-            .position(|c| c.chunk_type().to_string() == chunk_type)
-        {
-            let removed_chunk = self.chunks.remove(index);
-            Ok(removed_chunk)
-        } else {
-            Err(Error::NotFoundChunkType)
+            .enumerate()
+            .filter(|(_, c)| *c.chunk_type() == target_chunk_type)
+            .map(|(position, _)| position)
+            .peekable();
+        if positions.peek().is_none() {
+            return Err(Error::NotFoundChunkType);
         }
+        positions.nth(index).ok_or(Error::InvalidChunkIndex(index))
     }
 
     /// The header of this PNG.
@@ -95,16 +263,163 @@ impl Png {
             .find(|c| *c.chunk_type() == target_chunk_type))
     }
 
+    /// Returns all of the `Chunk`s with the specified `chunk_type`, in order, so that
+    /// the `index`-th one can be picked.
+    pub fn chunks_by_type(&self, chunk_type_str: &str) -> Result<Vec<&Chunk>> {
+        let target_chunk_type = ChunkType::from_str(chunk_type_str)?;
+        Ok(self
+            .chunks
+            .iter()
+            .filter(|c| *c.chunk_type() == target_chunk_type)
+            .collect())
+    }
+
+    /// Parses the image header, which must be the first chunk of this `Png`.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) => Ihdr::try_from(chunk),
+            None => Err(Error::InvalidStructure(
+                "IHDR must be the first chunk".to_string(),
+            )),
+        }
+    }
+
+    /// Parses the palette of this `Png`, if there is one.
+    pub fn palette(&self) -> Result<Option<Plte>> {
+        self.chunks
+            .iter()
+            .find(|c| c.chunk_type().as_bytes() == PLTE)
+            .map(Plte::try_from)
+            .transpose()
+    }
+
+    /// Decodes the image data of all IDAT chunks into unfiltered, deinterlaced pixels.
+    /// Indexed images are decoded into palette indices.
+    pub fn decode_pixels(&self) -> Result<PixelBuffer> {
+        let ihdr = self.ihdr()?;
+        let compressed: Vec<u8> = self
+            .chunks
+            .iter()
+            .filter(|c| c.chunk_type().as_bytes() == IDAT)
+            .flat_map(|c| c.data().iter().copied())
+            .collect();
+        if compressed.is_empty() {
+            return Err(Error::InvalidStructure(
+                "at least one IDAT chunk is required".to_string(),
+            ));
+        }
+        idat::decode(&ihdr, &compressed)
+    }
+
+    /// Replaces the image data with the given pixels, stored with the given interlace method.
+    /// The IHDR chunk is rewritten to match the pixels, every other chunk is kept: the new
+    /// IDAT chunks take the place of the old ones, or go right before IEND.
+    ///
+    /// When the color type or bit depth changes, the tRNS, bKGD and sBIT chunks are dropped,
+    /// along with PLTE and hIST for grayscale pixels. Indexed pixels need a fitting PLTE.
+    pub fn encode_pixels(&mut self, pixels: &PixelBuffer, interlace: Interlace) -> Result<()> {
+        // The current header must be in place, it gets replaced below
+        let current = self.ihdr()?;
+        let ihdr = Ihdr::new(
+            pixels.width(),
+            pixels.height(),
+            pixels.bit_depth(),
+            pixels.color_type(),
+            interlace,
+        )?;
+        let same_format =
+            current.color_type() == ihdr.color_type() && current.bit_depth() == ihdr.bit_depth();
+        if ihdr.color_type() == ColorType::Indexed {
+            match self.palette()? {
+                Some(plte) if plte.entries().len() <= 1 << ihdr.bit_depth() => {}
+                Some(_) => {
+                    return Err(Error::InvalidStructure(format!(
+                        "PLTE has more entries than a {}-bit index allows",
+                        ihdr.bit_depth()
+                    )))
+                }
+                None => {
+                    return Err(Error::InvalidStructure(
+                        "PLTE is required for indexed-color images".to_string(),
+                    ))
+                }
+            }
+        }
+        let compressed = idat::encode(&ihdr, pixels)?;
+
+        self.chunks[0] = ihdr.to_chunk();
+        if !same_format {
+            let drops_palette = matches!(
+                ihdr.color_type(),
+                ColorType::Grayscale | ColorType::GrayscaleAlpha
+            );
+            self.chunks.retain(|c| {
+                let chunk_type = c.chunk_type().as_bytes();
+                !PIXEL_FORMAT_CHUNK_TYPES.contains(&chunk_type)
+                    && !(drops_palette && (chunk_type == PLTE || chunk_type == HIST))
+            });
+        }
+        let first_idat = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type().as_bytes() == IDAT);
+        self.chunks.retain(|c| c.chunk_type().as_bytes() != IDAT);
+        let index = match first_idat {
+            Some(index) => index,
+            None => self.placement_index(ChunkPlacement::BeforeIend)?,
+        };
+        // IDAT is a valid chunk type
+        let idat_type = ChunkType::try_from(IDAT).unwrap();
+        let idat_chunks = compressed
+            .chunks(IDAT_CHUNK_LENGTH)
+            .map(|data| Chunk::new(idat_type, data));
+        self.chunks.splice(index..index, idat_chunks);
+        Ok(())
+    }
+
+    /// Parses all tEXt, zTXt and iTXt chunks of this `Png`, in order.
+    pub fn text_chunks(&self) -> Result<Vec<TextualChunk>> {
+        self.try_text_chunks().into_iter().collect()
+    }
+
+    /// Parses each tEXt, zTXt and iTXt chunk of this `Png` on its own, in order,
+    /// so that a malformed one does not hide the others.
+    pub fn try_text_chunks(&self) -> Vec<Result<TextualChunk>> {
+        self.chunks
+            .iter()
+            .filter(|c| TextualChunk::is_textual(c.chunk_type()))
+            .map(TextualChunk::try_from)
+            .collect()
+    }
+
+    /// Adds a textual chunk right before the IEND chunk.
+    pub fn add_text_chunk(&mut self, text: &TextualChunk) -> Result<()> {
+        self.append_chunk(text.to_chunk()?, false)
+    }
+
+    /// Removes all textual chunks with the given keyword, leaving malformed ones in place.
+    /// Returns the number of removed chunks.
+    pub fn remove_text_chunks(&mut self, keyword: &str) -> Result<usize> {
+        let before = self.chunks.len();
+        self.chunks.retain(|c| {
+            !(TextualChunk::is_textual(c.chunk_type())
+                && TextualChunk::try_from(c).is_ok_and(|text| text.keyword() == keyword))
+        });
+        Ok(before - self.chunks.len())
+    }
+
+    /// Strictly checks the ordering and multiplicity of the critical chunks,
+    /// so that the result can be read by any PNG decoder.
+    pub fn validate(&self) -> Result<()> {
+        validate_chunks(&self.chunks)
+    }
+
     /// Returns this `Png` as a byte sequence.
     /// These bytes will contain the header followed by the bytes of all of the chunks.
     pub fn as_bytes(&self) -> Vec<u8> {
-        // Collection of all chunks as bytes
-        let chunks_bytes: Vec<u8> = self.chunks.iter().flat_map(|ch| ch.as_bytes()).collect();
-        self.header
-            .iter()
-            .chain(chunks_bytes.iter())
-            .copied()
-            .collect()
+        // Writing into a `Vec` never fails
+        self.write_to(Vec::new())
+            .expect("Could not write PNG into memory")
     }
 }
 
@@ -113,26 +428,7 @@ impl TryFrom<&[u8]> for Png {
 
     /// Try to manipulate raw bytes into a PNG object.
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
-        let first_8_bytes = value.get(..8).expect("Byte stream less than 8 bytes");
-        if first_8_bytes != STANDARD_HEADER {
-            return Err(Error::InvalidHeaderByte);
-        }
-        let mut header: [u8; 8] = [0; 8];
-        header.copy_from_slice(first_8_bytes);
-
-        // go through bytes array and collect as chunks
-        let mut chunks: Vec<Chunk> = Vec::new();
-        let mut remaining_bytes = value.get(8..).unwrap();
-        let mut start_index: usize = 8;
-        while !remaining_bytes.is_empty() {
-            let possible_chunk = Chunk::try_from(remaining_bytes)?;
-            chunks.push(possible_chunk.clone());
-            // 12 extra bytes here for Chunk Length + Chunk Type + CRC
-            start_index += (possible_chunk.length() + 12) as usize;
-            remaining_bytes = value.get(start_index..).unwrap_or(&[]);
-        }
-
-        Ok(Self { header, chunks })
+        Png::with_limits(value, ParseLimits::default())
     }
 }
 
@@ -141,16 +437,8 @@ impl TryFrom<&Path> for Png {
 
     /// Creates a 'Png' from a file using the correct header
     fn try_from(value: &Path) -> std::result::Result<Self, Self::Error> {
-        let mut png_file = File::open(value).map_err(Error::FileOpenErr)?;
-
-        // Read the file into vector
-        let mut buffer = Vec::new();
-        png_file
-            .read_to_end(&mut buffer)
-            .map_err(Error::BufferReaderErr)?;
-
-        // Return this struct if input data is valid
-        Png::try_from(buffer.as_slice())
+        // Chunks are read one by one instead of loading the whole file up front
+        Png::from_file(value, ParseLimits::default())
     }
 }
 
@@ -161,11 +449,22 @@ impl Display for Png {
     }
 }
 
+/// The payload ID of a tagged chunk holding a well-formed piece of a split payload
+fn split_piece_id(chunk: &Chunk) -> Option<[u8; 8]> {
+    chunk
+        .data()
+        .strip_prefix(&TAG)
+        .filter(|piece| SplitPiece::is_piece(piece))
+        .and_then(|piece| SplitPiece::try_from(piece).ok())
+        .map(|piece| piece.id())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::img_format::Chunk;
     use crate::img_format::ChunkType;
+    use crate::img_format::{ColorType, CompressedTextChunk, Interlace, TextChunk};
     use std::convert::TryFrom;
     use std::str::FromStr;
 
@@ -215,6 +514,24 @@ mod tests {
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_png_limits() -> Result<()> {
+        let bytes = testing_png().as_bytes();
+        let limits = ParseLimits {
+            max_total_size: bytes.len() as u64 - 1,
+            ..ParseLimits::UNTRUSTED
+        };
+        assert!(matches!(
+            Png::with_limits(&bytes, limits),
+            Err(Error::LimitExceeded { chunk_index: 2, .. })
+        ));
+        assert_eq!(
+            Png::with_limits(&bytes, ParseLimits::UNTRUSTED)?.as_bytes(),
+            bytes
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_invalid_header() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -301,6 +618,285 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_chunks_of_the_same_type() -> Result<()> {
+        let mut png = testing_png();
+        for data in ["First", "Second", "Third"] {
+            png.append_chunk(chunk_from_strings("TeSt", data)?, false)?;
+        }
+        let data = |png: &Png| -> Result<Vec<String>> {
+            png.chunks_by_type("TeSt")?
+                .iter()
+                .map(|c| c.data_as_string())
+                .collect()
+        };
+        assert_eq!(data(&png)?, ["First", "Second", "Third"]);
+
+        let replaced = png.replace_chunk("TeSt", 1, chunk_from_strings("TeSt", "New")?)?;
+        assert_eq!(&replaced.data_as_string()?, "Second");
+        assert_eq!(&png.remove_chunk_at("TeSt", 2)?.data_as_string()?, "Third");
+        assert_eq!(data(&png)?, ["First", "New"]);
+
+        assert!(matches!(
+            png.remove_chunk_at("TeSt", 2),
+            Err(Error::InvalidChunkIndex(2))
+        ));
+        assert!(matches!(
+            png.remove_chunk_at("NoNe", 0),
+            Err(Error::NotFoundChunkType)
+        ));
+        assert!(png.chunks_by_type("NoNe")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_chunk_before_iend() -> Result<()> {
+        let mut png = Png::try_from(&PNG_FILE[..])?;
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap(), true)?;
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types[types.len() - 2..], ["TeSt", "IEND"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_chunks() -> Result<()> {
+        let mut png = Png::try_from(&PNG_FILE[..])?;
+        let chunk_type = ChunkType::from_str("TeSt")?;
+        let payload = b"This is where your secret message will be!".repeat(10);
+        assert_eq!(png.append_split_chunks(chunk_type, &payload, 100)?, 7);
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+
+        // Pieces are found in any order
+        let mut chunks = png.chunks().to_vec();
+        let first = chunks
+            .iter()
+            .position(|c| *c.chunk_type() == chunk_type)
+            .unwrap();
+        chunks.swap(first, first + 3);
+        let png = Png::from_chunks(chunks);
+        let piece = png.chunk_by_type("TeSt")?.expect("Should have Some(chunk)");
+        assert_eq!(png.split_payload(piece)?, Some(payload.clone()));
+
+        // A malformed piece of the same type is skipped
+        let truncated = piece.data()[..TAG.len() + 20].to_vec();
+        let mut chunks = png.chunks().to_vec();
+        chunks.insert(first + 7, Chunk::new(chunk_type, &truncated));
+        let malformed = Png::from_chunks(chunks);
+        let piece = malformed
+            .chunk_by_type("TeSt")?
+            .expect("Should have Some(chunk)");
+        assert_eq!(malformed.split_payload(piece)?, Some(payload.clone()));
+
+        // Removing a piece removes the whole payload, a malformed piece is removed alone
+        let mut malformed = malformed;
+        assert_eq!(malformed.remove_payload_at("TeSt", 3)?, 7);
+        assert_eq!(malformed.chunks_by_type("TeSt")?.len(), 1);
+        assert_eq!(malformed.remove_payload_at("TeSt", 0)?, 1);
+
+        let mut chunks = png.chunks().to_vec();
+        chunks.remove(first + 1);
+        let png = Png::from_chunks(chunks);
+        let piece = png.chunk_by_type("TeSt")?.expect("Should have Some(chunk)");
+        assert!(matches!(
+            png.split_payload(piece),
+            Err(Error::InvalidSplit(_))
+        ));
+
+        // A small payload still fits in a single chunk, readable by older versions
+        let mut png = testing_png();
+        assert_eq!(png.append_split_chunks(chunk_type, b"Message", 100)?, 1);
+        let chunk = png.chunk_by_type("TeSt")?.expect("Should have Some(chunk)");
+        assert_eq!(&chunk.data_as_string()?, "PNGmeMessage");
+        assert_eq!(png.split_payload(chunk)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_chunk_placement() -> Result<()> {
+        let mut png = testing_png();
+        let after = ChunkType::from_str("FrSt")?;
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "Message")?,
+            false,
+            ChunkPlacement::After(after),
+        )?;
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+
+        png.insert_chunk(
+            chunk_from_strings("InDx", "Message")?,
+            false,
+            ChunkPlacement::Index(0),
+        )?;
+        assert_eq!(&png.chunks()[0].chunk_type().to_string(), "InDx");
+
+        let result = png.insert_chunk(
+            chunk_from_strings("OuTs", "Message")?,
+            false,
+            ChunkPlacement::Index(42),
+        );
+        assert!(matches!(result, Err(Error::InvalidChunkIndex(42))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ihdr_and_validate() -> Result<()> {
+        let mut png = Png::try_from(&PNG_FILE[..])?;
+        let ihdr = png.ihdr()?;
+        assert_eq!((ihdr.width(), ihdr.height()), (50, 50));
+        assert!(png.palette()?.is_none());
+
+        // The testing image embeds an unknown critical chunk
+        assert!(png.validate().is_err());
+        png.remove_chunk("RuSt")?;
+        png.validate()?;
+
+        // The testing chunks do not make a valid image
+        assert!(testing_png().ihdr().is_err());
+        assert!(testing_png().validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_pixels() -> Result<()> {
+        let pixels = Png::try_from(&PNG_FILE[..])?.decode_pixels()?;
+        assert_eq!((pixels.width(), pixels.height()), (50, 50));
+        assert_eq!(pixels.color_type(), ColorType::Rgba);
+        assert_eq!(pixels.data().len(), 50 * 50 * 4);
+
+        assert!(testing_png().decode_pixels().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_pixels() -> Result<()> {
+        let mut png = Png::try_from(&PNG_FILE[..])?;
+        let other_chunks: Vec<Chunk> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().as_bytes() != IDAT)
+            .cloned()
+            .collect();
+        let mut pixels = png.decode_pixels()?;
+        pixels.set_sample(0, 255);
+        pixels.set_sample(1, 0);
+
+        png.encode_pixels(&pixels, Interlace::Adam7)?;
+        let png = Png::try_from(png.as_bytes().as_slice())?;
+        assert_eq!(png.decode_pixels()?, pixels);
+        assert_eq!(png.ihdr()?.interlace(), Interlace::Adam7);
+        // Every other chunk is kept in place
+        let kept: Vec<&Chunk> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().as_bytes() != IDAT)
+            .collect();
+        assert_eq!(kept.len(), other_chunks.len());
+        for (chunk, other) in kept.iter().zip(&other_chunks).skip(1) {
+            assert_eq!(chunk.as_bytes(), other.as_bytes());
+        }
+
+        // An image without IDAT gets its data before IEND
+        let mut png = Png::from_chunks(vec![
+            png.chunks()[0].clone(),
+            chunk_from_strings("IEND", "")?,
+        ]);
+        png.encode_pixels(&pixels, Interlace::None)?;
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "IDAT");
+        assert_eq!(png.decode_pixels()?, pixels);
+
+        assert!(testing_png()
+            .encode_pixels(&pixels, Interlace::None)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_pixels_format_change() -> Result<()> {
+        let mut png = Png::try_from(&PNG_FILE[..])?;
+        let ihdr = png.ihdr()?;
+        let palette = Plte::new(vec![[0, 0, 0], [255, 255, 255]])?;
+        png.insert_chunk(palette.to_chunk(), false, ChunkPlacement::Index(1))?;
+        png.insert_chunk(
+            chunk_from_strings("bKGD", "RRGGBB")?,
+            false,
+            ChunkPlacement::Index(2),
+        )?;
+
+        // An indexed image needs a palette large enough for its indices
+        let indexed = PixelBuffer::new(
+            ihdr.width(),
+            ihdr.height(),
+            1,
+            ColorType::Indexed,
+            vec![0; ihdr.width().div_ceil(8) as usize * ihdr.height() as usize],
+        )?;
+        let mut with_palette = Png::try_from(png.as_bytes().as_slice())?;
+        with_palette.encode_pixels(&indexed, Interlace::None)?;
+        assert!(with_palette.chunk_by_type("PLTE")?.is_some());
+        assert!(with_palette.chunk_by_type("bKGD")?.is_none());
+
+        // Grayscale pixels drop the palette along with the chunks tied to the former format
+        let gray = PixelBuffer::new(
+            ihdr.width(),
+            ihdr.height(),
+            8,
+            ColorType::Grayscale,
+            vec![128; (ihdr.width() * ihdr.height()) as usize],
+        )?;
+        png.encode_pixels(&gray, Interlace::None)?;
+        assert!(png.chunk_by_type("PLTE")?.is_none());
+        assert!(png.chunk_by_type("bKGD")?.is_none());
+        assert_eq!(png.decode_pixels()?, gray);
+
+        let before = png.as_bytes();
+        assert!(matches!(
+            png.encode_pixels(&indexed, Interlace::None),
+            Err(Error::InvalidStructure(_))
+        ));
+        assert_eq!(png.as_bytes(), before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_chunks() -> Result<()> {
+        let mut png = Png::try_from(&PNG_FILE[..])?;
+        let author = TextualChunk::Text(TextChunk::new("Author", "Cuong")?);
+        let comment = TextualChunk::Compressed(CompressedTextChunk::new("Comment", "Hi")?);
+        png.add_text_chunk(&author)?;
+        png.add_text_chunk(&comment)?;
+        png.add_text_chunk(&author)?;
+        assert_eq!(
+            png.text_chunks()?,
+            [author.clone(), comment.clone(), author.clone()]
+        );
+
+        assert_eq!(png.remove_text_chunks("Author")?, 2);
+        assert_eq!(png.text_chunks()?, [comment]);
+        assert_eq!(png.remove_text_chunks("Author")?, 0);
+
+        // A malformed text chunk is reported on its own and kept
+        png.append_chunk(
+            Chunk::new(ChunkType::from_str("tEXt")?, b"No separator"),
+            false,
+        )?;
+        png.add_text_chunk(&author)?;
+        assert!(png.text_chunks().is_err());
+        let texts = png.try_text_chunks();
+        assert_eq!(texts.len(), 3);
+        assert!(matches!(texts[1], Err(Error::InvalidText(_))));
+        assert_eq!(texts[2].as_ref().ok(), Some(&author));
+        assert_eq!(png.remove_text_chunks("Author")?, 1);
+        assert_eq!(png.try_text_chunks().len(), 2);
+        Ok(())
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use crate::img_format::parse::check_signature;
+use crate::img_format::png::STANDARD_HEADER;
+use crate::img_format::{ChunkRef, ChunkType, ParseLimits, Png};
+use crate::{Error, Result};
+
+/// A read-only view over a PNG datastream stored in a borrowed byte slice
+/// (e.g. a file read into memory or a memory-mapped file).
+///
+/// Chunk payloads are never copied, which makes `PngRef` cheap for scanning many files.
+/// Use `Png` instead when the chunks need to be edited.
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+}
+
+impl<'a> PngRef<'a> {
+    /// Lists the `ChunkRef`s stored in this `PngRef`
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+
+    /// Searches for a `ChunkRef` with the specified `chunk_type` and returns the first
+    /// matching `ChunkRef` from this `PngRef`.
+    pub fn chunk_by_type(&self, chunk_type_str: &str) -> Result<Option<&ChunkRef<'a>>> {
+        let target_chunk_type = ChunkType::from_str(chunk_type_str)?;
+        Ok(self
+            .chunks
+            .iter()
+            .find(|c| *c.chunk_type() == target_chunk_type))
+    }
+
+    /// Borrows the chunks of the given PNG datastream, enforcing the given `ParseLimits`.
+    pub fn with_limits(bytes: &'a [u8], limits: ParseLimits) -> Result<Self> {
+        check_signature(bytes)?;
+
+        let mut chunks = Vec::new();
+        let mut offset = STANDARD_HEADER.len();
+        while offset < bytes.len() {
+            let chunk = ChunkRef::parse(&bytes[offset..], offset as u64, chunks.len(), &limits)?;
+            offset += chunk.total_length();
+            chunks.push(chunk);
+        }
+
+        Ok(Self { chunks })
+    }
+
+    /// Copies all borrowed chunks into an owned `Png`.
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks.iter().map(ChunkRef::to_chunk).collect())
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = Error;
+
+    /// Borrows the chunks of the given PNG datastream.
+    fn try_from(value: &'a [u8]) -> std::result::Result<Self, Self::Error> {
+        PngRef::with_limits(value, ParseLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_format::testing::testing_bytes;
+
+    #[test]
+    fn test_png_ref_from_bytes() -> Result<()> {
+        let bytes = testing_bytes();
+        let png_ref = PngRef::try_from(bytes.as_slice())?;
+        assert_eq!(png_ref.chunks().len(), 3);
+
+        let chunk = png_ref
+            .chunk_by_type("miDl")?
+            .expect("Should have Some(chunk)");
+        assert_eq!(chunk.data(), b"I am another chunk");
+        Ok(())
+    }
+
+    #[test]
+    fn test_png_ref_to_png() -> Result<()> {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_slice())?.to_png();
+        assert_eq!(png.as_bytes(), bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_png_ref() {
+        let bytes = testing_bytes();
+        assert!(PngRef::try_from(&bytes[..4]).is_err());
+        assert!(PngRef::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PngRef::try_from(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_png_ref_error_position() {
+        let mut bytes = testing_bytes();
+        // Corrupt the chunk type of the second chunk
+        let second_chunk = 8 + 12 + 20;
+        bytes[second_chunk + 5] = b'1';
+        let result = PngRef::try_from(bytes.as_slice());
+        assert!(matches!(
+            result,
+            Err(Error::Malformed { offset, chunk_index: 1, .. }) if offset == second_chunk as u64 + 4
+        ));
+    }
+
+    #[test]
+    fn test_png_ref_limits() {
+        let bytes = testing_bytes();
+        let limits = ParseLimits {
+            max_total_size: 50,
+            ..Default::default()
+        };
+        let result = PngRef::with_limits(bytes.as_slice(), limits);
+        assert!(matches!(
+            result,
+            Err(Error::LimitExceeded { chunk_index: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{Error, Result};
+use std::io::{self, Read};
+
+use crate::img_format::parse::{check_crc, check_signature, malformed, parse_chunk_type};
+use crate::img_format::{Chunk, ParseLimits};
+
+/// Reads a PNG datastream chunk by chunk from any `Read` source.
+///
+/// Only the chunk being read is held in memory, and its CRC is checked as soon as
+/// it has been read, so callers can stop early without consuming the whole stream.
+pub struct PngReader<R: Read> {
+    inner: R,
+    limits: ParseLimits,
+    /// Position of the next byte to read in the datastream
+    offset: u64,
+    /// Index of the next chunk to read
+    chunk_index: usize,
+    finished: bool,
+}
+
+impl<R: Read> PngReader<R> {
+    /// Creates a reader over the given source and checks the PNG signature.
+    pub fn new(inner: R) -> Result<Self> {
+        Self::with_limits(inner, ParseLimits::default())
+    }
+
+    /// Creates a reader enforcing the given `ParseLimits` and checks the PNG signature.
+    pub fn with_limits(inner: R, limits: ParseLimits) -> Result<Self> {
+        let mut reader = Self {
+            inner,
+            limits,
+            offset: 0,
+            chunk_index: 0,
+            finished: false,
+        };
+
+        let mut header: [u8; 8] = [0; 8];
+        let read = reader.read_up_to(&mut header)?;
+        check_signature(&header[..read])?;
+        Ok(reader)
+    }
+
+    /// Reads the next `Chunk` of the stream.
+    /// Returns `None` once the end of the stream is reached.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let result = self.read_chunk();
+        // Do not try to read anything after the end of stream or a broken chunk
+        if !matches!(result, Ok(Some(_))) {
+            self.finished = true;
+        }
+        result
+    }
+
+    /// Unwraps this `PngReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let chunk_offset = self.offset;
+        let chunk_index = self.chunk_index;
+
+        // first 4 bytes for the length
+        let mut length_bytes: [u8; 4] = [0; 4];
+        match self.read_up_to(&mut length_bytes)? {
+            0 => return Ok(None),
+            4 => {}
+            _ => return Err(malformed(chunk_offset, chunk_index, "4-byte chunk length")),
+        }
+        let length = u32::from_be_bytes(length_bytes);
+        self.limits.check_chunk(chunk_offset, chunk_index, length)?;
+
+        // the second 4 bytes for the chunk type
+        let mut chunk_type_bytes: [u8; 4] = [0; 4];
+        self.read_field(&mut chunk_type_bytes, "4-byte chunk type")?;
+        let chunk_type = parse_chunk_type(chunk_offset + 4, chunk_index, chunk_type_bytes)?;
+
+        // the following for chunk data, the length is not trusted for pre-allocation
+        let data_offset = self.offset;
+        let mut data = Vec::new();
+        (&mut self.inner)
+            .take(length as u64)
+            .read_to_end(&mut data)
+            .map_err(Error::BufferReaderErr)?;
+        self.offset += data.len() as u64;
+        if data.len() != length as usize {
+            return Err(malformed(
+                data_offset,
+                chunk_index,
+                format!("{length} bytes of chunk data"),
+            ));
+        }
+
+        // the last 4 bytes for CRC
+        let crc_offset = self.offset;
+        let mut crc_bytes: [u8; 4] = [0; 4];
+        self.read_field(&mut crc_bytes, "4-byte CRC")?;
+        let crc = u32::from_be_bytes(crc_bytes);
+        check_crc(crc_offset, chunk_index, &chunk_type, &data, crc)?;
+
+        self.chunk_index += 1;
+        Ok(Some(Chunk::from_parts(chunk_type, data, crc)))
+    }
+
+    /// Fills the given buffer entirely, failing with the `expected` description otherwise.
+    fn read_field(&mut self, buf: &mut [u8], expected: &str) -> Result<()> {
+        let offset = self.offset;
+        if self.read_up_to(buf)? != buf.len() {
+            return Err(malformed(offset, self.chunk_index, expected));
+        }
+        Ok(())
+    }
+
+    /// Reads until the given buffer is full or the stream ends.
+    /// Returns the number of bytes read.
+    fn read_up_to(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(Error::BufferReaderErr(err)),
+            }
+        }
+        self.offset += filled as u64;
+        Ok(filled)
+    }
+}
+
+impl<R: Read> Iterator for PngReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_format::testing::testing_bytes;
+
+    #[test]
+    fn test_read_all_chunks() -> Result<()> {
+        let bytes = testing_bytes();
+        let chunks = PngReader::new(bytes.as_slice())?.collect::<Result<Vec<Chunk>>>()?;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(&chunks[2].chunk_type().to_string(), "LASt");
+        assert_eq!(chunks[2].data(), b"I am the last chunk");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stop_early() -> Result<()> {
+        let bytes = testing_bytes();
+        let mut reader = PngReader::new(bytes.as_slice())?;
+        let first = reader.next().expect("Should have a chunk")?;
+        assert_eq!(&first.chunk_type().to_string(), "FrSt");
+
+        // Only the first chunk has been consumed from the source
+        let remaining = reader.into_inner();
+        assert_eq!(remaining.len(), bytes.len() - 8 - first.as_bytes().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let chunks: Vec<Result<Chunk>> = PngReader::new(bytes.as_slice()).unwrap().collect();
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(
+            chunks[2],
+            Err(Error::Malformed { chunk_index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let bytes = testing_bytes();
+        let truncated = &bytes[..bytes.len() - 2];
+        let result = PngReader::new(truncated)
+            .unwrap()
+            .collect::<Result<Vec<Chunk>>>();
+        // The CRC of the last chunk starts 4 bytes before the end of the stream
+        let crc_offset = bytes.len() as u64 - 4;
+        assert!(matches!(
+            result,
+            Err(Error::Malformed { offset, chunk_index: 2, .. }) if offset == crc_offset
+        ));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 13;
+        assert!(matches!(
+            PngReader::new(bytes.as_slice()),
+            Err(Error::Malformed { offset: 0, .. })
+        ));
+        assert!(PngReader::new(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let bytes = testing_bytes();
+        let limits = ParseLimits {
+            max_chunks: 2,
+            ..Default::default()
+        };
+        let result = PngReader::with_limits(bytes.as_slice(), limits)
+            .unwrap()
+            .collect::<Result<Vec<Chunk>>>();
+        assert!(matches!(
+            result,
+            Err(Error::LimitExceeded { chunk_index: 2, .. })
+        ));
+
+        let limits = ParseLimits {
+            max_chunk_length: 19,
+            ..Default::default()
+        };
+        let result = PngReader::with_limits(bytes.as_slice(), limits)
+            .unwrap()
+            .collect::<Result<Vec<Chunk>>>();
+        assert!(matches!(
+            result,
+            Err(Error::LimitExceeded {
+                offset: 8,
+                chunk_index: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_huge_declared_length() {
+        let mut bytes = testing_bytes();
+        // Declare a 2GB chunk while only a few bytes follow
+        bytes[8..12].copy_from_slice(&0x7fff_ffffu32.to_be_bytes());
+        let result = PngReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<Chunk>>>();
+        assert!(matches!(
+            result,
+            Err(Error::Malformed {
+                offset: 16,
+                chunk_index: 0,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+use sha2::{Digest, Sha256};
+
+use super::png::TAG;
+use super::MAX_CHUNK_LENGTH;
+use crate::{Error, Result};
+
+/// Every piece of a split payload starts with this line, right after the PNGme tag
+const SPLIT_MAGIC: &[u8] = b"PNGme-split-v1\n";
+/// The magic, the payload ID, the sequence number and the total number of pieces
+const HEADER_LENGTH: usize = SPLIT_MAGIC.len() + 8 + 4 + 4;
+
+/// The most missing pieces listed by `reassemble`
+const MAX_LISTED_MISSING: usize = 5;
+
+/// The default maximum data length of the chunks holding a payload, like the IDAT chunks
+/// written by `Png::encode_pixels`
+pub const DEFAULT_SPLIT_LENGTH: usize = 1 << 20;
+
+/// A piece of a payload split across several tagged chunks of the same type.
+///
+/// Its chunk data is laid out as:
+///
+/// | PNGme tag | magic | payload ID | sequence number | total number of pieces | data |
+///
+/// The payload ID takes eight bytes, the counters four each, big-endian. The ID is taken from
+/// the hash of the payload, so that splitting the same payload always gives the same chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitPiece<'a> {
+    id: [u8; 8],
+    sequence: u32,
+    total: u32,
+    data: &'a [u8],
+}
+
+impl<'a> SplitPiece<'a> {
+    /// Whether the data of a tagged chunk, without its tag, is a piece of a split payload
+    pub fn is_piece(payload: &[u8]) -> bool {
+        payload.starts_with(SPLIT_MAGIC)
+    }
+
+    pub fn id(&self) -> [u8; 8] {
+        self.id
+    }
+
+    /// The position of this piece in the payload, from 0
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.data.len());
+        bytes.extend_from_slice(SPLIT_MAGIC);
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&self.sequence.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes.extend_from_slice(self.data);
+        bytes
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SplitPiece<'a> {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self> {
+        let header = bytes
+            .strip_prefix(SPLIT_MAGIC)
+            .filter(|header| header.len() >= HEADER_LENGTH - SPLIT_MAGIC.len())
+            .ok_or_else(|| Error::InvalidSplit("truncated piece".to_string()))?;
+        let (id, header) = header.split_at(8);
+        let (sequence, header) = header.split_at(4);
+        let (total, data) = header.split_at(4);
+
+        let piece = SplitPiece {
+            id: id.try_into().expect("Checked length"),
+            sequence: u32::from_be_bytes(sequence.try_into().expect("Checked length")),
+            total: u32::from_be_bytes(total.try_into().expect("Checked length")),
+            data,
+        };
+        if piece.sequence >= piece.total {
+            return Err(Error::InvalidSplit(format!(
+                "piece #{} out of {}",
+                piece.sequence + 1,
+                piece.total
+            )));
+        }
+        Ok(piece)
+    }
+}
+
+/// Splits the payload into the data of chunks of at most `max_chunk_length` bytes,
+/// once tagged.
+pub fn split(payload: &[u8], max_chunk_length: usize) -> Result<Vec<Vec<u8>>> {
+    let overhead = TAG.len() + HEADER_LENGTH;
+    if max_chunk_length <= overhead || max_chunk_length > MAX_CHUNK_LENGTH as usize {
+        return Err(Error::InvalidSplit(format!(
+            "the maximum chunk length must be between {} and {MAX_CHUNK_LENGTH} bytes",
+            overhead + 1
+        )));
+    }
+
+    let pieces: Vec<&[u8]> = if payload.is_empty() {
+        vec![payload]
+    } else {
+        payload.chunks(max_chunk_length - overhead).collect()
+    };
+    let total = u32::try_from(pieces.len())
+        .map_err(|_| Error::InvalidSplit("too many pieces".to_string()))?;
+    let id: [u8; 8] = Sha256::digest(payload)[..8]
+        .try_into()
+        .expect("Valid length");
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, data)| {
+            SplitPiece {
+                id,
+                sequence: sequence as u32,
+                total,
+                data,
+            }
+            .as_bytes()
+        })
+        .collect())
+}
+
+/// Reassembles a payload from all of its pieces, in any order.
+pub fn reassemble(pieces: &[SplitPiece]) -> Result<Vec<u8>> {
+    let first = pieces
+        .first()
+        .ok_or_else(|| Error::InvalidSplit("no pieces".to_string()))?;
+    if pieces
+        .iter()
+        .any(|piece| piece.id != first.id || piece.total != first.total)
+    {
+        return Err(Error::InvalidSplit(
+            "the pieces belong to different payloads".to_string(),
+        ));
+    }
+
+    // Sorted rather than slotted by sequence number, as the total comes from the pieces
+    let mut ordered: Vec<&SplitPiece> = pieces.iter().collect();
+    ordered.sort_unstable_by_key(|piece| piece.sequence);
+    if let Some(pair) = ordered
+        .windows(2)
+        .find(|pair| pair[0].sequence == pair[1].sequence)
+    {
+        return Err(Error::InvalidSplit(format!(
+            "piece #{} is duplicated",
+            pair[0].sequence + 1
+        )));
+    }
+
+    // Without duplicates, there are at most as many pieces as the total
+    let missing_count = first.total as usize - ordered.len();
+    if missing_count > 0 {
+        let mut missing: Vec<String> = (0..first.total)
+            .filter(|sequence| {
+                ordered
+                    .binary_search_by_key(sequence, |piece| piece.sequence)
+                    .is_err()
+            })
+            .take(MAX_LISTED_MISSING)
+            .map(|sequence| format!("#{}", sequence + 1))
+            .collect();
+        if missing_count > MAX_LISTED_MISSING {
+            missing.push(format!("{} more", missing_count - MAX_LISTED_MISSING));
+        }
+        return Err(Error::InvalidSplit(format!(
+            "missing pieces {} out of {}",
+            missing.join(", "),
+            first.total
+        )));
+    }
+
+    Ok(ordered
+        .into_iter()
+        .flat_map(|piece| piece.data)
+        .copied()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(pieces: &[Vec<u8>]) -> Result<Vec<SplitPiece<'_>>> {
+        pieces
+            .iter()
+            .map(|piece| SplitPiece::try_from(piece.as_slice()))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_reassemble() -> Result<()> {
+        let payload: Vec<u8> = (0..=255).collect();
+        let max_chunk_length = TAG.len() + HEADER_LENGTH + 100;
+        let pieces = split(&payload, max_chunk_length)?;
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces
+            .iter()
+            .all(|piece| TAG.len() + piece.len() <= max_chunk_length));
+        assert!(pieces.iter().all(|piece| SplitPiece::is_piece(piece)));
+
+        let mut parsed = parse(&pieces)?;
+        assert_eq!(parsed[1].sequence(), 1);
+        assert_eq!(parsed[1].total(), 3);
+        parsed.reverse();
+        assert_eq!(reassemble(&parsed)?, payload);
+
+        assert_eq!(reassemble(&parse(&split(b"", max_chunk_length)?)?)?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reassemble_invalid() -> Result<()> {
+        let payload: Vec<u8> = (0..=255).collect();
+        let max_chunk_length = TAG.len() + HEADER_LENGTH + 100;
+        let pieces = split(&payload, max_chunk_length)?;
+        let pieces = parse(&pieces)?;
+
+        assert!(matches!(
+            reassemble(&[pieces[0], pieces[2]]),
+            Err(Error::InvalidSplit(message)) if message == "missing pieces #2 out of 3"
+        ));
+        assert!(reassemble(&[pieces[0], pieces[1], pieces[1]]).is_err());
+
+        // A forged total is reported, not allocated
+        let forged = SplitPiece {
+            total: u32::MAX,
+            ..pieces[0]
+        };
+        assert!(matches!(
+            reassemble(&[forged]),
+            Err(Error::InvalidSplit(message))
+                if message == format!(
+                    "missing pieces #2, #3, #4, #5, #6, {} more out of {}",
+                    u32::MAX - 6,
+                    u32::MAX
+                )
+        ));
+
+        let others = split(b"Another payload", max_chunk_length)?;
+        let other = SplitPiece::try_from(others[0].as_slice())?;
+        assert!(reassemble(&[pieces[0], pieces[1], pieces[2], other]).is_err());
+
+        assert!(split(&payload, TAG.len() + HEADER_LENGTH).is_err());
+        assert!(SplitPiece::try_from(&pieces[0].as_bytes()[..HEADER_LENGTH - 1]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Fixtures shared by the unit tests
+
+use std::str::FromStr;
+
+use super::png::STANDARD_HEADER;
+use super::{Chunk, ChunkPlacement, ChunkType, ColorType, Ihdr, Interlace, PixelBuffer, Plte, Png};
+use crate::Result;
+
+pub(crate) fn testing_chunks() -> Vec<Chunk> {
+    vec![
+        Chunk::new(
+            ChunkType::from_str("FrSt").unwrap(),
+            b"I am the first chunk",
+        ),
+        Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk"),
+        Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk"),
+    ]
+}
+
+/// The PNG signature followed by the `testing_chunks`, without IHDR nor IEND
+pub(crate) fn testing_bytes() -> Vec<u8> {
+    STANDARD_HEADER
+        .iter()
+        .copied()
+        .chain(testing_chunks().iter().flat_map(|chunk| chunk.as_bytes()))
+        .collect()
+}
+
+/// IHDR, IDAT and IEND chunks around the given image data, which is not meant to be decoded
+pub(crate) fn testing_png(idat: &[u8]) -> Result<Png> {
+    let chunks = [("IHDR", &[1, 2, 3][..]), ("IDAT", idat), ("IEND", &[])]
+        .iter()
+        .map(|(chunk_type, data)| Ok(Chunk::new(ChunkType::from_str(chunk_type)?, data)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Png::from_chunks(chunks))
+}
+
+/// A decodable image whose bytes of pixels count up from 0, with a gray palette if indexed
+pub(crate) fn testing_image(
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlace: Interlace,
+) -> Result<Png> {
+    let ihdr = Ihdr::new(width, height, bit_depth, color_type, interlace)?;
+    let iend = Chunk::new(ChunkType::from_str("IEND")?, &[]);
+    let mut png = Png::from_chunks(vec![ihdr.to_chunk(), iend]);
+    if color_type == ColorType::Indexed {
+        let palette = Plte::new((0..=255).map(|i| [i, i, i]).collect())?;
+        png.insert_chunk(palette.to_chunk(), false, ChunkPlacement::Index(1))?;
+    }
+    let samples = width as usize * height as usize * color_type.channels();
+    let data: Vec<u8> = (0..samples * bit_depth as usize / 8)
+        .map(|i| i as u8)
+        .collect();
+    let pixels = PixelBuffer::new(width, height, bit_depth, color_type, data)?;
+    png.encode_pixels(&pixels, interlace)?;
+    Ok(png)
+}
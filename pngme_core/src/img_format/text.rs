@@ -0,0 +1,459 @@
+use crate::{Error, Result};
+use std::fmt::Display;
+
+use crate::img_format::zlib::{deflate, inflate};
+use crate::img_format::{Chunk, ChunkType};
+
+pub(crate) const TEXT: [u8; 4] = *b"tEXt";
+pub(crate) const ZTXT: [u8; 4] = *b"zTXt";
+pub(crate) const ITXT: [u8; 4] = *b"iTXt";
+
+/// The maximum length of a decompressed text, so that a small chunk cannot inflate without limit
+pub const MAX_TEXT_LENGTH: usize = 16 * 1024 * 1024;
+
+/// An uncompressed Latin-1 text (tEXt chunk)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+}
+
+impl TextChunk {
+    /// Creates a tEXt chunk, both the keyword and the text must be Latin-1.
+    pub fn new(keyword: &str, text: &str) -> Result<TextChunk> {
+        validate_keyword(keyword)?;
+        to_latin1(text)?;
+        Ok(Self {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns this text as a tEXt `Chunk`.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let data = [to_latin1(&self.keyword)?, vec![0], to_latin1(&self.text)?].concat();
+        Ok(Chunk::new(ChunkType::try_from(TEXT)?, &data))
+    }
+}
+
+impl TryFrom<&[u8]> for TextChunk {
+    type Error = Error;
+
+    /// Parses the data of a tEXt chunk.
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        let (keyword, text) = split_keyword(value)?;
+        Ok(Self {
+            keyword,
+            text: from_latin1(text),
+        })
+    }
+}
+
+/// A zlib-compressed Latin-1 text (zTXt chunk)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+    keyword: String,
+    text: String,
+}
+
+impl CompressedTextChunk {
+    /// Creates a zTXt chunk, both the keyword and the text must be Latin-1.
+    pub fn new(keyword: &str, text: &str) -> Result<CompressedTextChunk> {
+        validate_keyword(keyword)?;
+        to_latin1(text)?;
+        Ok(Self {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns this text as a zTXt `Chunk`.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let data = [
+            to_latin1(&self.keyword)?,
+            // null separator followed by the compression method 0 (zlib)
+            vec![0, 0],
+            deflate(&to_latin1(&self.text)?)?,
+        ]
+        .concat();
+        Ok(Chunk::new(ChunkType::try_from(ZTXT)?, &data))
+    }
+}
+
+impl TryFrom<&[u8]> for CompressedTextChunk {
+    type Error = Error;
+
+    /// Parses the data of a zTXt chunk.
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        let (keyword, rest) = split_keyword(value)?;
+        let (&method, compressed) = rest
+            .split_first()
+            .ok_or(invalid("missing compression method"))?;
+        check_compression_method(method)?;
+        Ok(Self {
+            keyword,
+            text: from_latin1(&inflate(compressed, MAX_TEXT_LENGTH)?),
+        })
+    }
+}
+
+/// An international UTF-8 text with an optional language tag, optionally compressed (iTXt chunk)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    keyword: String,
+    compressed: bool,
+    language_tag: String,
+    translated_keyword: String,
+    text: String,
+}
+
+impl InternationalTextChunk {
+    /// Creates an iTXt chunk.
+    /// The language tag (e.g. "en", "vi-VN") may be empty when the language is unknown.
+    pub fn new(
+        keyword: &str,
+        text: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        compressed: bool,
+    ) -> Result<InternationalTextChunk> {
+        validate_keyword(keyword)?;
+        validate_language_tag(language_tag)?;
+        if translated_keyword.contains('\0') {
+            return Err(invalid("the translated keyword must not contain a null"));
+        }
+        Ok(Self {
+            keyword: keyword.to_string(),
+            compressed,
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Returns this text as an iTXt `Chunk`.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let text = if self.compressed {
+            deflate(self.text.as_bytes())?
+        } else {
+            self.text.as_bytes().to_vec()
+        };
+        let data = [
+            to_latin1(&self.keyword)?,
+            // null separator, compression flag and compression method 0 (zlib)
+            vec![0, self.compressed as u8, 0],
+            self.language_tag.as_bytes().to_vec(),
+            vec![0],
+            self.translated_keyword.as_bytes().to_vec(),
+            vec![0],
+            text,
+        ]
+        .concat();
+        Ok(Chunk::new(ChunkType::try_from(ITXT)?, &data))
+    }
+}
+
+impl TryFrom<&[u8]> for InternationalTextChunk {
+    type Error = Error;
+
+    /// Parses the data of an iTXt chunk.
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        let (keyword, rest) = split_keyword(value)?;
+        let (flags, rest) = rest
+            .split_at_checked(2)
+            .ok_or(invalid("missing compression flag and method"))?;
+        let compressed = match flags[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(invalid(format!("unknown compression flag {flag}"))),
+        };
+        if compressed {
+            check_compression_method(flags[1])?;
+        }
+
+        let (language_tag, rest) = split_null(rest).ok_or(invalid("missing language tag"))?;
+        let language_tag = from_utf8(language_tag)?;
+        validate_language_tag(&language_tag)?;
+        let (translated_keyword, text) =
+            split_null(rest).ok_or(invalid("missing translated keyword"))?;
+        let text = if compressed {
+            from_utf8(&inflate(text, MAX_TEXT_LENGTH)?)?
+        } else {
+            from_utf8(text)?
+        };
+
+        Ok(Self {
+            keyword,
+            compressed,
+            language_tag,
+            translated_keyword: from_utf8(translated_keyword)?,
+            text,
+        })
+    }
+}
+
+/// Any of the textual chunks defined by the PNG spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextualChunk {
+    Text(TextChunk),
+    Compressed(CompressedTextChunk),
+    International(InternationalTextChunk),
+}
+
+impl TextualChunk {
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextualChunk::Text(chunk) => chunk.keyword(),
+            TextualChunk::Compressed(chunk) => chunk.keyword(),
+            TextualChunk::International(chunk) => chunk.keyword(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextualChunk::Text(chunk) => chunk.text(),
+            TextualChunk::Compressed(chunk) => chunk.text(),
+            TextualChunk::International(chunk) => chunk.text(),
+        }
+    }
+
+    /// Returns this text as a tEXt, zTXt or iTXt `Chunk`.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        match self {
+            TextualChunk::Text(chunk) => chunk.to_chunk(),
+            TextualChunk::Compressed(chunk) => chunk.to_chunk(),
+            TextualChunk::International(chunk) => chunk.to_chunk(),
+        }
+    }
+
+    /// Returns true if the given chunk type is one of the textual chunk types.
+    pub fn is_textual(chunk_type: &ChunkType) -> bool {
+        [TEXT, ZTXT, ITXT].contains(&chunk_type.as_bytes())
+    }
+}
+
+impl TryFrom<&Chunk> for TextualChunk {
+    type Error = Error;
+
+    fn try_from(value: &Chunk) -> std::result::Result<Self, Self::Error> {
+        match value.chunk_type().as_bytes() {
+            TEXT => TextChunk::try_from(value.data()).map(TextualChunk::Text),
+            ZTXT => CompressedTextChunk::try_from(value.data()).map(TextualChunk::Compressed),
+            ITXT => InternationalTextChunk::try_from(value.data()).map(TextualChunk::International),
+            _ => Err(invalid(format!(
+                "{} is not a textual chunk",
+                value.chunk_type()
+            ))),
+        }
+    }
+}
+
+impl Display for TextualChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextualChunk::Text(chunk) => write!(f, "tEXt {}: {}", chunk.keyword, chunk.text),
+            TextualChunk::Compressed(chunk) => {
+                write!(f, "zTXt {}: {}", chunk.keyword, chunk.text)
+            }
+            TextualChunk::International(chunk) if chunk.language_tag.is_empty() => {
+                write!(f, "iTXt {}: {}", chunk.keyword, chunk.text)
+            }
+            TextualChunk::International(chunk) => write!(
+                f,
+                "iTXt {} [{}]: {}",
+                chunk.keyword, chunk.language_tag, chunk.text
+            ),
+        }
+    }
+}
+
+/// A keyword is 1-79 printable Latin-1 characters, without leading, trailing
+/// or consecutive spaces.
+fn validate_keyword(keyword: &str) -> Result<()> {
+    let bytes = to_latin1(keyword)?;
+    if bytes.is_empty() || bytes.len() > 79 {
+        return Err(invalid("the keyword must be 1 to 79 characters long"));
+    }
+    if bytes
+        .iter()
+        .any(|&b| !(32..=126).contains(&b) && !(161..=255).contains(&b))
+    {
+        return Err(invalid(
+            "the keyword must only contain printable characters",
+        ));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(invalid(
+            "the keyword must not have leading, trailing or consecutive spaces",
+        ));
+    }
+    Ok(())
+}
+
+/// A language tag is made of alphanumeric words separated by hyphens, as defined in RFC 3066.
+fn validate_language_tag(language_tag: &str) -> Result<()> {
+    if language_tag.is_empty() {
+        return Ok(());
+    }
+    if language_tag.split('-').any(|word| {
+        word.is_empty() || word.len() > 8 || !word.chars().all(|c| c.is_ascii_alphanumeric())
+    }) {
+        return Err(invalid(format!("invalid language tag {language_tag}")));
+    }
+    Ok(())
+}
+
+fn check_compression_method(method: u8) -> Result<()> {
+    if method != 0 {
+        return Err(invalid(format!("unknown compression method {method}")));
+    }
+    Ok(())
+}
+
+/// Splits the null-terminated keyword from the rest of the chunk data.
+fn split_keyword(data: &[u8]) -> Result<(String, &[u8])> {
+    let (keyword, rest) = split_null(data).ok_or(invalid("missing keyword separator"))?;
+    let keyword = from_latin1(keyword);
+    validate_keyword(&keyword)?;
+    Ok((keyword, rest))
+}
+
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let position = data.iter().position(|&b| b == 0)?;
+    Some((&data[..position], &data[position + 1..]))
+}
+
+/// Every Latin-1 byte maps to the Unicode code point of the same value.
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn to_latin1(s: &str) -> Result<Vec<u8>> {
+    s.chars()
+        .map(|c| u8::try_from(c).map_err(|_| invalid(format!("'{c}' is not a Latin-1 character"))))
+        .collect()
+}
+
+fn from_utf8(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::StringConversion)
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidText(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_chunk_roundtrip() -> Result<()> {
+        let text = TextChunk::new("Author", "Cuong Nguyen")?;
+        let chunk = text.to_chunk()?;
+        assert_eq!(&chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Author\0Cuong Nguyen");
+        assert_eq!(TextualChunk::try_from(&chunk)?, TextualChunk::Text(text));
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_chunk_latin1() -> Result<()> {
+        let chunk = TextChunk::new("Comment", "Café")?.to_chunk()?;
+        // 'é' is stored as a single Latin-1 byte
+        assert_eq!(chunk.data(), b"Comment\0Caf\xe9");
+        assert_eq!(TextualChunk::try_from(&chunk)?.text(), "Café");
+
+        assert!(TextChunk::new("Comment", "Nguyễn").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_text_chunk_roundtrip() -> Result<()> {
+        let long_text = "This is where your secret message will be!".repeat(20);
+        let text = CompressedTextChunk::new("Description", &long_text)?;
+        let chunk = text.to_chunk()?;
+        assert_eq!(&chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().len() < long_text.len());
+        assert_eq!(
+            TextualChunk::try_from(&chunk)?,
+            TextualChunk::Compressed(text)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_international_text_chunk_roundtrip() -> Result<()> {
+        for compressed in [false, true] {
+            let text =
+                InternationalTextChunk::new("Title", "Chú chó", "vi-VN", "Tiêu đề", compressed)?;
+            let chunk = text.to_chunk()?;
+            assert_eq!(&chunk.chunk_type().to_string(), "iTXt");
+            let parsed = TextualChunk::try_from(&chunk)?;
+            assert_eq!(parsed.to_string(), "iTXt Title [vi-VN]: Chú chó");
+            assert_eq!(parsed, TextualChunk::International(text));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_keyword() {
+        let too_long = "k".repeat(80);
+        for keyword in [
+            "",
+            " Title",
+            "Title ",
+            "Ti  tle",
+            "Ti\ntle",
+            too_long.as_str(),
+        ] {
+            assert!(TextChunk::new(keyword, "text").is_err(), "{keyword:?}");
+        }
+        assert!(TextChunk::new("Ti tle", "text").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_text_data() {
+        // No keyword separator
+        assert!(TextChunk::try_from(&b"Title"[..]).is_err());
+        // Unknown compression method
+        assert!(CompressedTextChunk::try_from(&b"Title\0\x01abc"[..]).is_err());
+        // Invalid language tag
+        assert!(InternationalTextChunk::try_from(&b"Title\0\0\0en_US\0\0text"[..]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{Error, Result};
+
+use crate::img_format::ihdr::IHDR;
+use crate::img_format::plte::PLTE;
+use crate::img_format::png::{IDAT, IEND};
+use crate::img_format::{Chunk, ColorType, Ihdr, Plte};
+
+/// Checks the ordering and multiplicity of the critical chunks as required by the PNG spec:
+/// - IHDR comes first and only once,
+/// - PLTE comes at most once, before the first IDAT, and only when the color type allows it,
+/// - IDAT chunks are present and consecutive,
+/// - IEND comes last and only once,
+/// - no other critical chunk is present.
+pub(crate) fn validate_chunks(chunks: &[Chunk]) -> Result<()> {
+    let ihdr = match chunks.first() {
+        Some(first) if first.chunk_type().as_bytes() == IHDR => Ihdr::try_from(first)?,
+        _ => return Err(invalid("IHDR must be the first chunk")),
+    };
+
+    let mut plte: Option<Plte> = None;
+    let mut first_idat: Option<usize> = None;
+    let mut last_idat: Option<usize> = None;
+    let mut iend: Option<usize> = None;
+
+    for (index, chunk) in chunks.iter().enumerate().skip(1) {
+        let chunk_type = chunk.chunk_type();
+        if iend.is_some() {
+            return Err(invalid(format!("{chunk_type} found after IEND")));
+        }
+
+        match chunk_type.as_bytes() {
+            IHDR => return Err(invalid("IHDR must appear only once")),
+            PLTE => {
+                if plte.is_some() {
+                    return Err(invalid("PLTE must appear at most once"));
+                }
+                if first_idat.is_some() {
+                    return Err(invalid("PLTE must come before the first IDAT"));
+                }
+                plte = Some(Plte::try_from(chunk)?);
+            }
+            IDAT => {
+                if last_idat.is_some_and(|last| last + 1 != index) {
+                    return Err(invalid("IDAT chunks must be consecutive"));
+                }
+                first_idat.get_or_insert(index);
+                last_idat = Some(index);
+            }
+            IEND => iend = Some(index),
+            _ if chunk_type.is_critical() => {
+                return Err(invalid(format!("unknown critical chunk {chunk_type}")))
+            }
+            _ => {}
+        }
+    }
+
+    if first_idat.is_none() {
+        return Err(invalid("at least one IDAT chunk is required"));
+    }
+    if iend.is_none() {
+        return Err(invalid("IEND must be the last chunk"));
+    }
+
+    match (ihdr.color_type(), plte) {
+        (ColorType::Indexed, None) => Err(invalid("PLTE is required for indexed-color images")),
+        (ColorType::Indexed, Some(plte)) if plte.entries().len() > 1 << ihdr.bit_depth() => {
+            Err(invalid(format!(
+                "PLTE has more entries than a {}-bit index allows",
+                ihdr.bit_depth()
+            )))
+        }
+        (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(_)) => {
+            Err(invalid("PLTE must not appear in grayscale images"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidStructure(reason.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_format::{ChunkType, Interlace};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn ihdr_chunk(color_type: ColorType) -> Chunk {
+        Ihdr::new(1, 1, 8, color_type, Interlace::None)
+            .unwrap()
+            .to_chunk()
+    }
+
+    fn valid_chunks() -> Vec<Chunk> {
+        vec![
+            ihdr_chunk(ColorType::Rgb),
+            chunk("teXt", b"ancillary"),
+            chunk("IDAT", b"first"),
+            chunk("IDAT", b"second"),
+            chunk("IEND", b""),
+        ]
+    }
+
+    #[test]
+    fn test_valid_chunks() {
+        assert!(validate_chunks(&valid_chunks()).is_ok());
+    }
+
+    #[test]
+    fn test_ihdr_not_first() {
+        let mut chunks = valid_chunks();
+        chunks.swap(0, 1);
+        assert!(matches!(
+            validate_chunks(&chunks),
+            Err(Error::InvalidStructure(_))
+        ));
+    }
+
+    #[test]
+    fn test_chunk_after_iend() {
+        let mut chunks = valid_chunks();
+        chunks.push(chunk("teXt", b"trailing"));
+        assert!(validate_chunks(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_missing_iend() {
+        let mut chunks = valid_chunks();
+        chunks.pop();
+        assert!(validate_chunks(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_non_consecutive_idat() {
+        let mut chunks = valid_chunks();
+        chunks.insert(3, chunk("teXt", b"in between"));
+        assert!(validate_chunks(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_plte_rules() {
+        let plte = Plte::new(vec![[0, 0, 0]]).unwrap().to_chunk();
+
+        let mut chunks = valid_chunks();
+        chunks.insert(1, plte.clone());
+        assert!(validate_chunks(&chunks).is_ok());
+
+        // PLTE after IDAT
+        let mut chunks = valid_chunks();
+        chunks.insert(4, plte.clone());
+        assert!(validate_chunks(&chunks).is_err());
+
+        // Indexed-color image without PLTE
+        let mut chunks = valid_chunks();
+        chunks[0] = ihdr_chunk(ColorType::Indexed);
+        assert!(validate_chunks(&chunks).is_err());
+
+        // Grayscale image with PLTE
+        let mut chunks = valid_chunks();
+        chunks[0] = ihdr_chunk(ColorType::Grayscale);
+        chunks.insert(1, plte);
+        assert!(validate_chunks(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_unknown_critical_chunk() {
+        let mut chunks = valid_chunks();
+        chunks.insert(1, chunk("RuSt", b"critical"));
+        assert!(validate_chunks(&chunks).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{Error, Result};
+use std::io::Write;
+
+use crate::img_format::chunk::PNG_CRC;
+use crate::img_format::png::STANDARD_HEADER;
+use crate::img_format::Chunk;
+
+/// Writes a PNG datastream chunk by chunk into any `Write` sink.
+///
+/// The signature is written on creation, then every `Chunk` is written as soon as it
+/// is given, with its CRC computed over the bytes actually streamed out.
+pub struct PngWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Creates a writer over the given sink and writes the PNG signature.
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner
+            .write_all(&STANDARD_HEADER)
+            .map_err(Error::BufferWriterErr)?;
+        Ok(Self { inner })
+    }
+
+    /// Writes the given `Chunk` as described by the PNG spec.
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        let chunk_type = chunk.chunk_type().as_bytes();
+        let mut digest = PNG_CRC.digest();
+
+        self.write(&chunk.length().to_be_bytes())?;
+        self.write(&chunk_type)?;
+        digest.update(&chunk_type);
+        self.write(chunk.data())?;
+        digest.update(chunk.data());
+        self.write(&digest.finalize().to_be_bytes())
+    }
+
+    /// Flushes the written chunks and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.flush().map_err(Error::BufferWriterErr)?;
+        Ok(self.inner)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes).map_err(Error::BufferWriterErr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_format::testing::{testing_bytes, testing_chunks};
+    use crate::img_format::PngReader;
+
+    #[test]
+    fn test_write_chunks() -> Result<()> {
+        let chunks = testing_chunks();
+        let mut writer = PngWriter::new(Vec::new())?;
+        for chunk in chunks.iter() {
+            writer.write_chunk(chunk)?;
+        }
+        let bytes = writer.finish()?;
+        assert_eq!(bytes, testing_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() -> Result<()> {
+        let mut writer = PngWriter::new(Vec::new())?;
+        for chunk in testing_chunks().iter() {
+            writer.write_chunk(chunk)?;
+        }
+        let bytes = writer.finish()?;
+
+        let chunks = PngReader::new(bytes.as_slice())?.collect::<Result<Vec<Chunk>>>()?;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].data(), b"I am the last chunk");
+        Ok(())
+    }
+}
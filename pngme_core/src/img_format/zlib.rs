@@ -0,0 +1,107 @@
+use crate::crypto::SecretBytes;
+use crate::{Error, Result};
+use std::io::{self, Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Compresses the given bytes into a zlib datastream, as used by PNG.
+pub(crate) fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    deflate_into(data, &mut compressed)?;
+    Ok(compressed)
+}
+
+/// Compresses the given bytes into a zlib datastream appended to `out`.
+pub(crate) fn deflate_into(data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(out, Compression::default());
+    encoder.write_all(data).map_err(Error::CompressionErr)?;
+    encoder.finish().map_err(Error::CompressionErr)?;
+    Ok(())
+}
+
+/// Decompresses the given zlib datastream, refusing to produce more than `max_length` bytes.
+pub(crate) fn inflate(data: &[u8], max_length: usize) -> Result<Vec<u8>> {
+    read_limited(ZlibDecoder::new(data), max_length)
+}
+
+/// Reads a decompressing reader to the end, refusing to produce more than `max_length` bytes.
+fn read_limited(reader: impl Read, max_length: usize) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    reader
+        // One extra byte tells whether the limit has been exceeded
+        .take(max_length as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(Error::DecompressionErr)?;
+    if decompressed.len() > max_length {
+        return Err(Error::DecompressedTooLarge(max_length));
+    }
+    Ok(decompressed)
+}
+
+/// Decompresses the given secret zlib datastream as `inflate` does, see `read_limited_secret`.
+pub(crate) fn inflate_secret(data: &[u8], max_length: usize) -> Result<SecretBytes> {
+    read_limited_secret(ZlibDecoder::new(data), max_length)
+}
+
+/// Reads a decompressing reader to the end as `read_limited` does, into a secret buffer
+/// allocated once at `max_length + 1` bytes, see `SecretBytes`.
+pub(crate) fn read_limited_secret(mut reader: impl Read, max_length: usize) -> Result<SecretBytes> {
+    let mut buffer = SecretBytes::zeroed(max_length + 1);
+    let mut length = 0;
+    while length < buffer.len() {
+        match reader.read(&mut buffer.as_mut_slice()[length..]) {
+            Ok(0) => break,
+            Ok(read) => length += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::DecompressionErr(err)),
+        }
+    }
+    if length > max_length {
+        return Err(Error::DecompressedTooLarge(max_length));
+    }
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zlib_roundtrip() -> Result<()> {
+        let data = b"This is where your secret message will be!".repeat(10);
+        let compressed = deflate(&data)?;
+        assert!(compressed.len() < data.len());
+        assert_eq!(inflate(&compressed, data.len())?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inflate_limit() -> Result<()> {
+        let compressed = deflate(&[0; 1000])?;
+        assert!(matches!(
+            inflate(&compressed, 999),
+            Err(Error::DecompressedTooLarge(999))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inflate_secret() -> Result<()> {
+        let data = b"This is where your secret message will be!".repeat(10);
+        let compressed = deflate(&data)?;
+        assert_eq!(&*inflate_secret(&compressed, data.len())?, data.as_slice());
+        assert!(matches!(
+            inflate_secret(&compressed, data.len() - 1),
+            Err(Error::DecompressedTooLarge(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inflate_garbage() {
+        assert!(inflate(b"not a zlib stream", 100).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 pub mod crypto;
 mod error;
 pub mod img_format;
+pub mod payload;
+pub mod stego;
 
 pub use crate::error::Error;
 type Result<T> = std::result::Result<T, Error>;
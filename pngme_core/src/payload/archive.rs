@@ -0,0 +1,380 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{check_length, read_array, read_string};
+use crate::crypto::SecretBytes;
+use crate::{Error, Result};
+
+/// Every archive payload starts with this line
+const ARCHIVE_MAGIC: &[u8] = b"PNGme-archive-v1\n";
+
+const DIRECTORY_KIND: u8 = 0;
+const FILE_KIND: u8 = 1;
+
+/// A directory or a file of an archive, under a relative path with `/` separators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    path: String,
+    mode: u32,
+    mtime: u64,
+    /// The content of a file, none for a directory
+    data: Option<SecretBytes>,
+}
+
+impl ArchiveEntry {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The permission bits, e.g. `0o644`
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The modification time, in seconds since the Unix epoch
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.data.is_none()
+    }
+
+    pub fn data(&self) -> Option<&SecretBytes> {
+        self.data.as_ref()
+    }
+
+    fn encoded_len(&self) -> usize {
+        let data_len = self.data.as_ref().map_or(0, |data| 8 + data.len());
+        1 + 2 + self.path.len() + 4 + 8 + data_len
+    }
+}
+
+/// A directory tree hidden as a whole.
+///
+/// It is laid out as the magic followed by its entries, parents first:
+///
+/// | kind | path length | path | mode | mtime | (size | data, for files) |
+///
+/// The kind takes one byte, the path length two, the mode four and the mtime and size eight,
+/// all big-endian. Symbolic links are not supported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Archive {
+    entries: Vec<ArchiveEntry>,
+}
+
+impl Archive {
+    /// Whether the decrypted payload is an archive rather than a file or a plain message
+    pub fn is_archive(payload: &[u8]) -> bool {
+        payload.starts_with(ARCHIVE_MAGIC)
+    }
+
+    /// Packs the content of a directory, sorted by path. The directory itself is not part of it.
+    pub fn from_dir(dir: &Path) -> Result<Archive> {
+        let mut archive = Archive::default();
+        archive.add_dir(dir, "")?;
+        Ok(archive)
+    }
+
+    fn add_dir(&mut self, dir: &Path, prefix: &str) -> Result<()> {
+        let mut children = fs::read_dir(dir)
+            .and_then(|children| children.collect::<io::Result<Vec<_>>>())
+            .map_err(Error::FileOpenErr)?;
+        children.sort_by_key(|child| child.file_name());
+
+        for child in children {
+            let name = child.file_name().into_string().map_err(|name| {
+                Error::InvalidPayload(format!("the file name {name:?} is not UTF-8"))
+            })?;
+            let path = format!("{prefix}{name}");
+            check_length(path.len(), "path")?;
+            let metadata = fs::symlink_metadata(child.path()).map_err(Error::FileOpenErr)?;
+            let data = if metadata.is_dir() {
+                None
+            } else if metadata.is_file() {
+                let data = fs::read(child.path()).map_err(Error::FileOpenErr)?;
+                Some(SecretBytes::new(data))
+            } else {
+                return Err(Error::InvalidPayload(format!(
+                    "{path} is neither a file nor a directory"
+                )));
+            };
+
+            let is_dir = data.is_none();
+            self.entries.push(ArchiveEntry {
+                path: path.clone(),
+                mode: mode_of(&metadata),
+                mtime: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |mtime| mtime.as_secs()),
+                data,
+            });
+            if is_dir {
+                self.add_dir(&child.path(), &format!("{path}/"))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn as_bytes(&self) -> SecretBytes {
+        // At its final capacity, see `SecretBytes`
+        let length = ARCHIVE_MAGIC.len()
+            + self
+                .entries
+                .iter()
+                .map(ArchiveEntry::encoded_len)
+                .sum::<usize>();
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend_from_slice(ARCHIVE_MAGIC);
+        for entry in &self.entries {
+            let kind = if entry.is_dir() {
+                DIRECTORY_KIND
+            } else {
+                FILE_KIND
+            };
+            bytes.push(kind);
+            bytes.extend_from_slice(&(entry.path.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(entry.path.as_bytes());
+            bytes.extend_from_slice(&entry.mode.to_be_bytes());
+            bytes.extend_from_slice(&entry.mtime.to_be_bytes());
+            if let Some(data) = &entry.data {
+                bytes.extend_from_slice(&(data.len() as u64).to_be_bytes());
+                bytes.extend_from_slice(data);
+            }
+        }
+        SecretBytes::new(bytes)
+    }
+
+    /// Restores the entries under `dir`, which is created if needed.
+    ///
+    /// Paths escaping `dir` are rejected when reading the archive, and existing files are never
+    /// overwritten nor followed when they are symbolic links.
+    pub fn extract_to(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).map_err(Error::FileWriteErr)?;
+
+        for entry in &self.entries {
+            let path = Path::new(&entry.path);
+            let Some(data) = &entry.data else {
+                create_dirs(dir, path)?;
+                continue;
+            };
+            if let Some(parent) = path.parent() {
+                create_dirs(dir, parent)?;
+            }
+            let target = dir.join(path);
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&target)
+                .map_err(Error::FileWriteErr)?;
+            io::Write::write_all(&mut file, data).map_err(Error::FileWriteErr)?;
+            file.set_modified(mtime_of(entry))
+                .map_err(Error::FileWriteErr)?;
+            set_mode(&target, entry.mode)?;
+        }
+
+        // Last and children first, so that writing into a directory does not change its
+        // mtime, nor is prevented by its mode
+        for entry in self.entries.iter().rev().filter(|entry| entry.is_dir()) {
+            let target = dir.join(&entry.path);
+            #[cfg(unix)]
+            fs::File::open(&target)
+                .and_then(|dir| dir.set_modified(mtime_of(entry)))
+                .map_err(Error::FileWriteErr)?;
+            set_mode(&target, entry.mode)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&[u8]> for Archive {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let mut rest = bytes
+            .strip_prefix(ARCHIVE_MAGIC)
+            .ok_or_else(|| Error::InvalidPayload("not an archive".to_string()))?;
+
+        let mut entries = Vec::new();
+        while !rest.is_empty() {
+            let [kind] = read_array(&mut rest, "entry kind")?;
+            let path = read_string(&mut rest, "path")?;
+            check_path(&path)?;
+            let mode = u32::from_be_bytes(read_array(&mut rest, "mode")?);
+            let mtime = u64::from_be_bytes(read_array(&mut rest, "mtime")?);
+            let data = match kind {
+                DIRECTORY_KIND => None,
+                FILE_KIND => {
+                    let size = u64::from_be_bytes(read_array(&mut rest, "size")?);
+                    if (rest.len() as u64) < size {
+                        return Err(Error::InvalidPayload(format!("truncated {path}")));
+                    }
+                    let (data, tail) = rest.split_at(size as usize);
+                    rest = tail;
+                    Some(SecretBytes::from(data))
+                }
+                kind => {
+                    return Err(Error::InvalidPayload(format!(
+                        "unknown kind {kind} of {path}"
+                    )))
+                }
+            };
+            entries.push(ArchiveEntry {
+                path,
+                mode,
+                mtime,
+                data,
+            });
+        }
+        Ok(Archive { entries })
+    }
+}
+
+/// A path is relative and made of plain names only, so that extracting it cannot escape
+/// the destination directory.
+fn check_path(path: &str) -> Result<()> {
+    let is_safe = !path.is_empty()
+        && !path.contains(['\\', '\0'])
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !is_safe {
+        return Err(Error::InvalidPayload(format!("unsafe path {path:?}")));
+    }
+    Ok(())
+}
+
+/// Creates the directories of the relative path under `root`, refusing to go through
+/// anything but directories.
+fn create_dirs(root: &Path, path: &Path) -> Result<()> {
+    let mut current = root.to_path_buf();
+    for component in path.components() {
+        current.push(component);
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(Error::InvalidPayload(format!(
+                    "{} already exists and is not a directory",
+                    current.display()
+                )))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir(&current).map_err(Error::FileWriteErr)?
+            }
+            Err(err) => return Err(Error::FileWriteErr(err)),
+        }
+    }
+    Ok(())
+}
+
+fn mtime_of(entry: &ArchiveEntry) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(entry.mtime)
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o777
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+/// Applies the permission bits, without the setuid, setgid and sticky bits
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    let permissions = std::os::unix::fs::PermissionsExt::from_mode(mode & 0o777);
+    fs::set_permissions(path, permissions).map_err(Error::FileWriteErr)
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    let mut permissions = fs::metadata(path)
+        .map_err(Error::FileWriteErr)?
+        .permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions).map_err(Error::FileWriteErr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_archive_roundtrip() -> Result<()> {
+        let source = testing_dir("pngme_test_archive_source");
+        fs::create_dir_all(source.join("keys/old")).map_err(Error::FileWriteErr)?;
+        fs::write(source.join("config.toml"), "verbose = true").map_err(Error::FileWriteErr)?;
+        fs::write(source.join("keys/alice"), [0, 159, 146, 150]).map_err(Error::FileWriteErr)?;
+
+        let archive = Archive::from_dir(&source)?;
+        let paths: Vec<_> = archive.entries().iter().map(ArchiveEntry::path).collect();
+        assert_eq!(paths, ["config.toml", "keys", "keys/alice", "keys/old"]);
+
+        let bytes = archive.as_bytes();
+        assert!(Archive::is_archive(&bytes));
+        assert!(!Archive::is_archive(b"FooBar!"));
+        let parsed = Archive::try_from(&*bytes)?;
+        assert_eq!(parsed, archive);
+
+        let target = testing_dir("pngme_test_archive_target");
+        parsed.extract_to(&target)?;
+        assert_eq!(
+            fs::read(target.join("keys/alice")).map_err(Error::FileOpenErr)?,
+            [0, 159, 146, 150]
+        );
+        assert!(target.join("keys/old").is_dir());
+        assert_eq!(Archive::from_dir(&target)?, archive);
+        // Existing files are not overwritten
+        assert!(parsed.extract_to(&target).is_err());
+
+        fs::remove_dir_all(&source).map_err(Error::FileWriteErr)?;
+        fs::remove_dir_all(&target).map_err(Error::FileWriteErr)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_unsafe_paths() {
+        for path in [
+            "../evil",
+            "keys/../../evil",
+            "/etc/passwd",
+            "",
+            "./a",
+            "a\\..\\b",
+        ] {
+            let archive = Archive {
+                entries: vec![ArchiveEntry {
+                    path: path.to_string(),
+                    mode: 0o644,
+                    mtime: 0,
+                    data: Some(SecretBytes::from(&b"evil"[..])),
+                }],
+            };
+            assert!(
+                Archive::try_from(&*archive.as_bytes()).is_err(),
+                "{path:?} should be rejected"
+            );
+        }
+        assert!(check_path("keys/alice").is_ok());
+    }
+}
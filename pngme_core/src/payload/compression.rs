@@ -0,0 +1,174 @@
+use super::read_array;
+use crate::crypto::SecretBytes;
+use crate::img_format::zlib;
+use crate::{Error, Result};
+
+use zeroize::Zeroizing;
+
+/// Every compressed payload starts with this line
+const COMPRESSED_MAGIC: &[u8] = b"PNGme-compressed-v1\n";
+
+/// The default limit of `decompress`, against payloads inflating to exhaust the memory
+pub const MAX_DECOMPRESSED_LENGTH: usize = 64 * 1024 * 1024;
+
+/// The algorithm compressing a payload before it is encrypted.
+///
+/// A compressed payload is laid out as:
+///
+/// | magic | algorithm | original size | compressed payload |
+///
+/// The algorithm takes one byte and the size eight, big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// zlib-wrapped deflate, always available
+    Deflate = 1,
+    /// Only available with the `zstd` feature
+    Zstd = 2,
+}
+
+impl Compression {
+    /// Whether this build can compress and decompress with the algorithm
+    pub fn is_available(&self) -> bool {
+        match self {
+            Compression::Deflate => true,
+            Compression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Compression::Deflate),
+            2 => Ok(Compression::Zstd),
+            _ => Err(Error::InvalidPayload(format!("unknown compression {id}"))),
+        }
+    }
+}
+
+/// Whether the decrypted payload has to be decompressed first
+pub fn is_compressed(payload: &[u8]) -> bool {
+    payload.starts_with(COMPRESSED_MAGIC)
+}
+
+/// Compresses a message, file or archive, recording the algorithm in front of it.
+pub fn compress(payload: &[u8], compression: Compression) -> Result<SecretBytes> {
+    // At a capacity above the worst case of both algorithms, see `SecretBytes`, and wiped
+    // even if the compression fails halfway
+    let capacity = COMPRESSED_MAGIC.len() + 1 + 8 + payload.len() + (payload.len() >> 8) + 128;
+    let mut bytes = Zeroizing::new(Vec::with_capacity(capacity));
+    bytes.extend_from_slice(COMPRESSED_MAGIC);
+    bytes.push(compression.id());
+    bytes.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    match compression {
+        Compression::Deflate => zlib::deflate_into(payload, &mut bytes)?,
+        Compression::Zstd => zstd_compress(payload, &mut bytes)?,
+    };
+    Ok(SecretBytes::new(std::mem::take(&mut *bytes)))
+}
+
+/// Decompresses a payload of `compress`, refusing to produce more than `max_length` bytes.
+pub fn decompress(payload: &[u8], max_length: usize) -> Result<SecretBytes> {
+    let mut rest = payload
+        .strip_prefix(COMPRESSED_MAGIC)
+        .ok_or_else(|| Error::InvalidPayload("not compressed".to_string()))?;
+    let [id] = read_array(&mut rest, "compression")?;
+    let compression = Compression::try_from(id)?;
+    let size = u64::from_be_bytes(read_array(&mut rest, "size")?);
+    if size > max_length as u64 {
+        return Err(Error::DecompressedTooLarge(max_length));
+    }
+
+    // The recorded size is only trusted as a limit, the actual size is checked below
+    let size = size as usize;
+    let decompressed = match compression {
+        Compression::Deflate => zlib::inflate_secret(rest, size)?,
+        Compression::Zstd => zstd_decompress(rest, size)?,
+    };
+    if decompressed.len() != size {
+        return Err(Error::InvalidPayload(format!(
+            "expected {size} bytes once decompressed, found {}",
+            decompressed.len()
+        )));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_compress(payload: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    use std::io::Write;
+
+    // Level 0 is zstd's default
+    let mut encoder = zstd::stream::write::Encoder::new(out, 0).map_err(Error::CompressionErr)?;
+    encoder.write_all(payload).map_err(Error::CompressionErr)?;
+    encoder.finish().map_err(Error::CompressionErr)?;
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decompress(data: &[u8], max_length: usize) -> Result<SecretBytes> {
+    let decoder = zstd::stream::read::Decoder::new(data).map_err(Error::DecompressionErr)?;
+    zlib::read_limited_secret(decoder, max_length)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_compress(_payload: &[u8], _out: &mut Vec<u8>) -> Result<()> {
+    Err(Error::UnsupportedCompression("zstd"))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decompress(_data: &[u8], _max_length: usize) -> Result<SecretBytes> {
+    Err(Error::UnsupportedCompression("zstd"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        b"This is where your secret message will be!".repeat(100)
+    }
+
+    #[test]
+    fn test_compression_roundtrip() -> Result<()> {
+        let payload = testing_payload();
+        for compression in [Compression::Deflate, Compression::Zstd] {
+            if !compression.is_available() {
+                assert!(compress(&payload, compression).is_err());
+                continue;
+            }
+            let compressed = compress(&payload, compression)?;
+            assert!(is_compressed(&compressed));
+            assert!(compressed.len() < payload.len());
+            assert_eq!(
+                &*decompress(&compressed, MAX_DECOMPRESSED_LENGTH)?,
+                payload.as_slice()
+            );
+        }
+        assert!(!is_compressed(&payload));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompression_limit() -> Result<()> {
+        let payload = testing_payload();
+        let compressed = compress(&payload, Compression::Deflate)?;
+        assert!(matches!(
+            decompress(&compressed, payload.len() - 1),
+            Err(Error::DecompressedTooLarge(_))
+        ));
+
+        // A bomb lying about its size is cut at the recorded size
+        let mut lying = compressed.to_vec();
+        let size_offset = COMPRESSED_MAGIC.len() + 1;
+        lying[size_offset..size_offset + 8].copy_from_slice(&10u64.to_be_bytes());
+        assert!(decompress(&lying, MAX_DECOMPRESSED_LENGTH).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use sha2::{Digest, Sha256};
+
+use super::{check_length, read_array, read_string};
+use crate::crypto::SecretBytes;
+use crate::{Error, Result};
+
+/// Every file payload starts with this line
+const FILE_MAGIC: &[u8] = b"PNGme-file-v1\n";
+
+/// The MIME type of files whose type is not known
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// A hidden file along with its metadata.
+///
+/// It is laid out as:
+///
+/// | magic | name length | name | MIME type length | MIME type | size | SHA-256 | data |
+///
+/// Lengths take two bytes and the size eight, all big-endian. The size and the hash are
+/// checked against the data when reading the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePayload {
+    name: String,
+    mime_type: String,
+    data: SecretBytes,
+}
+
+impl FilePayload {
+    /// Whether the decrypted payload is a file rather than a plain message
+    pub fn is_file(payload: &[u8]) -> bool {
+        payload.starts_with(FILE_MAGIC)
+    }
+
+    /// Wraps the content of a file. Only the last component of its path is kept as its name,
+    /// and its MIME type is guessed from its extension.
+    pub fn new(path: &str, data: SecretBytes) -> Result<FilePayload> {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or_default();
+        check_name(name)?;
+        Ok(FilePayload {
+            name: name.to_string(),
+            mime_type: mime_type_of(name).to_string(),
+            data,
+        })
+    }
+
+    /// Overrides the MIME type guessed from the name.
+    pub fn with_mime_type(mut self, mime_type: &str) -> Result<FilePayload> {
+        check_length(mime_type.len(), "MIME type")?;
+        self.mime_type = mime_type.to_string();
+        Ok(self)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn sha256(&self) -> [u8; 32] {
+        Sha256::digest(&*self.data).into()
+    }
+
+    pub fn data(&self) -> &SecretBytes {
+        &self.data
+    }
+
+    pub fn as_bytes(&self) -> SecretBytes {
+        let mut bytes = Vec::with_capacity(
+            FILE_MAGIC.len() + 2 + self.name.len() + 2 + self.mime_type.len() + 8 + 32,
+        );
+        bytes.extend_from_slice(FILE_MAGIC);
+        for field in [&self.name, &self.mime_type] {
+            bytes.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+        }
+        bytes.extend_from_slice(&self.size().to_be_bytes());
+        bytes.extend_from_slice(&self.sha256());
+        // Only the data is secret: the metadata is not worth wiping
+        [bytes.as_slice(), &self.data].concat().into()
+    }
+}
+
+impl TryFrom<&[u8]> for FilePayload {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let mut rest = bytes
+            .strip_prefix(FILE_MAGIC)
+            .ok_or_else(|| Error::InvalidPayload("not a file".to_string()))?;
+
+        let name = read_string(&mut rest, "name")?;
+        check_name(&name)?;
+        let mime_type = read_string(&mut rest, "MIME type")?;
+        let size = u64::from_be_bytes(read_array(&mut rest, "size")?);
+        let sha256: [u8; 32] = read_array(&mut rest, "hash")?;
+
+        let payload = FilePayload {
+            name,
+            mime_type,
+            data: SecretBytes::from(rest),
+        };
+        if payload.size() != size {
+            return Err(Error::InvalidPayload(format!(
+                "expected {size} bytes of data, found {}",
+                payload.size()
+            )));
+        }
+        if payload.sha256() != sha256 {
+            return Err(Error::InvalidPayload("the hash does not match".to_string()));
+        }
+        Ok(payload)
+    }
+}
+
+/// Guesses the MIME type of a file from the extension of its name.
+pub fn mime_type_of(name: &str) -> &'static str {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => return DEFAULT_MIME_TYPE,
+    };
+    match extension.as_str() {
+        "txt" | "md" => "text/plain",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "tar" => "application/x-tar",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => DEFAULT_MIME_TYPE,
+    }
+}
+
+/// A name is a single path component, so that writing the file cannot escape a directory.
+fn check_name(name: &str) -> Result<()> {
+    check_length(name.len(), "name")?;
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        return Err(Error::InvalidPayload(format!("invalid file name {name:?}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_file() -> Result<FilePayload> {
+        FilePayload::new("photos/cat.PNG", SecretBytes::from(&[0, 159, 146, 150][..]))
+    }
+
+    #[test]
+    fn test_file_payload_roundtrip() -> Result<()> {
+        let file = testing_file()?;
+        assert_eq!(file.name(), "cat.PNG");
+        assert_eq!(file.mime_type(), "image/png");
+        assert_eq!(file.size(), 4);
+
+        let bytes = file.as_bytes();
+        assert!(FilePayload::is_file(&bytes));
+        assert!(!FilePayload::is_file(b"FooBar!"));
+        let parsed = FilePayload::try_from(&*bytes)?;
+        assert_eq!(parsed, file);
+        assert_eq!(&**parsed.data(), &[0, 159, 146, 150]);
+
+        let file = file.with_mime_type("image/x-cat")?;
+        assert_eq!(
+            FilePayload::try_from(&*file.as_bytes())?.mime_type(),
+            "image/x-cat"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_payload_tampered() -> Result<()> {
+        let bytes = testing_file()?.as_bytes();
+
+        let mut tampered = bytes.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(FilePayload::try_from(tampered.as_slice()).is_err());
+        // Truncated data
+        assert!(FilePayload::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(FilePayload::try_from(&bytes[..FILE_MAGIC.len() + 3]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_payload_names() {
+        assert!(FilePayload::new("../..", SecretBytes::default()).is_err());
+        assert!(FilePayload::new("dir/", SecretBytes::default()).is_err());
+        assert_eq!(mime_type_of("notes.txt"), "text/plain");
+        assert_eq!(mime_type_of("README"), DEFAULT_MIME_TYPE);
+        assert_eq!(mime_type_of("archive.unknown"), DEFAULT_MIME_TYPE);
+    }
+}
@@ -0,0 +1,75 @@
+use super::{is_compressed, Archive, FilePayload};
+use crate::crypto::SecretBytes;
+
+/// Every plain message starts with this line, so that it is never mistaken for another
+/// kind of payload. The messages of older versions have none
+const MESSAGE_MAGIC: &[u8] = b"PNGme-message-v1\n";
+
+/// What a decrypted payload holds, told by the magic line it starts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// A plain message, from `message_payload` or an older version
+    Message,
+    File,
+    Archive,
+    /// Any other kind, compressed
+    Compressed,
+}
+
+impl PayloadKind {
+    /// The kind of a decrypted payload. A payload without any known magic line is a plain
+    /// message of an older version.
+    pub fn of(payload: &[u8]) -> PayloadKind {
+        if payload.starts_with(MESSAGE_MAGIC) {
+            PayloadKind::Message
+        } else if is_compressed(payload) {
+            PayloadKind::Compressed
+        } else if Archive::is_archive(payload) {
+            PayloadKind::Archive
+        } else if FilePayload::is_file(payload) {
+            PayloadKind::File
+        } else {
+            PayloadKind::Message
+        }
+    }
+}
+
+/// Tags a plain message, whatever it starts with.
+pub fn message_payload(message: &[u8]) -> SecretBytes {
+    let mut bytes = Vec::with_capacity(MESSAGE_MAGIC.len() + message.len());
+    bytes.extend_from_slice(MESSAGE_MAGIC);
+    bytes.extend_from_slice(message);
+    SecretBytes::new(bytes)
+}
+
+/// The text of a plain message payload, without its magic line.
+pub fn message_text(payload: &[u8]) -> &[u8] {
+    payload.strip_prefix(MESSAGE_MAGIC).unwrap_or(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::{compress, Compression};
+    use crate::Result;
+
+    #[test]
+    fn test_payload_kind() -> Result<()> {
+        let file = FilePayload::new("notes.txt", SecretBytes::from(&b"Notes"[..]))?.as_bytes();
+        assert_eq!(PayloadKind::of(&file), PayloadKind::File);
+        assert_eq!(
+            PayloadKind::of(&compress(&file, Compression::Deflate)?),
+            PayloadKind::Compressed
+        );
+
+        // A message looking like another kind is still a message
+        let message = message_payload(&file);
+        assert_eq!(PayloadKind::of(&message), PayloadKind::Message);
+        assert_eq!(message_text(&message), &*file);
+
+        // Messages of older versions are untagged
+        assert_eq!(PayloadKind::of(b"Hello"), PayloadKind::Message);
+        assert_eq!(message_text(b"Hello"), b"Hello");
+        Ok(())
+    }
+}
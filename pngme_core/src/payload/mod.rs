@@ -0,0 +1,47 @@
+mod archive;
+mod compression;
+mod file;
+mod message;
+
+pub use archive::Archive;
+pub use archive::ArchiveEntry;
+pub use compression::compress;
+pub use compression::decompress;
+pub use compression::is_compressed;
+pub use compression::Compression;
+pub use compression::MAX_DECOMPRESSED_LENGTH;
+pub use file::mime_type_of;
+pub use file::FilePayload;
+pub use file::DEFAULT_MIME_TYPE;
+pub use message::message_payload;
+pub use message::message_text;
+pub use message::PayloadKind;
+
+use crate::{Error, Result};
+
+fn check_length(length: usize, field: &str) -> Result<()> {
+    if length > u16::MAX as usize {
+        return Err(Error::InvalidPayload(format!("the {field} is too long")));
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(rest: &mut &[u8], field: &str) -> Result<[u8; N]> {
+    if rest.len() < N {
+        return Err(Error::InvalidPayload(format!("truncated {field}")));
+    }
+    let (array, tail) = rest.split_at(N);
+    *rest = tail;
+    Ok(array.try_into().expect("Checked length"))
+}
+
+fn read_string(rest: &mut &[u8], field: &str) -> Result<String> {
+    let length = u16::from_be_bytes(read_array(rest, field)?) as usize;
+    if rest.len() < length {
+        return Err(Error::InvalidPayload(format!("truncated {field}")));
+    }
+    let (string, tail) = rest.split_at(length);
+    *rest = tail;
+    String::from_utf8(string.to_vec())
+        .map_err(|_| Error::InvalidPayload(format!("the {field} is not UTF-8")))
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha3::{Digest, Sha3_256};
+
+use crate::crypto::{Kdf, KeySource, SALT_LENGTH};
+use crate::img_format::{ColorType, PixelBuffer, Png};
+use crate::{Error, Result};
+
+/// Separates the PRNG seed from any other use of the same key
+const SEED_DOMAIN: &[u8] = b"PNGme LSB positions";
+
+/// The first samples hold the salt, and nothing else: random bits read as noise
+const SALT_SAMPLES: usize = SALT_LENGTH * 8;
+
+/// The salt, then the payload length as a big-endian u32
+const HEADER_LENGTH: usize = SALT_LENGTH + 4;
+
+/// Hides the payload in the least significant bits of the pixel samples.
+///
+/// The samples holding the payload are picked in a pseudo-random order seeded with the key
+/// derived by `kdf` from a passphrase and a salt, or with the raw key. The salt is random
+/// if not given, and stored in the first samples. The KDF is not stored: the same secret
+/// and KDF are needed to find the payload again. The IDAT chunks are re-encoded, every
+/// other chunk is kept.
+pub fn lsb_embed(
+    png: &mut Png,
+    key_source: &KeySource,
+    kdf: Kdf,
+    salt: Option<&[u8]>,
+    payload: &[u8],
+) -> Result<()> {
+    let kdf = positions_kdf(key_source, kdf).ok_or(Error::KeyDerivationErr)?;
+    kdf.check_params()?;
+    let salt = match salt {
+        Some(salt) if salt.len() == SALT_LENGTH => salt.to_vec(),
+        Some(_) => return Err(Error::KeyDerivationErr),
+        None => {
+            let mut salt = vec![0; SALT_LENGTH];
+            getrandom::getrandom(&mut salt).map_err(|_| Error::EncryptionErr)?;
+            salt
+        }
+    };
+
+    let interlace = png.ihdr()?.interlace();
+    let mut pixels = png.decode_pixels()?;
+    check_supported(&pixels)?;
+    let available = capacity(&pixels);
+    if payload.len() > available {
+        return Err(Error::InsufficientCapacity {
+            needed: payload.len(),
+            available,
+        });
+    }
+    // The capacity of the largest images does not fit a u32
+    let length = u32::try_from(payload.len()).map_err(|_| Error::InsufficientCapacity {
+        needed: payload.len(),
+        available: u32::MAX as usize,
+    })?;
+
+    write_bytes(&mut pixels, 0..SALT_SAMPLES, &salt);
+
+    let key = kdf.derive_key(key_source, &salt)?;
+    let positions = SamplePositions::new(&salt, &key, pixels.sample_count());
+    let data: Vec<u8> = length
+        .to_be_bytes()
+        .into_iter()
+        .chain(payload.iter().copied())
+        .collect();
+    write_bytes(&mut pixels, positions, &data);
+
+    png.encode_pixels(&pixels, interlace)
+}
+
+/// Looks for a payload hidden by `lsb_embed` with the same secret and KDF.
+/// Returns `None` when the image cannot hold one at all, when a passphrase comes with
+/// an unsalted KDF, or when the length read is empty or larger than the image can hold.
+///
+/// Any image reads as a salt, and nothing tells a wrong secret apart from a right one:
+/// the payload read with a wrong one is either `None` or garbage, to be rejected by its
+/// authenticated encryption.
+pub fn lsb_extract(png: &Png, key_source: &KeySource, kdf: Kdf) -> Result<Option<Vec<u8>>> {
+    let Some(kdf) = positions_kdf(key_source, kdf) else {
+        return Ok(None);
+    };
+    kdf.check_params()?;
+    let pixels = png.decode_pixels()?;
+    if check_supported(&pixels).is_err() || pixels.sample_count() < HEADER_LENGTH * 8 {
+        return Ok(None);
+    }
+
+    let salt = read_bytes(&pixels, 0..SALT_SAMPLES, SALT_LENGTH);
+    let key = kdf.derive_key(key_source, &salt)?;
+    let mut positions = SamplePositions::new(&salt, &key, pixels.sample_count());
+    let length = read_bytes(&pixels, &mut positions, 4);
+    let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+    // A flat image reads as a length of zero, or as one of every bit set
+    if length == 0 || length > capacity(&pixels) {
+        return Ok(None);
+    }
+
+    Ok(Some(read_bytes(&pixels, &mut positions, length)))
+}
+
+/// The number of payload bytes that `lsb_embed` can hide in the image.
+pub fn lsb_capacity(png: &Png) -> Result<usize> {
+    let pixels = png.decode_pixels()?;
+    check_supported(&pixels)?;
+    Ok(capacity(&pixels))
+}
+
+/// The KDF seeding the positions: keys are used as they are, passphrases need a salted KDF
+fn positions_kdf(key_source: &KeySource, kdf: Kdf) -> Option<Kdf> {
+    match key_source {
+        KeySource::Key(_) => Some(Kdf::RawKey),
+        KeySource::Passphrase(_) if kdf.is_salted() => Some(kdf),
+        KeySource::Passphrase(_) => None,
+    }
+}
+
+fn capacity(pixels: &PixelBuffer) -> usize {
+    (pixels.sample_count() / 8).saturating_sub(HEADER_LENGTH)
+}
+
+fn check_supported(pixels: &PixelBuffer) -> Result<()> {
+    if pixels.color_type() == ColorType::Indexed {
+        // Flipping a bit of a palette index may pick a completely different color
+        return Err(Error::UnsupportedImage(
+            "indexed-color images are not supported".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the bytes to the least significant bits of the samples at the next positions.
+fn write_bytes(pixels: &mut PixelBuffer, mut positions: impl Iterator<Item = usize>, bytes: &[u8]) {
+    for byte in bytes {
+        for shift in (0..8).rev() {
+            // The capacity has been checked by the caller
+            let index = positions.next().expect("Not enough samples");
+            let bit = ((byte >> shift) & 1) as u16;
+            pixels.set_sample(index, (pixels.sample(index) & !1) | bit);
+        }
+    }
+}
+
+/// Reads `count` bytes from the least significant bits of the samples at the next positions.
+fn read_bytes(
+    pixels: &PixelBuffer,
+    positions: impl Iterator<Item = usize>,
+    count: usize,
+) -> Vec<u8> {
+    let indices: Vec<usize> = positions.take(count * 8).collect();
+    indices
+        .chunks(8)
+        .map(|bits| {
+            bits.iter().fold(0, |byte, &index| {
+                (byte << 1) | (pixels.sample(index) & 1) as u8
+            })
+        })
+        .collect()
+}
+
+/// Yields every sample index after the salt once, in a key-dependent order.
+///
+/// This is a Fisher-Yates shuffle run lazily: only the swapped entries are stored,
+/// so hiding a short payload in a large image stays cheap.
+struct SamplePositions {
+    rng: ChaCha20Rng,
+    swapped: HashMap<usize, usize>,
+    next: usize,
+    total: usize,
+}
+
+impl SamplePositions {
+    fn new(salt: &[u8], key: &[u8], sample_count: usize) -> SamplePositions {
+        let mut hasher = Sha3_256::new();
+        hasher.update(SEED_DOMAIN);
+        hasher.update(salt);
+        hasher.update(key);
+        Self {
+            rng: ChaCha20Rng::from_seed(hasher.finalize().into()),
+            swapped: HashMap::new(),
+            next: 0,
+            total: sample_count.saturating_sub(SALT_SAMPLES),
+        }
+    }
+
+    /// Returns a uniformly distributed number below `bound`.
+    fn below(&mut self, bound: usize) -> usize {
+        let bound = bound as u64;
+        // Rejecting the last incomplete range of values avoids a modulo bias
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.rng.next_u64();
+            if value < zone {
+                return (value % bound) as usize;
+            }
+        }
+    }
+}
+
+impl Iterator for SamplePositions {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.total {
+            return None;
+        }
+        let current = self.next;
+        let picked = current + self.below(self.total - current);
+        let value = self.swapped.remove(&picked).unwrap_or(picked);
+        if picked != current {
+            // The current position is never visited again, move its value to the picked one
+            let current_value = self.swapped.remove(&current).unwrap_or(current);
+            self.swapped.insert(picked, current_value);
+        }
+        self.next += 1;
+        Some(SALT_SAMPLES + value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{SecretBytes, SecretString};
+    use crate::img_format::testing::testing_image;
+    use crate::img_format::{Interlace, TextChunk, TextualChunk};
+    use std::collections::HashSet;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const SALT: &[u8] = b"0123456789abcdef";
+    /// Cheap enough for tests
+    const TESTING_KDF: Kdf = Kdf::Argon2id {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn key() -> KeySource {
+        KeySource::Key(SecretBytes::from(KEY))
+    }
+
+    #[test]
+    fn test_lsb_roundtrip() -> Result<()> {
+        let cases = [
+            (8, ColorType::Rgb, Interlace::None),
+            (8, ColorType::Rgba, Interlace::Adam7),
+            (16, ColorType::GrayscaleAlpha, Interlace::None),
+        ];
+        for (bit_depth, color_type, interlace) in cases {
+            let mut png = testing_image(40, 20, bit_depth, color_type, interlace)?;
+            let before = png.decode_pixels()?;
+            lsb_embed(
+                &mut png,
+                &key(),
+                TESTING_KDF,
+                None,
+                b"A secret in the pixels",
+            )?;
+
+            let png = Png::try_from(png.as_bytes().as_slice())?;
+            assert_eq!(png.ihdr()?.interlace(), interlace);
+            assert_eq!(
+                lsb_extract(&png, &key(), TESTING_KDF)?.as_deref(),
+                Some(&b"A secret in the pixels"[..])
+            );
+            // Only the least significant bits have changed
+            let after = png.decode_pixels()?;
+            for index in 0..before.sample_count() {
+                assert_eq!(before.sample(index) >> 1, after.sample(index) >> 1);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_passphrase() -> Result<()> {
+        let passphrase = KeySource::Passphrase(SecretString::from("HelloWorld!"));
+        let mut png = testing_image(40, 20, 8, ColorType::Rgb, Interlace::None)?;
+        lsb_embed(&mut png, &passphrase, TESTING_KDF, Some(SALT), b"secret")?;
+        assert_eq!(
+            lsb_extract(&png, &passphrase, TESTING_KDF)?,
+            Some(b"secret".to_vec())
+        );
+
+        // Only the salt is stored, in the first samples
+        let salt = read_bytes(&png.decode_pixels()?, 0..SALT_SAMPLES, SALT_LENGTH);
+        assert_eq!(salt, SALT);
+        // Other KDF settings pick other positions
+        let other_kdf = Kdf::Argon2id {
+            memory_kib: 8,
+            iterations: 2,
+            parallelism: 1,
+        };
+        assert_ne!(
+            lsb_extract(&png, &passphrase, other_kdf)?,
+            Some(b"secret".to_vec())
+        );
+
+        // The same salt gives the same image, a random one does not
+        let mut same = testing_image(40, 20, 8, ColorType::Rgb, Interlace::None)?;
+        lsb_embed(&mut same, &passphrase, TESTING_KDF, Some(SALT), b"secret")?;
+        assert_eq!(same.as_bytes(), png.as_bytes());
+        let mut other = testing_image(40, 20, 8, ColorType::Rgb, Interlace::None)?;
+        lsb_embed(&mut other, &passphrase, TESTING_KDF, None, b"secret")?;
+        assert_ne!(other.as_bytes(), png.as_bytes());
+
+        assert!(lsb_embed(&mut png, &passphrase, Kdf::Sha3, None, b"secret").is_err());
+        assert_eq!(lsb_extract(&png, &passphrase, Kdf::Sha3)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_flat_image() -> Result<()> {
+        let passphrase = KeySource::Passphrase(SecretString::from("hunter2"));
+        for value in [0, 0xFF] {
+            let pixels = PixelBuffer::new(32, 32, 8, ColorType::Rgb, vec![value; 32 * 3 * 32])?;
+            let mut png = testing_image(32, 32, 8, ColorType::Rgb, Interlace::None)?;
+            png.encode_pixels(&pixels, Interlace::None)?;
+            assert_eq!(lsb_extract(&png, &passphrase, TESTING_KDF)?, None);
+            assert_eq!(lsb_extract(&png, &key(), TESTING_KDF)?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_wrong_key() -> Result<()> {
+        let mut png = testing_image(40, 20, 8, ColorType::Rgb, Interlace::None)?;
+        assert_eq!(lsb_extract(&png, &key(), TESTING_KDF)?, None);
+        lsb_embed(&mut png, &key(), TESTING_KDF, None, b"secret")?;
+        let other = KeySource::Key(SecretBytes::from(&b"another key"[..]));
+        assert_ne!(
+            lsb_extract(&png, &other, TESTING_KDF).ok().flatten(),
+            Some(b"secret".to_vec())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_capacity() -> Result<()> {
+        let mut png = testing_image(40, 20, 8, ColorType::Grayscale, Interlace::None)?;
+        // 800 samples hold 100 bytes, including the header
+        assert_eq!(lsb_capacity(&png)?, 100 - HEADER_LENGTH);
+        assert!(matches!(
+            lsb_embed(&mut png, &key(), TESTING_KDF, None, &[0; 81]),
+            Err(Error::InsufficientCapacity {
+                needed: 81,
+                available: 80
+            })
+        ));
+        lsb_embed(&mut png, &key(), TESTING_KDF, None, &[0xAB; 80])?;
+        assert_eq!(
+            lsb_extract(&png, &key(), TESTING_KDF)?,
+            Some(vec![0xAB; 80])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_keeps_chunks() -> Result<()> {
+        let mut png = testing_image(40, 20, 8, ColorType::Rgb, Interlace::None)?;
+        let author = TextualChunk::Text(TextChunk::new("Author", "Cuong")?);
+        png.add_text_chunk(&author)?;
+        lsb_embed(&mut png, &key(), TESTING_KDF, None, b"secret")?;
+        assert_eq!(png.text_chunks()?, vec![author]);
+        png.validate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsb_indexed_image() -> Result<()> {
+        let mut png = testing_image(40, 20, 8, ColorType::Indexed, Interlace::None)?;
+        assert!(matches!(
+            lsb_embed(&mut png, &key(), TESTING_KDF, None, b"secret"),
+            Err(Error::UnsupportedImage(_))
+        ));
+        assert_eq!(lsb_extract(&png, &key(), TESTING_KDF)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_positions() {
+        let positions: Vec<usize> = SamplePositions::new(SALT, KEY, 400).collect();
+        assert_eq!(positions.len(), 400 - SALT_SAMPLES);
+        assert_eq!(
+            positions.iter().collect::<HashSet<_>>().len(),
+            positions.len()
+        );
+        // The salt is left alone
+        assert!(positions.iter().all(|&index| index >= SALT_SAMPLES));
+        // The order only depends on the salt and the key
+        assert_eq!(
+            SamplePositions::new(SALT, KEY, 400).collect::<Vec<_>>(),
+            positions
+        );
+        assert_ne!(
+            SamplePositions::new(SALT, b"key", 400).collect::<Vec<_>>(),
+            positions
+        );
+        assert_ne!(
+            SamplePositions::new(b"salt", KEY, 400).collect::<Vec<_>>(),
+            positions
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod lsb;
+
+pub use lsb::lsb_capacity;
+pub use lsb::lsb_embed;
+pub use lsb::lsb_extract;
@@ -5,7 +5,12 @@ use pngme_core::img_format::TAG;
 use std::str::FromStr;
 
 use pngme_core::crypto;
-use pngme_core::img_format::{Chunk, ChunkType, Png};
+use pngme_core::crypto::{
+    Binding, Cipher, Envelope, Kdf, KeySource, SealOptions, SecretBytes, SecretString,
+};
+use pngme_core::img_format::{ChunkType, ParseLimits, Png, DEFAULT_SPLIT_LENGTH};
+use pngme_core::payload::{self, Compression, FilePayload, PayloadKind};
+use pngme_core::stego;
 use wasm_bindgen::prelude::*;
 
 use base64::{engine::general_purpose::STANDARD, Engine as _};
@@ -20,7 +25,6 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[wasm_bindgen]
 pub struct PublicMaterial {
     encoded_bytes: Box<[u8]>,
-    nonce: String,
 }
 
 #[wasm_bindgen]
@@ -29,46 +33,285 @@ impl PublicMaterial {
     pub fn encoded_bytes(&self) -> Box<[u8]> {
         self.encoded_bytes.clone()
     }
+}
+
+/// The options of `encode`
+#[wasm_bindgen]
+pub struct EncodeOptions {
+    mode: String,
+    cipher: String,
+    kdf: String,
+    compression: String,
+    bind_chunk_type: bool,
+    bind_image: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            mode: "chunk".to_string(),
+            cipher: "aes256gcm".to_string(),
+            kdf: "argon2id".to_string(),
+            compression: String::new(),
+            bind_chunk_type: false,
+            bind_image: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl EncodeOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> EncodeOptions {
+        EncodeOptions::default()
+    }
 
+    /// Either "chunk" (hidden in a chunk, the default) or "lsb" (hidden in the pixels)
     #[wasm_bindgen(getter)]
-    pub fn nonce(&self) -> String {
-        self.nonce.clone()
+    pub fn mode(&self) -> String {
+        self.mode.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_mode(&mut self, mode: String) {
+        self.mode = mode;
     }
+
+    /// Either "aes256gcm" (the default), "xchacha20poly1305", or "aes256gcmsiv" for
+    /// a deterministic output: the same message, passphrase and image give the same image
+    #[wasm_bindgen(getter)]
+    pub fn cipher(&self) -> String {
+        self.cipher.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_cipher(&mut self, cipher: String) {
+        self.cipher = cipher;
+    }
+
+    /// Either "argon2id" (the default) or "pbkdf2", faster on constrained devices
+    #[wasm_bindgen(getter)]
+    pub fn kdf(&self) -> String {
+        self.kdf.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_kdf(&mut self, kdf: String) {
+        self.kdf = kdf;
+    }
+
+    /// Either "" (no compression, the default) or "deflate". "zstd" is not available in WASM
+    #[wasm_bindgen(getter)]
+    pub fn compression(&self) -> String {
+        self.compression.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_compression(&mut self, compression: String) {
+        self.compression = compression;
+    }
+
+    /// Whether the message only decrypts in a chunk of its original type (chunk mode only)
+    #[wasm_bindgen(getter)]
+    pub fn bind_chunk_type(&self) -> bool {
+        self.bind_chunk_type
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_bind_chunk_type(&mut self, bind_chunk_type: bool) {
+        self.bind_chunk_type = bind_chunk_type;
+    }
+
+    /// Whether the message only decrypts in its original image (chunk mode only)
+    #[wasm_bindgen(getter)]
+    pub fn bind_image(&self) -> bool {
+        self.bind_image
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_bind_image(&mut self, bind_image: bool) {
+        self.bind_image = bind_image;
+    }
+}
+
+/// A file hidden in an image, as returned by `decode_file`
+#[wasm_bindgen]
+pub struct DecodedFile {
+    name: String,
+    mime_type: String,
+    data: Box<[u8]>,
 }
 
+#[wasm_bindgen]
+impl DecodedFile {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn mime_type(&self) -> String {
+        self.mime_type.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Box<[u8]> {
+        self.data.clone()
+    }
+}
+
+/// Encodes the message into the image.
+/// `chunk_type` is only used when hiding the message in a chunk.
 #[wasm_bindgen]
 pub fn encode(
     input_data: &[u8],
     passphrase: &str,
     message: &str,
     chunk_type: &str,
+    options: &EncodeOptions,
+) -> Result<PublicMaterial> {
+    encode_bytes(
+        input_data,
+        passphrase,
+        &payload::message_payload(message.as_bytes()),
+        chunk_type,
+        options,
+    )
+}
+
+/// Encodes the file into the image, along with its name, MIME type, size and hash.
+/// The MIME type is guessed from the name when `mime_type` is empty.
+#[wasm_bindgen]
+pub fn encode_file(
+    input_data: &[u8],
+    passphrase: &str,
+    file_data: &[u8],
+    file_name: &str,
+    mime_type: &str,
+    chunk_type: &str,
+    options: &EncodeOptions,
+) -> Result<PublicMaterial> {
+    let mut file =
+        FilePayload::new(file_name, SecretBytes::from(file_data)).map_err(JsError::from)?;
+    if !mime_type.is_empty() {
+        file = file.with_mime_type(mime_type).map_err(JsError::from)?;
+    }
+    encode_bytes(
+        input_data,
+        passphrase,
+        &file.as_bytes(),
+        chunk_type,
+        options,
+    )
+}
+
+fn encode_bytes(
+    input_data: &[u8],
+    passphrase: &str,
+    plaintext: &[u8],
+    chunk_type: &str,
+    options: &EncodeOptions,
 ) -> Result<PublicMaterial> {
     log!("Encoding....");
-    // let mut png = Png::try_from_file(Path::new(input_data)).map_err(JsError::from)?;
-    let mut png = Png::try_from(input_data).map_err(JsError::from)?;
+    let mut png = Png::with_limits(input_data, ParseLimits::UNTRUSTED).map_err(JsError::from)?;
     log!("Tried_from raw bytes to png...done");
 
-    let chunk_type = ChunkType::from_str(chunk_type).map_err(JsError::from)?;
-    log!("Converting chunk_type...done");
-    let key = crypto::sha3_hash(passphrase).map_err(JsError::from)?;
-    let (chunk_content, nonce_raw) =
-        crypto::aes256gcm_encrypt(message.as_bytes(), &key).map_err(JsError::from)?;
+    let cipher =
+        match options.cipher.as_str() {
+            "aes256gcm" => Cipher::Aes256Gcm,
+            "xchacha20poly1305" => Cipher::XChaCha20Poly1305,
+            "aes256gcmsiv" => Cipher::Aes256GcmSiv,
+            _ => return Err(JsError::new(
+                "Unknown cipher, expected \"aes256gcm\", \"xchacha20poly1305\" or \"aes256gcmsiv\"",
+            )),
+        };
+    let kdf = match options.kdf.as_str() {
+        "argon2id" => Kdf::ARGON2ID_DEFAULT,
+        "pbkdf2" => Kdf::PBKDF2_DEFAULT,
+        _ => {
+            return Err(JsError::new(
+                "Unknown KDF, expected \"argon2id\" or \"pbkdf2\"",
+            ))
+        }
+    };
+    let compression = match options.compression.as_str() {
+        "" => None,
+        "deflate" => Some(Compression::Deflate),
+        "zstd" => {
+            return Err(JsError::new(
+                "zstd compression is not available in WASM, expected \"\" or \"deflate\"",
+            ))
+        }
+        _ => {
+            return Err(JsError::new(
+                "Unknown compression, expected \"\" or \"deflate\"",
+            ))
+        }
+    };
+    let chunk_type = match options.mode.as_str() {
+        "chunk" => Some(ChunkType::from_str(chunk_type).map_err(JsError::from)?),
+        "lsb" => None,
+        _ => return Err(JsError::new("Unknown mode, expected \"chunk\" or \"lsb\"")),
+    };
+    let binding = Binding::select(
+        chunk_type,
+        options.bind_chunk_type,
+        options.bind_image,
+        &png,
+    )
+    .map_err(JsError::from)?;
+
+    let compressed = compression
+        .map(|compression| payload::compress(plaintext, compression))
+        .transpose()
+        .map_err(JsError::from)?;
+    let plaintext = compressed.as_deref().unwrap_or(plaintext);
+
+    let key_source = KeySource::Passphrase(SecretString::from(passphrase));
+    // Salted with the carrier, so that the output is deterministic too
+    let salt = cipher
+        .is_deterministic()
+        .then(|| crypto::critical_chunks_hash(&png)[..crypto::SALT_LENGTH].to_vec());
+    // The nonce and everything else needed for decryption are stored with the message
+    let ciphertext = Envelope::seal(
+        plaintext,
+        &key_source,
+        &SealOptions {
+            cipher,
+            kdf,
+            binding,
+            salt: salt.clone(),
+        },
+    )
+    .map_err(JsError::from)?
+    .as_bytes();
     log!("Encrypting...done");
 
-    let new_chunk = Chunk::new(chunk_type, &chunk_content);
-    png.append_chunk(new_chunk, true).map_err(JsError::from)?;
-    log!("Appending chunk...done");
+    match chunk_type {
+        Some(chunk_type) => {
+            let count = png
+                .append_split_chunks(chunk_type, &ciphertext, DEFAULT_SPLIT_LENGTH)
+                .map_err(JsError::from)?;
+            log!("Appending {count} chunk(s)...done");
+        }
+        None => {
+            stego::lsb_embed(&mut png, &key_source, kdf, salt.as_deref(), &ciphertext)
+                .map_err(JsError::from)?;
+            log!("Embedding into pixels...done");
+        }
+    }
+    png.validate().map_err(JsError::from)?;
+    log!("Validating png...done");
 
-    // base64-encode the raw nonce
-    let nonce = STANDARD.encode(nonce_raw);
     let encoded_bytes = png.as_bytes().into_boxed_slice();
 
-    Ok(PublicMaterial {
-        encoded_bytes,
-        nonce,
-    })
+    Ok(PublicMaterial { encoded_bytes })
 }
 
+/// Decodes the message hidden in the image.
+/// `nonce` is only needed for messages encoded by older versions, and must be empty otherwise.
+/// A tagged chunk of `chunk_type` (or any tagged chunk if `chunk_type` is empty) is
+/// looked for first, then a message hidden in the pixels with either KDF of `encode`.
 #[wasm_bindgen]
 pub fn decode(
     input_data: &[u8],
@@ -76,26 +319,116 @@ pub fn decode(
     nonce: &str,
     chunk_type: &str,
 ) -> Result<String> {
+    let plaintext_bytes = decode_bytes(input_data, passphrase, nonce, chunk_type)?;
+    if PayloadKind::of(&plaintext_bytes) != PayloadKind::Message {
+        return Err(JsError::new(
+            "A file or directory is hidden in this image, use decode_file instead",
+        ));
+    }
+    Ok(String::from_utf8_lossy(payload::message_text(&plaintext_bytes)).to_string())
+}
+
+/// Decodes the file hidden in the image, as `decode` does for messages.
+/// A plain message is returned as a file without a name, of type `text/plain`.
+#[wasm_bindgen]
+pub fn decode_file(
+    input_data: &[u8],
+    passphrase: &str,
+    nonce: &str,
+    chunk_type: &str,
+) -> Result<DecodedFile> {
+    let plaintext_bytes = decode_bytes(input_data, passphrase, nonce, chunk_type)?;
+    match PayloadKind::of(&plaintext_bytes) {
+        PayloadKind::Archive => {
+            return Err(JsError::new(
+                "A directory is hidden in this image, extract it with the command-line tool",
+            ));
+        }
+        PayloadKind::Message => {
+            return Ok(DecodedFile {
+                name: String::new(),
+                mime_type: "text/plain".to_string(),
+                data: payload::message_text(&plaintext_bytes)
+                    .to_vec()
+                    .into_boxed_slice(),
+            });
+        }
+        // Left to the file parser, which reports anything else as malformed
+        PayloadKind::File | PayloadKind::Compressed => {}
+    }
+    let file = FilePayload::try_from(&*plaintext_bytes).map_err(JsError::from)?;
+    Ok(DecodedFile {
+        name: file.name().to_string(),
+        mime_type: file.mime_type().to_string(),
+        data: file.data().to_vec().into_boxed_slice(),
+    })
+}
+
+fn decode_bytes(
+    input_data: &[u8],
+    passphrase: &str,
+    nonce: &str,
+    chunk_type: &str,
+) -> Result<SecretBytes> {
     log!("Decoding...");
-    let png = Png::try_from(input_data).map_err(JsError::from)?;
+    let png = Png::with_limits(input_data, ParseLimits::UNTRUSTED).map_err(JsError::from)?;
     log!("Tried_from raw bytes to png...done");
 
-    let key = crypto::sha3_hash(passphrase).map_err(JsError::from)?;
-    let nonce = STANDARD
-        .decode(nonce)
-        .map_err(|_| JsError::new("Invalid nonce"))?;
-    log!("Base64-decoding Nonce...done");
-    if let Some(mess_chunk) = png.chunk_by_type(chunk_type).map_err(JsError::from)? {
-        let ciphertext = mess_chunk
-            .data()
-            .strip_prefix(&TAG)
-            .ok_or(JsError::new("Tag missing"))?;
-        log!("Found a hidden message");
-        let plaintext_bytes =
-            crypto::aes256gcm_decrypt(ciphertext, &key, &nonce).map_err(JsError::from)?;
-        log!("Decrypting...done");
-        Ok(String::from_utf8_lossy(&plaintext_bytes).to_string())
+    let key_source = KeySource::Passphrase(SecretString::from(passphrase));
+
+    let mess_chunk = if chunk_type.is_empty() {
+        png.chunks().iter().find(|c| c.data().starts_with(&TAG))
+    } else {
+        png.chunk_by_type(chunk_type).map_err(JsError::from)?
+    };
+    let (binding, ciphertext) = if let Some(mess_chunk) = mess_chunk {
+        let binding = Binding::new()
+            .with_chunk_type(*mess_chunk.chunk_type())
+            .with_image(&png);
+        let ciphertext = match png.split_payload(mess_chunk).map_err(JsError::from)? {
+            Some(ciphertext) => ciphertext,
+            None => mess_chunk
+                .data()
+                .strip_prefix(&TAG)
+                .ok_or(JsError::new("Tag missing"))?
+                .to_vec(),
+        };
+        (binding, ciphertext)
+    } else {
+        // The KDF of the pixels is not stored, `encode` only uses the defaults of these two
+        let mut found = None;
+        for kdf in [Kdf::ARGON2ID_DEFAULT, Kdf::PBKDF2_DEFAULT] {
+            found = stego::lsb_extract(&png, &key_source, kdf)
+                .map_err(JsError::from)?
+                .filter(|ciphertext| Envelope::try_from(ciphertext.as_slice()).is_ok());
+            if found.is_some() {
+                break;
+            }
+        }
+        let ciphertext = found.ok_or(JsError::new("Hidden message not found"))?;
+        (Binding::new(), ciphertext)
+    };
+    log!("Found a hidden message");
+
+    let plaintext_bytes = if nonce.is_empty() {
+        let envelope = Envelope::try_from(ciphertext.as_slice()).map_err(JsError::from)?;
+        envelope
+            .open_bound(&key_source, &binding)
+            .map_err(JsError::from)?
     } else {
-        Err(JsError::new("Hidden message not found"))
+        let nonce = STANDARD
+            .decode(nonce)
+            .map_err(|_| JsError::new("Invalid nonce"))?;
+        log!("Base64-decoding Nonce...done");
+        let legacy_key = key_source.legacy_key().map_err(JsError::from)?;
+        crypto::aes256gcm_decrypt(&ciphertext, &legacy_key, &nonce).map_err(JsError::from)?
+    };
+    log!("Decrypting...done");
+    if PayloadKind::of(&plaintext_bytes) == PayloadKind::Compressed {
+        let decompressed = payload::decompress(&plaintext_bytes, payload::MAX_DECOMPRESSED_LENGTH)
+            .map_err(JsError::from)?;
+        log!("Decompressing...done");
+        return Ok(decompressed);
     }
+    Ok(plaintext_bytes)
 }
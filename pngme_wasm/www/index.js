@@ -5,7 +5,13 @@ import * as util from "./js/utils.js";
 const encryptRadio = document.getElementById('encryptRadio');
 const decryptRadio = document.getElementById('decryptRadio');
 const messageField = document.getElementById('message');
+const secretFileInput = document.getElementById('secretFile');
 const chunkTypeField = document.getElementById('chunkType');
+const embedModeField = document.getElementById('embedMode');
+const cipherField = document.getElementById('cipher');
+const kdfField = document.getElementById('kdf');
+const compressionField = document.getElementById('compression');
+const bindField = document.getElementById('bind');
 const pngURLInput = document.getElementById('url');
 const uploadInput = document.getElementById('uploadInput');
 const passphraseInput = document.getElementById('passphrase');
@@ -13,18 +19,22 @@ const nonceInput = document.getElementById('nonceInput');
 
 const resetableFields = document.getElementsByClassName("shouldReset");
 
-const nonceValueElement = document.getElementById('nonceValue');
 const outputImageElement = document.getElementById('outputImage');
 const encodeResultBoxElement = document.getElementById('encodeResultBox');
 const decodeResultBoxElement = document.getElementById('decodeResultBox');
 const secretMessageBoxElement = document.getElementById('hiddenMessage');
 const downloadLinkElement = document.getElementById('downloadLink');
+const fileDownloadLinkElement = document.getElementById('fileDownloadLink');
 
 // Toggle the message and chunk type field according to the chosen
 // operation mode (encode or decode)
 const toggleField = () => {
     if (encryptRadio.checked) {
         messageField.hidden = false;
+        embedModeField.hidden = false;
+        cipherField.hidden = false;
+        kdfField.hidden = false;
+        bindField.hidden = false;
 
         nonceInput.hidden = true;
         nonceInput.value = "";
@@ -33,6 +43,11 @@ const toggleField = () => {
     } else if (decryptRadio.checked) {
         messageField.hidden = true;
         messageField.value = "";
+        // The mode of a hidden message is detected when decoding
+        embedModeField.hidden = true;
+        cipherField.hidden = true;
+        kdfField.hidden = true;
+        bindField.hidden = true;
 
         nonceInput.hidden = false;
     }
@@ -50,9 +65,9 @@ decryptRadio.addEventListener('change', toggleField);
  * 
  * @returns {Promise<Uint8Array|null>}
  */
-const readUploadFile = () => {
+const readUploadFile = (input = uploadInput) => {
     return new Promise((resolve, reject) => {
-        const file = uploadInput.files[0];
+        const file = input.files[0];
         if (file) {
             const reader = new FileReader();
 
@@ -73,7 +88,6 @@ const readUploadFile = () => {
 
 const resetEncodeBoxContent = () => {
     encodeResultBoxElement.hidden = true;
-    nonceValueElement.textContent = "";
     outputImageElement.src = "#";
     downloadLinkElement.download = "";
 
@@ -83,6 +97,8 @@ const resetEncodeBoxContent = () => {
 const resetDecodeBoxContent = () => {
     decodeResultBoxElement.hidden = true;
     secretMessageBoxElement.textContent = "";
+    fileDownloadLinkElement.hidden = true;
+    fileDownloadLinkElement.download = "";
 
     refresInputContents();
 }
@@ -190,44 +206,54 @@ pngmeForm.addEventListener('submit', async (event) => {
         if (util.isEmpty(passphrase)) {
             throw new Err("Please enter the passphrase!");
         }
+        const opertaionMode = document.querySelector('input[name="opMode"]:checked').value;
+        const embedMode = embedModeField.value;
+
+        // Decoding looks for any hidden message when no chunk type is given
         const chunkType = chunkTypeField.value;
-        if (util.isEmpty(chunkType)) {
+        if (util.isEmpty(chunkType) && opertaionMode === 'encode' && embedMode === 'chunk') {
             throw new Err("Please enter the chunk type");
         }
-        if (chunkType.length !== 4) {
+        if (!util.isEmpty(chunkType) && chunkType.length !== 4) {
             throw new Err("Your chunk type is not exactly 4-bytes long");
         }
 
-        const opertaionMode = document.querySelector('input[name="opMode"]:checked').value;
-
         if (opertaionMode === 'encode') {
             const message = messageField.value;
-            if (util.isEmpty(message)) {
-                throw new Err("Please enter your message");
+            const secretFile = secretFileInput.files[0];
+            if (util.isEmpty(message) && !secretFile) {
+                throw new Err("Please enter your message or choose a file");
             }
 
             // Start encode
-            const publicMaterial = pngme.encode(fileBytes, passphrase, message, chunkType);
-            const nonce = publicMaterial.nonce;
+            const options = new pngme.EncodeOptions();
+            options.mode = embedMode;
+            options.cipher = cipherField.value;
+            options.kdf = kdfField.value;
+            options.compression = compressionField.value;
+            options.bind_chunk_type = bindField.value.includes('chunk-type');
+            options.bind_image = bindField.value.includes('image');
+            // The MIME type is guessed from the file name
+            const publicMaterial = secretFile
+                ? pngme.encode_file(fileBytes, passphrase, await readUploadFile(secretFileInput),
+                    secretFile.name, "", chunkType, options)
+                : pngme.encode(fileBytes, passphrase, message, chunkType, options);
             const encodedBytes = publicMaterial.encoded_bytes;
 
             resetDecodeBoxContent();
 
             // Display results
             displayImage(encodedBytes, filename);
-            nonceValueElement.textContent = nonce;
             encodeResultBoxElement.hidden = false;
 
         } else if (opertaionMode === 'decode') {
-            const nonce = nonceInput.value;
-            if (util.isEmpty(nonce)) {
-                throw new Err("Please enter your message");
-            }
+            // Images encoded by the current version store their Nonce
+            const nonce = nonceInput.value.trim();
             // Start decode
             console.log("hihihi")
 
-            const plaintext = pngme.decode(fileBytes, passphrase, nonce, chunkType);
-            if (!plaintext) {
+            const decodedFile = pngme.decode_file(fileBytes, passphrase, nonce, chunkType);
+            if (!decodedFile) {
                 throw new Err("Something wrong in decoding");
             }
 
@@ -235,7 +261,16 @@ pngmeForm.addEventListener('submit', async (event) => {
 
             // Display results
             decodeResultBoxElement.hidden = false;
-            secretMessageBoxElement.textContent = plaintext;
+            if (util.isEmpty(decodedFile.name)) {
+                secretMessageBoxElement.textContent = new TextDecoder().decode(decodedFile.data);
+            } else {
+                const blob = new Blob([decodedFile.data], { type: decodedFile.mime_type });
+                secretMessageBoxElement.textContent =
+                    `${decodedFile.name} (${decodedFile.mime_type}, ${blob.size} bytes)`;
+                fileDownloadLinkElement.href = URL.createObjectURL(blob);
+                fileDownloadLinkElement.download = decodedFile.name;
+                fileDownloadLinkElement.hidden = false;
+            }
         }
 
     } catch (error) {